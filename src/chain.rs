@@ -0,0 +1,416 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+use log::info;
+use serde::Deserialize;
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo};
+use vulkano::descriptor_set::allocator::{StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo};
+use vulkano::descriptor_set::{DescriptorSet, WriteDescriptorSet};
+use vulkano::device::{Device, DeviceCreateInfo, DeviceFeatures, DeviceExtensions, QueueCreateInfo};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::compute::ComputePipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo};
+use vulkano::shader::SpecializationConstant;
+use vulkano::sync::{self, GpuFuture};
+use vulkano::VulkanLibrary;
+
+use crate::device::{
+    check_device_support, check_queue_priority, check_workgroup_size, enable_portability_subset,
+    find_compute_queue_family, pick_physical_device,
+};
+use crate::fence::{block_on, wait_with_timeout};
+use crate::format::select_storage_format;
+use crate::input::{load_input_image, upload_input_image};
+use crate::instance::{create_instance, setup_debug_messenger};
+use crate::out_of_memory::MemoryContext;
+use crate::{
+    div_ceil, Counters, Params, PixelFormat, PushConstants, RenderConfig, RenderOutput, ShaderKind, DEFAULT_PALETTE,
+};
+
+/// A `--pipeline` file, the passes run in order and each one reads what the one before wrote.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainFile {
+    passes: Vec<PassEntry>,
+}
+
+/// One pass of a `--pipeline` file as written, every push constant it leaves out is taken from
+/// the command line.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PassEntry {
+    shader: String,
+    center: Option<[f32; 2]>,
+    zoom: Option<f32>,
+    iterations: Option<u32>,
+    time: Option<f32>,
+    scale: Option<f32>,
+    radius: Option<u32>,
+    seed: Option<u32>,
+    strength: Option<f32>,
+}
+
+/// A pass of a chain, the shader it dispatches and the push constant values it overrides.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainPass {
+    pub shader: ShaderKind,
+    pub center: Option<[f32; 2]>,
+    pub zoom: Option<f32>,
+    pub iterations: Option<u32>,
+    pub time: Option<f32>,
+    pub scale: Option<f32>,
+    pub radius: Option<u32>,
+    pub seed: Option<u32>,
+    pub strength: Option<f32>,
+}
+
+impl ChainPass {
+    /// Push constants of the pass, `base` with the values the pass sets replaced.
+    fn push_constants(&self, base: PushConstants, pass_index: u32) -> PushConstants
+    {
+        PushConstants {
+            center: self.center.unwrap_or(base.center),
+            zoom: self.zoom.unwrap_or(base.zoom),
+            iterations: self.iterations.unwrap_or(base.iterations),
+            time: self.time.unwrap_or(base.time),
+            scale: self.scale.unwrap_or(base.scale),
+            radius: self.radius.unwrap_or(base.radius),
+            seed: self.seed.unwrap_or(base.seed),
+            strength: self.strength.unwrap_or(base.strength),
+            pass_index,
+            ..base
+        }
+    }
+}
+
+/// Parses the JSON chain of passes in `text`, checking that every shader it names exists and can
+/// be chained.
+///
+/// ```json
+/// { "passes": [{ "shader": "noise", "seed": 7 }, { "shader": "blur", "radius": 3 }] }
+/// ```
+pub fn parse_chain(text: &str) -> Result<Vec<ChainPass>>
+{
+    let file: ChainFile = serde_json::from_str(text).context("failed to parse the passes")?;
+    ensure!(!file.passes.is_empty(), "the pipeline has no passes");
+
+    let mut passes = Vec::with_capacity(file.passes.len());
+    for (index, entry) in file.passes.into_iter().enumerate() {
+        let Some(shader) = ShaderKind::from_str(&entry.shader, true).ok() else {
+            let names: Vec<String> = ShaderKind::value_variants()
+                .iter()
+                .filter_map(|shader| shader.to_possible_value())
+                .map(|value| value.get_name().to_owned())
+                .collect();
+            bail!("pass {index} names the unknown shader {:?}, expected one of {}", entry.shader, names.join(", "));
+        };
+        ensure!(
+            !shader.is_volume() && shader != ShaderKind::Resize,
+            "pass {index} runs the {shader:?} shader, which doesn't write a flat image of the size of the other passes",
+        );
+
+        passes.push(ChainPass {
+            shader,
+            center: entry.center,
+            zoom: entry.zoom,
+            iterations: entry.iterations,
+            time: entry.time,
+            scale: entry.scale,
+            radius: entry.radius,
+            seed: entry.seed,
+            strength: entry.strength,
+        });
+    }
+
+    Ok(passes)
+}
+
+/// Reads and parses the `--pipeline` file at `path`, see [`parse_chain`].
+pub fn load_chain(path: &Path) -> Result<Vec<ChainPass>>
+{
+    let text = fs::read_to_string(path).with_context(|| format!("failed to read the pipeline {}", path.display()))?;
+
+    parse_chain(&text).with_context(|| format!("invalid pipeline {}", path.display()))
+}
+
+/// Renders `passes` one after the other in a single command buffer and returns what the last one
+/// wrote.
+///
+/// The passes alternate between writing two images and read the other one at binding 1, like the
+/// passes of an iterative shader, so each one reads the output of the pass before it. The first
+/// pass reads `config.input` there if it is given. Push constants a pass doesn't set come from
+/// `config`, and `pass_index` is the index of the pass in the chain.
+pub fn render_chain(config: &RenderConfig, passes: &[ChainPass]) -> Result<RenderOutput>
+{
+    ensure!(!passes.is_empty(), "the pipeline has no passes");
+    ensure!(
+        config.pixel_format == PixelFormat::Rgba8 && !config.srgb,
+        "the passes read each other as rgba8, a pipeline can only render linear 8-bit output"
+    );
+    ensure!(!config.workgroup_size.contains(&0), "workgroup size must be non-zero");
+    check_queue_priority(config.queue_priority)?;
+
+    let input = config.input.as_deref().map(load_input_image).transpose()?;
+    let (width, height) = match &input {
+        Some(input) => (input.width, input.height),
+        None => (config.width, config.height),
+    };
+    ensure!(width > 0 && height > 0, "image dimensions must be non-zero, got {width}x{height}");
+    ensure!(
+        input.is_some() || !passes[0].shader.needs_input(),
+        "the first pass runs the {:?} shader, which needs an --input image",
+        passes[0].shader,
+    );
+
+    let library = VulkanLibrary::new().context("no local Vulkan library/DLL")?;
+    let instance = create_instance(library, config.validation, false)?;
+    let _debug_messenger = setup_debug_messenger(&instance)?;
+
+    let physical_device = pick_physical_device(
+        &instance,
+        config.device_index,
+        config.device_uuid,
+        config.allow_software,
+    )?;
+    check_workgroup_size(&physical_device, config.workgroup_size)?;
+    let (queue_family_index, _) = find_compute_queue_family(&physical_device)?;
+
+    let enabled_features = passes
+        .iter()
+        .fold(DeviceFeatures::empty(), |features, pass| features.union(&pass.shader.required_features()));
+    let mut enabled_extensions = passes
+        .iter()
+        .fold(DeviceExtensions::empty(), |extensions, pass| extensions.union(&pass.shader.required_extensions()));
+    check_device_support(&physical_device, &enabled_features, &enabled_extensions)?;
+    enable_portability_subset(&physical_device, &mut enabled_extensions);
+
+    info!("Requesting queue priority {}", config.queue_priority);
+
+    let (device, mut queues) = Device::new(
+        physical_device,
+        DeviceCreateInfo {
+            queue_create_infos: vec![QueueCreateInfo {
+                queue_family_index,
+                queues: vec![config.queue_priority],
+                ..Default::default()
+            }],
+            enabled_features,
+            enabled_extensions,
+            ..Default::default()
+        },
+    )
+    .context("failed to create device")?;
+    let queue = queues.next().context("device returned no queues")?;
+
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
+        device.clone(),
+        StandardDescriptorSetAllocatorCreateInfo {
+            set_count: passes.len(),
+            ..Default::default()
+        },
+    ));
+    let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(device.clone(), Default::default()));
+
+    // The input and chained shaders declare their input as rgba8, so a fallback format can't be
+    // read by the next pass
+    let usage = ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC;
+    let storage_format = select_storage_format(device.physical_device(), PixelFormat::Rgba8, ImageType::Dim2d, usage)?;
+    ensure!(
+        storage_format == Format::R8G8B8A8_UNORM,
+        "a pipeline needs R8G8B8A8_UNORM storage images, the device only offers {storage_format:?}"
+    );
+
+    let mut views = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let image = Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: storage_format,
+                extent: [width, height, 1],
+                usage,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+        )
+        .memory_context("failed to create a pass image")?;
+        views.push(ImageView::new_default(image).context("failed to create a pass ImageView")?);
+    }
+
+    let input_view = match &input {
+        Some(input) => {
+            let input_image =
+                upload_input_image(&memory_allocator, &command_buffer_allocator, &queue, input, config.timeout)?;
+            Some(ImageView::new_default(input_image).context("failed to create an input ImageView")?)
+        }
+        None => None,
+    };
+
+    let params_buffer = Buffer::from_data(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        Params { palette: DEFAULT_PALETTE },
+    )
+    .memory_context("failed to create a uniform buffer")?;
+    // Nothing reads the counters back, the shaders just need somewhere to count
+    let counters_buf = Buffer::from_data(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+        Counters::default(),
+    )
+    .memory_context("failed to create a storage buffer")?;
+
+    let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+        command_buffer_allocator.clone(),
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .context("failed to create an AutoCommandBufferBuilder")?;
+
+    let base = PushConstants {
+        size: [width, height],
+        ..config.push_constants(config.time)
+    };
+    for (pass_index, pass) in passes.iter().enumerate() {
+        let pipeline = create_pass_pipeline(&device, pass.shader, config.workgroup_size)
+            .with_context(|| format!("failed to create the pipeline of pass {pass_index}"))?;
+        let layout = pipeline.layout().set_layouts().first().context("failed to return a layout")?;
+
+        // Pass n writes image n % 2 and reads the other one, which the pass before it wrote
+        let target = &views[pass_index % 2];
+        let source = match pass_index {
+            0 => input_view.as_ref(),
+            _ => Some(&views[(pass_index + 1) % 2]),
+        };
+
+        let mut descriptor_writes = vec![WriteDescriptorSet::image_view(0, target.clone())];
+        if layout.bindings().contains_key(&1) {
+            // Only life gets here without a source, and its first pass seeds the board instead
+            // of reading it
+            let source = source.unwrap_or(&views[1]);
+            descriptor_writes.push(WriteDescriptorSet::image_view(1, source.clone()));
+        }
+        if layout.bindings().contains_key(&2) {
+            descriptor_writes.push(WriteDescriptorSet::buffer(2, params_buffer.clone()));
+        }
+        if layout.bindings().contains_key(&3) {
+            descriptor_writes.push(WriteDescriptorSet::buffer(3, counters_buf.clone()));
+        }
+        let set = DescriptorSet::new(descriptor_set_allocator.clone(), layout.clone(), descriptor_writes, [])
+            .with_context(|| format!("failed to create the descriptor set of pass {pass_index}"))?;
+
+        // The builder sees each pass read what the one before wrote and inserts the barrier
+        command_buffer_builder
+            .bind_pipeline_compute(pipeline.clone())
+            .context("failed to bind a compute pipeline to a command buffer")?
+            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline.layout().clone(), 0, set)
+            .context("failed to bind descriptor sets to a command buffer")?
+            .push_constants(pipeline.layout().clone(), 0, pass.push_constants(base, pass_index as u32))
+            .context("failed to push constants to a command buffer")?;
+
+        unsafe {
+            command_buffer_builder
+                .dispatch([div_ceil(width, config.workgroup_size[0]), div_ceil(height, config.workgroup_size[1]), 1])
+                .with_context(|| format!("failed to dispatch pass {pass_index}"))?;
+        }
+        info!("Pass {pass_index}: {:?}", pass.shader);
+    }
+
+    let buf = Buffer::new_slice::<u8>(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        width as u64 * height as u64 * PixelFormat::Rgba8.bytes_per_pixel() as u64,
+    )
+    .memory_context("failed to create the readback buffer")?;
+
+    let result = views[(passes.len() - 1) % 2].image().clone();
+    command_buffer_builder
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(result, buf.clone()))
+        .context("failed to copy the last pass to the buffer")?;
+
+    let command_buffer = command_buffer_builder
+        .build()
+        .context("failed to build a PrimaryAutoCommandBuffer")?;
+    let future = sync::now(device.clone())
+        .then_execute(queue.clone(), command_buffer)
+        .context("failed to execute the passes")?
+        .then_signal_fence_and_flush()
+        .memory_context("failed to signal a fence after the passes")?;
+    block_on(wait_with_timeout(future, config.timeout, "pipeline"))?;
+
+    let pixels = buf.read().context("failed to read the buffer")?.to_vec();
+    info!("Rendered {} passes into a {width}x{height} image", passes.len());
+
+    PixelFormat::Rgba8.to_output(width, height, pixels)
+}
+
+/// Creates the pipeline of one pass with the workgroup size of the chain, and without the sRGB
+/// conversions as every pass writes and reads linear colours.
+fn create_pass_pipeline(
+    device: &Arc<Device>,
+    shader: ShaderKind,
+    workgroup_size: [u32; 2],
+) -> Result<Arc<ComputePipeline>>
+{
+    let specialization_info = [
+        (0, SpecializationConstant::U32(workgroup_size[0])),
+        (1, SpecializationConstant::U32(workgroup_size[1])),
+        (2, SpecializationConstant::Bool(false)),
+        (3, SpecializationConstant::Bool(false)),
+    ]
+    .into_iter()
+    .collect();
+    let stage = PipelineShaderStageCreateInfo::new(
+        shader
+            .load(device.clone(), false)
+            .context("failed to load a compute shader")?
+            .specialize(specialization_info)
+            .context("failed to specialize the compute shader")?
+            .entry_point("main")
+            .context("compute shader has no \"main\" entry point")?,
+    );
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(device.clone())
+            .context("failed to create PipelineLayoutCreateInfo")?,
+    )
+    .context("failed to create a new PipelineLayout")?;
+
+    ComputePipeline::new(device.clone(), None, ComputePipelineCreateInfo::stage_layout(stage, layout))
+        .context("failed to create a new ComputePipeline")
+}
@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use anyhow::{ensure, Context, Ok, Result};
+use image::DynamicImage;
+
+/// Per-channel error between two images, with channels normalised to 0.0..1.0.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ImageDiff {
+    /// Mean absolute error of the R, G, B and A channels
+    pub mean_error: [f32; 4],
+    /// Largest absolute error of the R, G, B and A channels
+    pub max_error: [f32; 4],
+}
+
+impl ImageDiff {
+    /// The largest error of any channel of any pixel.
+    pub fn max(&self) -> f32
+    {
+        self.max_error.into_iter().fold(0.0, f32::max)
+    }
+}
+
+/// Compares `image` with `reference` channel by channel, both converted to 32-bit float RGBA.
+pub fn compare_images(image: &DynamicImage, reference: &DynamicImage) -> Result<ImageDiff>
+{
+    ensure!(
+        image.width() == reference.width() && image.height() == reference.height(),
+        "the rendered image is {}x{}, but the reference is {}x{}",
+        image.width(),
+        image.height(),
+        reference.width(),
+        reference.height(),
+    );
+
+    let image = image.to_rgba32f();
+    let reference = reference.to_rgba32f();
+
+    let mut error_sum = [0.0f64; 4];
+    let mut max_error = [0.0f32; 4];
+    for (pixel, reference_pixel) in image.pixels().zip(reference.pixels()) {
+        for channel in 0..4 {
+            let error = (pixel[channel] - reference_pixel[channel]).abs();
+            error_sum[channel] += error as f64;
+            max_error[channel] = max_error[channel].max(error);
+        }
+    }
+
+    let pixel_count = (image.width() as f64 * image.height() as f64).max(1.0);
+
+    Ok(ImageDiff {
+        mean_error: error_sum.map(|sum| (sum / pixel_count) as f32),
+        max_error,
+    })
+}
+
+/// Loads the reference image at `path` and compares `image` with it.
+pub fn compare_with_reference(image: &DynamicImage, path: &Path) -> Result<ImageDiff>
+{
+    let reference = image::open(path)
+        .with_context(|| format!("failed to open reference image {}", path.display()))?;
+
+    compare_images(image, &reference)
+}
@@ -0,0 +1,626 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context, Ok, Result};
+use ash::vk;
+use log::{debug, info, warn};
+use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType, SubgroupFeatures};
+use vulkano::device::{Device, DeviceExtensions, DeviceFeatures, DeviceOwned, QueueFlags};
+use vulkano::image::sys::RawImage;
+use vulkano::image::{Image, ImageCreateInfo, ImageMemory};
+use vulkano::instance::Instance;
+use vulkano::memory::allocator::MemoryTypeFilter;
+use vulkano::memory::MemoryPropertyFlags;
+use vulkano::shader::ShaderStages;
+use vulkano::{DeviceSize, Version, VulkanObject};
+
+use crate::out_of_memory::MemoryContext;
+
+fn has_compute_queue(physical_device: &PhysicalDevice) -> bool
+{
+    physical_device
+        .queue_family_properties()
+        .iter()
+        .any(|queue_family_properties| queue_family_properties.queue_flags.contains(QueueFlags::COMPUTE))
+}
+
+/// Prints every physical device with its compute queue families to stdout.
+pub fn print_devices(instance: &Arc<Instance>) -> Result<()>
+{
+    let physical_devices = instance
+        .enumerate_physical_devices()
+        .context("could not enumerate physical devices")?;
+
+    for (index, physical_device) in physical_devices.enumerate() {
+        let properties = physical_device.properties();
+        let driver = properties
+            .driver_info
+            .clone()
+            .unwrap_or_else(|| Version::from(properties.driver_version).to_string());
+
+        println!("[{index}] {} ({:?}, driver {driver})", properties.device_name, properties.device_type);
+        match properties.device_uuid {
+            Some(uuid) => println!("    uuid {}", format_uuid(uuid)),
+            None => println!("    uuid unavailable, it needs Vulkan 1.1"),
+        }
+
+        let compute_families = physical_device
+            .queue_family_properties()
+            .iter()
+            .enumerate()
+            .filter(|(_, queue_family_properties)| queue_family_properties.queue_flags.contains(QueueFlags::COMPUTE));
+
+        for (queue_family_index, queue_family_properties) in compute_families {
+            println!(
+                "    queue family {queue_family_index}: {} queues, {:?}",
+                queue_family_properties.queue_count, queue_family_properties.queue_flags,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the limits of `physical_device` that constrain compute dispatches as a table to stdout.
+pub fn print_compute_limits(physical_device: &PhysicalDevice)
+{
+    let properties = physical_device.properties();
+    let format_dimensions = |[x, y, z]: [u32; 3]| format!("{x} x {y} x {z}");
+
+    let limits = [
+        ("max_compute_work_group_count", format_dimensions(properties.max_compute_work_group_count)),
+        ("max_compute_work_group_size", format_dimensions(properties.max_compute_work_group_size)),
+        ("max_compute_work_group_invocations", properties.max_compute_work_group_invocations.to_string()),
+        ("max_compute_shared_memory_size", format!("{} bytes", properties.max_compute_shared_memory_size)),
+        ("max_storage_buffer_range", format!("{} bytes", properties.max_storage_buffer_range)),
+        ("max_push_constants_size", format!("{} bytes", properties.max_push_constants_size)),
+        ("max_image_dimension2_d", properties.max_image_dimension2_d.to_string()),
+    ];
+    let name_width = limits.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+    println!("Compute limits of {}:", properties.device_name);
+    for (name, value) in limits {
+        println!("    {name:<name_width$}  {value:>20}");
+    }
+}
+
+fn warn_if_software(physical_device: &PhysicalDevice)
+{
+    if physical_device.properties().device_type == PhysicalDeviceType::Cpu {
+        warn!(
+            "!!! {} is a software (CPU) implementation, rendering will be very slow !!!",
+            physical_device.properties().device_name,
+        );
+    }
+}
+
+fn is_allowed(physical_device: &PhysicalDevice, allow_software: bool) -> bool
+{
+    allow_software || physical_device.properties().device_type != PhysicalDeviceType::Cpu
+}
+
+fn device_score(physical_device: &PhysicalDevice) -> (u32, u32)
+{
+    let properties = physical_device.properties();
+    let type_score = match properties.device_type {
+        PhysicalDeviceType::DiscreteGpu => 3,
+        PhysicalDeviceType::IntegratedGpu => 2,
+        PhysicalDeviceType::VirtualGpu | PhysicalDeviceType::Cpu => 1,
+        _ => 0,
+    };
+
+    (type_score, properties.max_compute_work_group_invocations)
+}
+
+fn enumerate_devices(instance: &Arc<Instance>) -> Result<Vec<Arc<PhysicalDevice>>>
+{
+    let physical_devices: Vec<_> = instance
+        .enumerate_physical_devices()
+        .context("could not enumerate physical devices")?
+        .collect();
+
+    if physical_devices.is_empty() {
+        bail!(
+            "no Vulkan devices found. On a machine without a GPU, install a software implementation \
+             such as Mesa's llvmpipe (lavapipe) or SwiftShader and run with --allow-software"
+        );
+    }
+
+    Ok(physical_devices)
+}
+
+/// Returns the devices with a compute queue, best scoring first.
+///
+/// CPU devices such as llvmpipe or SwiftShader are only included when `allow_software` is set.
+pub fn rank_physical_devices(instance: &Arc<Instance>, allow_software: bool) -> Result<Vec<Arc<PhysicalDevice>>>
+{
+    let physical_devices = enumerate_devices(instance)?;
+
+    let only_software = physical_devices
+        .iter()
+        .all(|physical_device| !is_allowed(physical_device, allow_software));
+    ensure!(
+        !only_software,
+        "only software Vulkan implementations are available, pass --allow-software to use them"
+    );
+
+    let mut ranked: Vec<_> = physical_devices
+        .into_iter()
+        .filter(|physical_device| has_compute_queue(physical_device) && is_allowed(physical_device, allow_software))
+        .collect();
+    ranked.sort_by_key(|physical_device| Reverse(device_score(physical_device)));
+
+    Ok(ranked)
+}
+
+/// Formats a device UUID the way `--list-devices` prints it and `--device-uuid` accepts it.
+pub fn format_uuid(uuid: [u8; 16]) -> String
+{
+    let hex: String = uuid.iter().map(|byte| format!("{byte:02x}")).collect();
+
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Parses a device UUID of 32 hex digits, the hyphens between the groups are optional.
+pub fn parse_uuid(text: &str) -> Result<[u8; 16]>
+{
+    let hex: String = text.chars().filter(|&character| character != '-').collect();
+    ensure!(
+        hex.len() == 32 && hex.chars().all(|character| character.is_ascii_hexdigit()),
+        "'{text}' is not a device UUID, expected 32 hex digits such as the ones --list-devices prints"
+    );
+
+    let mut uuid = [0; 16];
+    for (index, byte) in uuid.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)?;
+    }
+
+    Ok(uuid)
+}
+
+/// Picks the device at `device_index` in enumeration order, the one whose UUID is `device_uuid`,
+/// or the best scoring one if both are `None`.
+///
+/// Indices can change between runs while UUIDs stay the same, so `device_uuid` pins a GPU reliably.
+/// CPU devices such as llvmpipe or SwiftShader are only considered when `allow_software` is set.
+pub fn pick_physical_device(
+    instance: &Arc<Instance>,
+    device_index: Option<usize>,
+    device_uuid: Option<[u8; 16]>,
+    allow_software: bool,
+) -> Result<Arc<PhysicalDevice>>
+{
+    let selected = if let Some(device_index) = device_index {
+        let physical_device = enumerate_devices(instance)?
+            .into_iter()
+            .nth(device_index)
+            .with_context(|| format!("no device with index {device_index}, see --list-devices"))?;
+        Some((physical_device, format!("index {device_index}")))
+    } else if let Some(device_uuid) = device_uuid {
+        let uuid = format_uuid(device_uuid);
+        let physical_device = enumerate_devices(instance)?
+            .into_iter()
+            .find(|physical_device| physical_device.properties().device_uuid == Some(device_uuid))
+            .with_context(|| format!("no device has the UUID {uuid}, see --list-devices"))?;
+        Some((physical_device, format!("UUID {uuid}")))
+    } else {
+        None
+    };
+
+    if let Some((physical_device, selector)) = selected {
+        ensure!(
+            has_compute_queue(&physical_device),
+            "the device with {selector} ({}) has no compute queue",
+            physical_device.properties().device_name,
+        );
+        ensure!(
+            is_allowed(&physical_device, allow_software),
+            "the device with {selector} ({}) is a software implementation, pass --allow-software to use it",
+            physical_device.properties().device_name,
+        );
+
+        info!("Using device: {} (selected by {selector})", physical_device.properties().device_name);
+        warn_if_software(&physical_device);
+
+        return Ok(physical_device);
+    }
+
+    let physical_device = rank_physical_devices(instance, allow_software)?
+        .into_iter()
+        .next()
+        .context("no devices with a compute queue available")?;
+
+    info!(
+        "Using device: {} (type: {:?})",
+        physical_device.properties().device_name,
+        physical_device.properties().device_type,
+    );
+    warn_if_software(&physical_device);
+
+    Ok(physical_device)
+}
+
+/// Checks that `physical_device` supports every feature and extension in `features` and `extensions`.
+///
+/// The error lists everything that is missing, so it is caught before `Device::new` fails with a
+/// less specific message.
+pub fn check_device_support(
+    physical_device: &PhysicalDevice,
+    features: &DeviceFeatures,
+    extensions: &DeviceExtensions,
+) -> Result<()>
+{
+    let missing_features = features.difference(physical_device.supported_features());
+    let missing_extensions = extensions.difference(physical_device.supported_extensions());
+
+    let mut missing = Vec::new();
+    if missing_features != DeviceFeatures::empty() {
+        missing.push(format!("features {missing_features:?}"));
+    }
+    if !missing_extensions.is_empty() {
+        missing.push(format!("extensions {missing_extensions:?}"));
+    }
+
+    ensure!(
+        missing.is_empty(),
+        "{} doesn't support the required {}",
+        physical_device.properties().device_name,
+        missing.join(" and "),
+    );
+
+    Ok(())
+}
+
+/// Adds `VK_KHR_portability_subset` to `extensions` when `physical_device` advertises it.
+///
+/// Portability drivers such as MoltenVK only implement a subset of Vulkan, and the spec requires
+/// the extension to be enabled whenever it's supported, so the application acknowledges that.
+pub fn enable_portability_subset(physical_device: &PhysicalDevice, extensions: &mut DeviceExtensions)
+{
+    if !physical_device.supported_extensions().khr_portability_subset {
+        return;
+    }
+
+    info!(
+        "{} is a portability driver (e.g. MoltenVK), enabling VK_KHR_portability_subset, some Vulkan features are unavailable",
+        physical_device.properties().device_name,
+    );
+    extensions.khr_portability_subset = true;
+}
+
+/// Bytes of a memory heap that the process uses and may use, as reported by `VK_EXT_memory_budget`.
+#[derive(Clone, Copy, Debug)]
+pub struct HeapBudget {
+    pub usage: DeviceSize,
+    pub budget: DeviceSize,
+}
+
+impl HeapBudget {
+    /// Bytes that can still be allocated before the budget is exceeded.
+    pub fn available(&self) -> DeviceSize
+    {
+        self.budget.saturating_sub(self.usage)
+    }
+}
+
+/// Reads the usage and budget of every memory heap of `physical_device`, or `None` when it doesn't
+/// support `VK_EXT_memory_budget` or the instance can't query extended memory properties.
+///
+/// Vulkano doesn't expose the budget, so this chains the extension's struct into a raw
+/// `vkGetPhysicalDeviceMemoryProperties2`. The values change as allocations come and go, so they
+/// are read again on every call.
+pub fn memory_budgets(physical_device: &PhysicalDevice) -> Option<Vec<HeapBudget>>
+{
+    let instance = physical_device.instance();
+    let core_properties2 = instance.api_version() >= Version::V1_1;
+    if !physical_device.supported_extensions().ext_memory_budget
+        || !(core_properties2 || instance.enabled_extensions().khr_get_physical_device_properties2)
+    {
+        return None;
+    }
+
+    let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+    let mut memory_properties = vk::PhysicalDeviceMemoryProperties2::default().push_next(&mut budget_properties);
+    let fns = instance.fns();
+    // SAFETY: the device supports the extension of the chained struct, and the function is either
+    // core or from an enabled instance extension
+    unsafe {
+        if core_properties2 {
+            (fns.v1_1.get_physical_device_memory_properties2)(physical_device.handle(), &mut memory_properties);
+        } else {
+            (fns.khr_get_physical_device_properties2.get_physical_device_memory_properties2_khr)(
+                physical_device.handle(),
+                &mut memory_properties,
+            );
+        }
+    }
+    let heap_count = memory_properties.memory_properties.memory_heap_count as usize;
+
+    Some(
+        (0..heap_count)
+            .map(|heap_index| HeapBudget {
+                usage: budget_properties.heap_usage[heap_index],
+                budget: budget_properties.heap_budget[heap_index],
+            })
+            .collect(),
+    )
+}
+
+/// Logs the size and flags of every memory heap of `physical_device` at `debug!` level, with what
+/// the process uses of it and its budget when `VK_EXT_memory_budget` reports them.
+pub fn log_memory_heaps(physical_device: &PhysicalDevice)
+{
+    let memory_properties = physical_device.memory_properties();
+    let budgets = memory_budgets(physical_device);
+    if budgets.is_none() {
+        debug!("{} doesn't report memory budgets, only heap sizes", physical_device.properties().device_name);
+    }
+
+    debug!("Memory heaps of {}:", physical_device.properties().device_name);
+    for (heap_index, memory_heap) in memory_properties.memory_heaps.iter().enumerate() {
+        let property_flags = memory_properties
+            .memory_types
+            .iter()
+            .filter(|memory_type| memory_type.heap_index as usize == heap_index)
+            .fold(MemoryPropertyFlags::empty(), |flags, memory_type| flags | memory_type.property_flags);
+
+        let budget = match budgets.as_ref().and_then(|budgets| budgets.get(heap_index)) {
+            Some(budget) => format!(
+                ", {:.1} MiB used of a {:.1} MiB budget",
+                budget.usage as f64 / (1024.0 * 1024.0),
+                budget.budget as f64 / (1024.0 * 1024.0),
+            ),
+            None => String::new(),
+        };
+        debug!(
+            "    heap {heap_index}: {:.1} MiB, {:?}, memory types {property_flags:?}{budget}",
+            memory_heap.size as f64 / (1024.0 * 1024.0),
+            memory_heap.flags,
+        );
+    }
+}
+
+/// Checks that an image created with `create_info` can be allocated from a memory type with
+/// every flag `memory_type_filter` requires.
+///
+/// Which memory types an image may live in depends on its format, tiling and usage, so this asks
+/// the driver with an image that is never bound to memory. Without the check an impossible
+/// combination only shows up as a confusing allocation failure.
+pub fn check_image_memory_type(
+    device: &Arc<Device>,
+    create_info: &ImageCreateInfo,
+    memory_type_filter: MemoryTypeFilter,
+) -> Result<()>
+{
+    let raw_image = RawImage::new(device.clone(), create_info.clone())
+        .memory_context("failed to create an image to query its memory requirements")?;
+    let memory_type_bits = raw_image
+        .memory_requirements()
+        .first()
+        .context("the image reports no memory requirements")?
+        .memory_type_bits;
+
+    let memory_types = &device.physical_device().memory_properties().memory_types;
+    let allowed: Vec<_> = (0..memory_types.len() as u32)
+        .filter(|memory_type_index| memory_type_bits & (1 << memory_type_index) != 0)
+        .collect();
+    let required_flags = memory_type_filter.required_flags;
+    ensure!(
+        allowed
+            .iter()
+            .any(|&memory_type_index| memory_types[memory_type_index as usize].property_flags.contains(required_flags)),
+        "{} only allows {:?} storage images in memory types {allowed:?} ({:?}), none of which is {required_flags:?}",
+        device.physical_device().properties().device_name,
+        create_info.format,
+        allowed
+            .iter()
+            .map(|&memory_type_index| memory_types[memory_type_index as usize].property_flags)
+            .collect::<Vec<_>>(),
+    );
+
+    Ok(())
+}
+
+/// Logs which memory type `image` was allocated from, and whether it is device-local and
+/// host-visible.
+pub fn log_image_memory(image: &Image)
+{
+    let ImageMemory::Normal(allocations) = image.memory() else {
+        return;
+    };
+
+    let memory_types = &image.device().physical_device().memory_properties().memory_types;
+    for allocation in allocations {
+        let memory_type_index = allocation.device_memory().memory_type_index();
+        let property_flags = memory_types[memory_type_index as usize].property_flags;
+        let placement = match (
+            property_flags.intersects(MemoryPropertyFlags::DEVICE_LOCAL),
+            property_flags.intersects(MemoryPropertyFlags::HOST_VISIBLE),
+        ) {
+            (true, true) => "device-local and host-visible",
+            (true, false) => "device-local",
+            (false, true) => "host-visible",
+            (false, false) => "neither device-local nor host-visible",
+        };
+
+        info!("Storage image is in {placement} memory type {memory_type_index} ({property_flags:?})");
+    }
+}
+
+/// Checks that a `size` byte allocation fits in a memory heap with a memory type of
+/// `property_flags`, and warns when it takes up most of it.
+///
+/// With `VK_EXT_memory_budget` the allocation is checked against what is left of the budget of
+/// the heap with the most of it left, as other applications and the driver already use part of
+/// every heap. Without it only the total heap sizes are known, which catches sizes that can never
+/// fit, and the warning starts at 3/4 of the largest heap to leave room for what others use.
+pub fn check_heap_size(
+    physical_device: &PhysicalDevice,
+    size: DeviceSize,
+    property_flags: MemoryPropertyFlags,
+) -> Result<()>
+{
+    let memory_properties = physical_device.memory_properties();
+    let heap_indices: Vec<usize> = memory_properties
+        .memory_types
+        .iter()
+        .filter(|memory_type| memory_type.property_flags.contains(property_flags))
+        .map(|memory_type| memory_type.heap_index as usize)
+        .collect();
+    let largest_heap = heap_indices
+        .iter()
+        .map(|&heap_index| memory_properties.memory_heaps[heap_index].size)
+        .max()
+        .unwrap_or(0);
+
+    let device_name = &physical_device.properties().device_name;
+    ensure!(
+        size <= largest_heap,
+        "{size} bytes don't fit in the largest {property_flags:?} memory heap of {largest_heap} bytes on {device_name}, \
+         try a smaller image or pixel format"
+    );
+
+    if let Some(budgets) = memory_budgets(physical_device) {
+        let available = heap_indices
+            .iter()
+            .filter_map(|&heap_index| budgets.get(heap_index))
+            .map(HeapBudget::available)
+            .max()
+            .unwrap_or(0);
+        ensure!(
+            size <= available,
+            "{size} bytes exceed the {available} bytes left in the budget of the {property_flags:?} memory heaps on \
+             {device_name}, try a smaller image or pixel format, or close other applications using the GPU"
+        );
+        if size > available / 4 * 3 {
+            warn!(
+                "{size} bytes use {:.0}% of the budget left in the {property_flags:?} memory heaps on {device_name}, \
+                 allocating may fail",
+                size as f64 / available as f64 * 100.0,
+            );
+        }
+    } else if size > largest_heap / 4 * 3 {
+        warn!(
+            "{size} bytes use {:.0}% of the largest {property_flags:?} memory heap on {device_name}, allocating may fail",
+            size as f64 / largest_heap as f64 * 100.0,
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that `priority` is a queue priority Vulkan accepts, which ranges from 0.0 to 1.0.
+pub fn check_queue_priority(priority: f32) -> Result<()>
+{
+    ensure!((0.0..=1.0).contains(&priority), "queue priority must be within 0.0..=1.0, got {priority}");
+
+    Ok(())
+}
+
+/// Checks that a `workgroup_size` workgroup fits in the compute limits of `physical_device`.
+pub fn check_workgroup_size(physical_device: &PhysicalDevice, workgroup_size: [u32; 2]) -> Result<()>
+{
+    let [size_x, size_y] = workgroup_size;
+    let properties = physical_device.properties();
+    let [max_size_x, max_size_y, _] = properties.max_compute_work_group_size;
+    let max_invocations = properties.max_compute_work_group_invocations;
+    ensure!(
+        size_x <= max_size_x && size_y <= max_size_y,
+        "workgroup size {size_x}x{size_y} exceeds the device's max_compute_work_group_size of {max_size_x}x{max_size_y}"
+    );
+    ensure!(
+        size_x.checked_mul(size_y).is_some_and(|invocations| invocations <= max_invocations),
+        "workgroup size {size_x}x{size_y} exceeds the device's max_compute_work_group_invocations of {max_invocations}"
+    );
+
+    Ok(())
+}
+
+/// Logs the subgroup size of `physical_device`, and which subgroup operations compute shaders can use.
+pub fn log_subgroup_properties(physical_device: &PhysicalDevice)
+{
+    let properties = physical_device.properties();
+    let (Some(subgroup_size), Some(operations), Some(stages)) = (
+        properties.subgroup_size,
+        properties.subgroup_supported_operations,
+        properties.subgroup_supported_stages,
+    ) else {
+        info!("{} doesn't report subgroup properties, they need Vulkan 1.1", properties.device_name);
+        return;
+    };
+
+    info!("Subgroup size is {subgroup_size}, supported operations are {operations:?} in stages {stages:?}");
+}
+
+/// Checks that compute shaders on `physical_device` can use subgroup arithmetic operations.
+pub fn check_subgroup_support(physical_device: &PhysicalDevice) -> Result<()>
+{
+    let properties = physical_device.properties();
+    let device_name = &properties.device_name;
+    let operations = properties.subgroup_supported_operations.unwrap_or_default();
+    let stages = properties.subgroup_supported_stages.unwrap_or_default();
+    ensure!(
+        operations.contains(SubgroupFeatures::ARITHMETIC),
+        "{device_name} doesn't support subgroup arithmetic operations, its supported operations are {operations:?}"
+    );
+    ensure!(
+        stages.contains(ShaderStages::COMPUTE),
+        "{device_name} doesn't support subgroup operations in compute shaders, only in {stages:?}"
+    );
+
+    Ok(())
+}
+
+/// Picks the family for the compute work out of the `queue_flags` of every queue family of a device.
+///
+/// Prefers a dedicated (async) compute family without `GRAPHICS` and falls back to a combined
+/// graphics and compute family. A family without `COMPUTE` is never picked, even if it has
+/// `GRAPHICS`. Returns the family index and whether it is dedicated.
+pub fn select_compute_queue_family(queue_flags: &[QueueFlags]) -> Option<(u32, bool)>
+{
+    let dedicated = queue_flags
+        .iter()
+        .position(|flags| flags.contains(QueueFlags::COMPUTE) && !flags.intersects(QueueFlags::GRAPHICS));
+    if let Some(queue_family_index) = dedicated {
+        return Some((queue_family_index as u32, true));
+    }
+
+    queue_flags
+        .iter()
+        .position(|flags| flags.contains(QueueFlags::COMPUTE))
+        .map(|queue_family_index| (queue_family_index as u32, false))
+}
+
+/// Finds a queue family for the compute work, see [`select_compute_queue_family`].
+///
+/// Fails when no family supports `COMPUTE`, rather than leaving the dispatch to fail on a
+/// graphics-only queue when it's submitted.
+pub fn find_compute_queue_family(physical_device: &PhysicalDevice) -> Result<(u32, bool)>
+{
+    let queue_flags: Vec<_> = physical_device
+        .queue_family_properties()
+        .iter()
+        .map(|queue_family_properties| queue_family_properties.queue_flags)
+        .collect();
+
+    select_compute_queue_family(&queue_flags).with_context(|| {
+        format!(
+            "{} has no queue family that supports compute, its families support {queue_flags:?}",
+            physical_device.properties().device_name,
+        )
+    })
+}
+
+/// Returns a queue family that only supports transfers, so copies can run on it next to the
+/// compute work, or `None` when the device has none.
+pub fn find_transfer_queue_family(physical_device: &PhysicalDevice) -> Option<u32>
+{
+    physical_device
+        .queue_family_properties()
+        .iter()
+        .position(|queue_family_properties| {
+            queue_family_properties.queue_flags.contains(QueueFlags::TRANSFER)
+                && !queue_family_properties.queue_flags.intersects(QueueFlags::COMPUTE | QueueFlags::GRAPHICS)
+        })
+        .map(|queue_family_index| queue_family_index as u32)
+}
@@ -0,0 +1,214 @@
+use std::future::Future;
+use std::mem;
+use std::pin::{pin, Pin};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SendError, Sender};
+use std::sync::{Arc, OnceLock};
+use std::task::{self, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Ok, Result};
+use vulkano::sync::fence::Fence;
+use vulkano::sync::future::FenceSignalFuture;
+use vulkano::sync::GpuFuture;
+
+use crate::out_of_memory::MemoryContext;
+
+/// How often the fence is polled while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Completes once `duration` has passed.
+///
+/// The shared [`timer`] thread wakes the task, so this works on any executor without depending
+/// on its timer, and without a thread per delay.
+struct Delay {
+    duration: Duration,
+    started: bool,
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<()>
+    {
+        if self.started {
+            return Poll::Ready(());
+        }
+
+        self.started = true;
+        if let Err(SendError((_, waker))) = timer().send((Instant::now() + self.duration, cx.waker().clone())) {
+            waker.wake();
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Deadline of a pending [`Delay`] and the waker of its task.
+type TimerEntry = (Instant, Waker);
+
+/// Sender to the thread that wakes every [`Delay`] once its deadline has passed, started on the
+/// first delay.
+fn timer() -> &'static Sender<TimerEntry>
+{
+    static TIMER: OnceLock<Sender<TimerEntry>> = OnceLock::new();
+
+    TIMER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("fence timer".to_owned())
+            .spawn(move || run_timer(receiver))
+            .expect("failed to spawn the fence timer thread");
+        sender
+    })
+}
+
+fn run_timer(receiver: Receiver<TimerEntry>)
+{
+    let mut pending: Vec<TimerEntry> = Vec::new();
+
+    loop {
+        let now = Instant::now();
+        pending.retain(|(deadline, waker)| {
+            let expired = *deadline <= now;
+            if expired {
+                waker.wake_by_ref();
+            }
+            !expired
+        });
+
+        let received = match pending.iter().map(|(deadline, _)| *deadline).min() {
+            Some(deadline) => receiver.recv_timeout(deadline - now),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Err(RecvTimeoutError::Disconnected) => return,
+            received => pending.extend(received.ok()),
+        }
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>)
+    {
+        self.0.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current thread.
+pub fn block_on<F: Future>(future: F) -> F::Output
+{
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = task::Context::from_waker(&waker);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Waits until the GPU signals `future`, or fails once `timeout` has passed.
+///
+/// The fence is polled every `POLL_INTERVAL` without blocking the thread, so this can be awaited
+/// on an async runtime. `FenceSignalFuture::wait` with a timeout would also release the submitted
+/// resources while the GPU may still be using them. On expiry the future is leaked on purpose:
+/// dropping it would block forever, and freeing what it holds while the GPU may still access it
+/// is unsound.
+pub async fn wait_with_timeout<F>(future: FenceSignalFuture<F>, timeout: Duration, operation: &str) -> Result<()>
+where
+    F: GpuFuture,
+{
+    let start = Instant::now();
+
+    while !future.is_signaled().memory_context(format!("failed to query the fence of the {operation}"))? {
+        if start.elapsed() >= timeout {
+            mem::forget(future);
+            return Err(timed_out(operation, timeout));
+        }
+
+        Delay { duration: POLL_INTERVAL, started: false }.await;
+    }
+
+    // Returns immediately now that the fence is signaled, and cleans up the submission
+    future.wait(None).memory_context(format!("failed to wait for the {operation}"))?;
+
+    Ok(())
+}
+
+/// Fence future of a tile's submission, whichever queues it went through.
+pub(crate) type SubmittedFuture = FenceSignalFuture<Box<dyn GpuFuture + Send + Sync>>;
+
+/// Fence and submitted work of a [`Renderer::submit_and_get_fence`](crate::Renderer::submit_and_get_fence)
+/// that hasn't been waited for yet.
+///
+/// The handle owns the submission, which keeps its command buffers and every resource they use
+/// alive until the fence signals. Dropping it before then blocks until the GPU is done, like
+/// dropping any vulkano fence future does. Only [`wait`](Self::wait) releases the resources
+/// for the host again, so call it before reading any of them, even once
+/// [`is_ready`](Self::is_ready) returned true.
+pub struct FenceHandle {
+    future: SubmittedFuture,
+}
+
+impl FenceHandle {
+    pub(crate) fn new(future: SubmittedFuture) -> Self
+    {
+        Self { future }
+    }
+
+    /// Whether the GPU has finished the submission, without blocking.
+    ///
+    /// A fence that can't be queried, e.g. because the device was lost, counts as ready so that
+    /// the following [`wait`](Self::wait) reports the error.
+    pub fn is_ready(&self) -> bool
+    {
+        self.future.is_signaled().unwrap_or(true)
+    }
+
+    /// Blocks until the GPU has finished the submission, or fails once `timeout` has passed, and
+    /// then releases the resources it used.
+    ///
+    /// Returns immediately when [`is_ready`](Self::is_ready) already returned true. On expiry the
+    /// submission is leaked like in [`wait_with_timeout`].
+    pub fn wait(self, timeout: Duration) -> Result<()>
+    {
+        block_on(wait_with_timeout(self.future, timeout, "submission"))
+    }
+}
+
+/// Like [`wait_with_timeout`], for work that was submitted without a future and signals `fence`
+/// once it completes.
+///
+/// `in_use` holds the command buffers and anything else the work uses, and is handed back with
+/// the fence so that the caller can reuse them. On expiry both are leaked for the same reason the
+/// future is.
+pub async fn wait_for_fence<T>(fence: Fence, in_use: T, timeout: Duration, operation: &str) -> Result<(Fence, T)>
+{
+    let start = Instant::now();
+
+    while !fence.is_signaled().memory_context(format!("failed to query the fence of the {operation}"))? {
+        if start.elapsed() >= timeout {
+            mem::forget(fence);
+            mem::forget(in_use);
+            return Err(timed_out(operation, timeout));
+        }
+
+        Delay { duration: POLL_INTERVAL, started: false }.await;
+    }
+
+    Ok((fence, in_use))
+}
+
+fn timed_out(operation: &str, timeout: Duration) -> anyhow::Error
+{
+    anyhow!(
+        "the {operation} didn't complete within {} ms, the shader may be hanging or the GPU is unresponsive \
+         (raise --timeout-ms for very heavy renders)",
+        timeout.as_millis(),
+    )
+}
@@ -0,0 +1,400 @@
+use anyhow::{bail, ensure, Context, Result};
+#[cfg(feature = "image-output")]
+use image::{DynamicImage, ImageBuffer, Rgba, Rgba32FImage, RgbaImage};
+use log::{info, warn};
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::format::{Format, FormatFeatures};
+use vulkano::image::{mip_level_extent, ImageFormatInfo, ImageType, ImageUsage};
+use vulkano::DeviceSize;
+
+/// What a render returns: an image of the matching channel type, or with the `image-output`
+/// feature disabled its tightly packed pixel bytes.
+#[cfg(feature = "image-output")]
+pub type RenderOutput = DynamicImage;
+/// What a render returns: an image of the matching channel type, or with the `image-output`
+/// feature disabled its tightly packed pixel bytes.
+#[cfg(not(feature = "image-output"))]
+pub type RenderOutput = Vec<u8>;
+
+/// The tightly packed pixel bytes of `output`.
+#[cfg(feature = "image-output")]
+pub fn output_bytes(output: &RenderOutput) -> &[u8]
+{
+    output.as_bytes()
+}
+
+/// The tightly packed pixel bytes of `output`.
+#[cfg(not(feature = "image-output"))]
+pub fn output_bytes(output: &RenderOutput) -> &[u8]
+{
+    output
+}
+
+/// Checks that `format` can back a storage image of `image_type` created with `usage` on `physical_device`.
+pub fn check_storage_support(
+    physical_device: &PhysicalDevice,
+    format: Format,
+    image_type: ImageType,
+    usage: ImageUsage,
+) -> Result<()>
+{
+    let device_name = &physical_device.properties().device_name;
+
+    let format_properties = physical_device
+        .format_properties(format)
+        .with_context(|| format!("failed to query format properties of {format:?}"))?;
+    ensure!(
+        format_properties.optimal_tiling_features.contains(FormatFeatures::STORAGE_IMAGE),
+        "{format:?} doesn't support STORAGE_IMAGE with optimal tiling on {device_name}"
+    );
+
+    let image_format_properties = physical_device
+        .image_format_properties(ImageFormatInfo {
+            format,
+            image_type,
+            usage,
+            ..Default::default()
+        })
+        .with_context(|| format!("failed to query image format properties of {format:?}"))?;
+    ensure!(
+        image_format_properties.is_some(),
+        "{image_type:?} {format:?} images can't be created with {usage:?} usage on {device_name}"
+    );
+
+    Ok(())
+}
+
+/// Formats `--format-list` reports on, the candidates of every [`PixelFormat`] and other common
+/// storage formats.
+const LISTED_FORMATS: [Format; 12] = [
+    Format::R8G8B8A8_UNORM,
+    Format::B8G8R8A8_UNORM,
+    Format::R8G8B8A8_SNORM,
+    Format::R8G8B8A8_SRGB,
+    Format::R16G16B16A16_UNORM,
+    Format::R16G16B16A16_SFLOAT,
+    Format::R32G32B32A32_SFLOAT,
+    Format::A2B10G10R10_UNORM_PACK32,
+    Format::B10G11R11_UFLOAT_PACK32,
+    Format::R8_UNORM,
+    Format::R32_SFLOAT,
+    Format::R32_UINT,
+];
+
+/// Prints which of [`LISTED_FORMATS`] can back a 2D storage image on `physical_device` as a table
+/// to stdout, supported ones first, with the largest extent they can be created with.
+pub fn print_storage_formats(physical_device: &PhysicalDevice) -> Result<()>
+{
+    let mut rows = Vec::with_capacity(LISTED_FORMATS.len());
+    for format in LISTED_FORMATS {
+        let image_format_properties = physical_device
+            .image_format_properties(ImageFormatInfo {
+                format,
+                image_type: ImageType::Dim2d,
+                usage: ImageUsage::STORAGE,
+                ..Default::default()
+            })
+            .with_context(|| format!("failed to query image format properties of {format:?}"))?;
+        let max_extent = image_format_properties.map(|properties| properties.max_extent);
+        rows.push((format!("{format:?}"), max_extent));
+    }
+    // Stable, so the formats keep their order within both groups
+    rows.sort_by_key(|(_, max_extent)| max_extent.is_none());
+
+    let name_width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    println!("Storage image formats of {}:", physical_device.properties().device_name);
+    println!("    {:<name_width$}  {:<9}  {:>13}", "format", "supported", "max extent");
+    for (name, max_extent) in rows {
+        let (supported, max_extent) = match max_extent {
+            Some([width, height, _]) => ("yes", format!("{width} x {height}")),
+            None => ("no", "-".to_owned()),
+        };
+        println!("    {name:<name_width$}  {supported:<9}  {max_extent:>13}");
+    }
+
+    Ok(())
+}
+
+/// Picks the first of the [`PixelFormat::candidate_formats`] of `pixel_format` that can back a
+/// storage image of `image_type` created with `usage` on `physical_device`.
+pub fn select_storage_format(
+    physical_device: &PhysicalDevice,
+    pixel_format: PixelFormat,
+    image_type: ImageType,
+    usage: ImageUsage,
+) -> Result<Format>
+{
+    let mut errors = Vec::new();
+    for &format in pixel_format.candidate_formats() {
+        match check_storage_support(physical_device, format, image_type, usage) {
+            Ok(()) => {
+                info!("Using storage format {format:?}");
+                if format == Format::R8G8B8A8_SNORM {
+                    warn!("{format:?} only stores 7 bits per channel, the output loses precision");
+                }
+
+                return Ok(format);
+            }
+            Err(error) => errors.push(format!("{error:#}")),
+        }
+    }
+
+    bail!("no storage format for {pixel_format:?} is supported: {}", errors.join(", "))
+}
+
+/// Rewrites `bytes` read back from a `format` image in place into the RGBA layout of
+/// [`PixelFormat::to_dynamic_image`], for each of the [`PixelFormat::candidate_formats`].
+pub fn convert_to_rgba(format: Format, bytes: &mut [u8])
+{
+    match format {
+        Format::B8G8R8A8_UNORM => bytes.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2)),
+        // The shaders only write 0.0 to 1.0, which SNORM stores as 0 to 127
+        Format::R8G8B8A8_SNORM => bytes.iter_mut().for_each(|channel| {
+            let value = (*channel as i8).max(0) as f32 / 127.0;
+            *channel = (value * 255.0).round() as u8;
+        }),
+        _ => {}
+    }
+}
+
+/// Checks that `format` can be both source and destination of a linearly filtered blit.
+pub fn check_blit_support(physical_device: &PhysicalDevice, format: Format) -> Result<()>
+{
+    let required_features =
+        FormatFeatures::BLIT_SRC | FormatFeatures::BLIT_DST | FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR;
+
+    let format_properties = physical_device
+        .format_properties(format)
+        .with_context(|| format!("failed to query format properties of {format:?}"))?;
+    ensure!(
+        format_properties.optimal_tiling_features.contains(required_features),
+        "{format:?} doesn't support linear blits on {}, which generating mipmaps needs",
+        physical_device.properties().device_name,
+    );
+
+    Ok(())
+}
+
+/// Strips the padding from `rows` rows of `row_len` bytes that start `row_pitch` bytes apart.
+pub fn strip_row_padding(bytes: &[u8], row_len: usize, row_pitch: usize, rows: usize) -> Vec<u8>
+{
+    if row_len == row_pitch {
+        return bytes[..row_len * rows].to_vec();
+    }
+
+    bytes
+        .chunks(row_pitch)
+        .take(rows)
+        .flat_map(|row| &row[..row_len])
+        .copied()
+        .collect()
+}
+
+/// Order the channels of every output pixel are stored in.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Swizzle {
+    /// Red, green, blue, alpha, as the shaders write them
+    #[default]
+    Rgba,
+    /// Blue, green, red, alpha
+    Bgra,
+    /// Alpha, red, green, blue
+    Argb,
+}
+
+impl Swizzle {
+    /// The RGBA channel stored at each position of a pixel.
+    fn order(self) -> [usize; 4]
+    {
+        match self {
+            Swizzle::Rgba => [0, 1, 2, 3],
+            Swizzle::Bgra => [2, 1, 0, 3],
+            Swizzle::Argb => [3, 0, 1, 2],
+        }
+    }
+
+    /// Reorders the RGBA pixels of `pixel_format` in `bytes` in place.
+    pub fn apply(self, pixel_format: PixelFormat, bytes: &mut [u8])
+    {
+        if self == Swizzle::Rgba {
+            return;
+        }
+
+        let pixel_size = pixel_format.bytes_per_pixel() as usize;
+        let channel_size = pixel_size / 4;
+        let order = self.order();
+        for pixel in bytes.chunks_exact_mut(pixel_size) {
+            let mut rgba = [0u8; 16];
+            rgba[..pixel_size].copy_from_slice(pixel);
+            for (stored, channel) in pixel.chunks_exact_mut(channel_size).zip(order) {
+                stored.copy_from_slice(&rgba[channel * channel_size..][..channel_size]);
+            }
+        }
+    }
+}
+
+/// Storage format of the rendered image.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 8-bit unsigned normalized channels
+    #[default]
+    Rgba8,
+    /// 16-bit unsigned normalized channels
+    Rgba16,
+    /// 32-bit floating point channels
+    Rgba32f,
+}
+
+impl PixelFormat {
+    /// The Vulkan format the storage image is created with.
+    pub fn format(self) -> Format
+    {
+        match self {
+            PixelFormat::Rgba8 => Format::R8G8B8A8_UNORM,
+            PixelFormat::Rgba16 => Format::R16G16B16A16_UNORM,
+            PixelFormat::Rgba32f => Format::R32G32B32A32_SFLOAT,
+        }
+    }
+
+    /// Formats the image can be created with, in order of preference.
+    ///
+    /// Each one holds `bytes_per_pixel` bytes per pixel and is turned into the layout of
+    /// [`format`](Self::format) by [`convert_to_rgba`] when it's read back.
+    pub fn candidate_formats(self) -> &'static [Format]
+    {
+        match self {
+            PixelFormat::Rgba8 => &[Format::R8G8B8A8_UNORM, Format::B8G8R8A8_UNORM, Format::R8G8B8A8_SNORM],
+            PixelFormat::Rgba16 => &[Format::R16G16B16A16_UNORM],
+            PixelFormat::Rgba32f => &[Format::R32G32B32A32_SFLOAT],
+        }
+    }
+
+    /// Multiplies the colour channels of the RGBA pixels in `bytes` by their alpha in place,
+    /// rounding to the nearest value of the 8 and 16 bit formats.
+    pub fn premultiply_alpha(self, bytes: &mut [u8])
+    {
+        match self {
+            PixelFormat::Rgba8 => {
+                for pixel in bytes.chunks_exact_mut(4) {
+                    let alpha = pixel[3] as u32;
+                    for channel in &mut pixel[..3] {
+                        *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+                    }
+                }
+            }
+            PixelFormat::Rgba16 => {
+                for pixel in bytes.chunks_exact_mut(8) {
+                    let alpha = u16::from_ne_bytes([pixel[6], pixel[7]]) as u64;
+                    for channel in pixel[..6].chunks_exact_mut(2) {
+                        let value = u16::from_ne_bytes([channel[0], channel[1]]) as u64;
+                        channel.copy_from_slice(&(((value * alpha + 32767) / 65535) as u16).to_ne_bytes());
+                    }
+                }
+            }
+            PixelFormat::Rgba32f => {
+                for pixel in bytes.chunks_exact_mut(16) {
+                    let alpha = f32::from_ne_bytes([pixel[12], pixel[13], pixel[14], pixel[15]]);
+                    for channel in pixel[..12].chunks_exact_mut(4) {
+                        let value = f32::from_ne_bytes([channel[0], channel[1], channel[2], channel[3]]);
+                        channel.copy_from_slice(&(value * alpha).to_ne_bytes());
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> u32
+    {
+        match self {
+            PixelFormat::Rgba8 => 4,
+            PixelFormat::Rgba16 => 8,
+            PixelFormat::Rgba32f => 16,
+        }
+    }
+
+    /// Bytes from the start of one row of a `width` pixel wide image to the next in a buffer.
+    ///
+    /// Rows are padded to a multiple of both `row_alignment` and the pixel size, so every row
+    /// starts on a whole pixel. Both are powers of two, so that is the larger of the two, and a
+    /// `row_alignment` of 1 packs the rows tightly.
+    pub fn row_pitch(self, width: u32, row_alignment: DeviceSize) -> DeviceSize
+    {
+        let bytes_per_pixel = self.bytes_per_pixel() as DeviceSize;
+
+        (width as DeviceSize * bytes_per_pixel).next_multiple_of(row_alignment.max(bytes_per_pixel))
+    }
+
+    /// Size in bytes of `mip_levels` levels of an `extent` image stored one after the other,
+    /// largest first and with rows padded by [`row_pitch`](Self::row_pitch), or `None` when it
+    /// doesn't fit in a `DeviceSize`.
+    pub fn buffer_size(self, extent: [u32; 3], mip_levels: u32, row_alignment: DeviceSize) -> Option<DeviceSize>
+    {
+        (0..mip_levels)
+            .filter_map(|level| mip_level_extent(extent, level))
+            .try_fold(0 as DeviceSize, |size, [level_width, level_height, level_depth]| {
+                let level_size = self
+                    .row_pitch(level_width, row_alignment)
+                    .checked_mul(level_height as DeviceSize)?
+                    .checked_mul(level_depth as DeviceSize)?;
+                size.checked_add(level_size)
+            })
+    }
+
+    /// Turns the tightly packed pixels of a `width`x`height` render into its [`RenderOutput`].
+    #[cfg(feature = "image-output")]
+    pub fn to_output(self, width: u32, height: u32, pixels: Vec<u8>) -> Result<RenderOutput>
+    {
+        self.to_dynamic_image(width, height, &pixels)
+    }
+
+    /// Turns the tightly packed pixels of a `width`x`height` render into its [`RenderOutput`].
+    #[cfg(not(feature = "image-output"))]
+    pub fn to_output(self, width: u32, height: u32, pixels: Vec<u8>) -> Result<RenderOutput>
+    {
+        self.check_packed_len(width, height, pixels.len())?;
+
+        Ok(pixels)
+    }
+
+    /// Checks that `len` bytes are exactly the tightly packed pixels of a `width`x`height` image.
+    fn check_packed_len(self, width: u32, height: u32, len: usize) -> Result<()>
+    {
+        let expected_len = width as usize * height as usize * self.bytes_per_pixel() as usize;
+        ensure!(
+            len == expected_len,
+            "{len} bytes don't hold a {width}x{height} {self:?} image, which takes {expected_len} bytes"
+        );
+
+        Ok(())
+    }
+
+    /// Builds an image of the matching channel type from tightly packed pixel bytes.
+    #[cfg(feature = "image-output")]
+    pub fn to_dynamic_image(self, width: u32, height: u32, bytes: &[u8]) -> Result<DynamicImage>
+    {
+        // `from_raw` only returns `None` for a wrong length, which is reported with both lengths here
+        self.check_packed_len(width, height, bytes.len())?;
+
+        let image = match self {
+            PixelFormat::Rgba8 => {
+                RgbaImage::from_raw(width, height, bytes.to_vec()).map(DynamicImage::ImageRgba8)
+            }
+            PixelFormat::Rgba16 => {
+                let channels = bytes
+                    .chunks_exact(2)
+                    .map(|channel| u16::from_ne_bytes([channel[0], channel[1]]))
+                    .collect();
+                ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, channels).map(DynamicImage::ImageRgba16)
+            }
+            PixelFormat::Rgba32f => {
+                let channels = bytes
+                    .chunks_exact(4)
+                    .map(|channel| f32::from_ne_bytes([channel[0], channel[1], channel[2], channel[3]]))
+                    .collect();
+                Rgba32FImage::from_raw(width, height, channels).map(DynamicImage::ImageRgba32F)
+            }
+        };
+
+        image.context("failed to construct an ImageBuffer")
+    }
+}
@@ -47,9 +47,9 @@ fn main() -> Result<()>
         .iter()
         .enumerate()
         .position(|(_queue_family_index, queue_family_properties)| {
-            queue_family_properties.queue_flags.contains(QueueFlags::GRAPHICS)
+            queue_family_properties.queue_flags.contains(QueueFlags::COMPUTE)
         })
-        .context("couldn't find a graphical queue family")? as u32;
+        .context("couldn't find a compute queue family")? as u32;
 
     let (device, mut queues) = Device::new(
         physical_device,
@@ -0,0 +1,110 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Ok, Result};
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferToImageInfo};
+use vulkano::device::Queue;
+use vulkano::format::Format;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::sync::{self, GpuFuture};
+
+use crate::fence::{block_on, wait_with_timeout};
+use crate::out_of_memory::MemoryContext;
+
+/// Tightly packed 8-bit RGBA pixels of an input image.
+pub struct InputImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Loads the picture at `path`, converted to 8-bit RGBA.
+#[cfg(feature = "image-output")]
+pub fn load_input_image(path: &Path) -> Result<InputImage>
+{
+    let image = image::open(path)
+        .with_context(|| format!("failed to open input image {}", path.display()))?
+        .to_rgba8();
+
+    Ok(InputImage {
+        width: image.width(),
+        height: image.height(),
+        pixels: image.into_raw(),
+    })
+}
+
+/// Fails, decoding pictures needs the `image-output` feature.
+#[cfg(not(feature = "image-output"))]
+pub fn load_input_image(path: &Path) -> Result<InputImage>
+{
+    anyhow::bail!("can't decode input image {}, build with the image-output feature", path.display())
+}
+
+/// Uploads the pixels of `input` into a new `R8G8B8A8_UNORM` storage image through a staging buffer.
+pub fn upload_input_image(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: &Arc<StandardCommandBufferAllocator>,
+    queue: &Arc<Queue>,
+    input: &InputImage,
+    timeout: Duration,
+) -> Result<Arc<Image>>
+{
+    let image = Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R8G8B8A8_UNORM,
+            extent: [input.width, input.height, 1],
+            usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+    )
+    .memory_context("failed to create an input image")?;
+
+    let staging_buffer = Buffer::from_iter(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        input.pixels.iter().copied(),
+    )
+    .memory_context("failed to create an input staging buffer")?;
+
+    let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+        command_buffer_allocator.clone(),
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .context("failed to create an AutoCommandBufferBuilder")?;
+
+    command_buffer_builder
+        .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(staging_buffer, image.clone()))
+        .context("failed to copy the input buffer to an image")?;
+
+    let command_buffer = command_buffer_builder
+        .build()
+        .context("failed to build a PrimaryAutoCommandBuffer")?;
+
+    let future = sync::now(queue.device().clone())
+        .then_execute(queue.clone(), command_buffer)
+        .context("failed to execute the input upload")?
+        .then_signal_fence_and_flush()
+        .memory_context("failed to signal a fence after the input upload")?;
+
+    block_on(wait_with_timeout(future, timeout, "input upload"))?;
+
+    Ok(image)
+}
@@ -0,0 +1,157 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Ok, Result};
+use log::{debug, error, info, warn};
+use vulkano::instance::debug::{
+    DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessenger, DebugUtilsMessengerCallback,
+    DebugUtilsMessengerCreateInfo, ValidationFeatureEnable,
+};
+use vulkano::instance::{Instance, InstanceCreateFlags, InstanceCreateInfo, InstanceExtensions};
+use vulkano::VulkanLibrary;
+
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Number of error messages the debug messengers of this process have received.
+static VALIDATION_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of error messages the validation layer has reported so far, over every instance.
+pub fn validation_error_count() -> usize
+{
+    VALIDATION_ERRORS.load(Ordering::Relaxed)
+}
+
+/// Whether an instance of this process was created with the validation layer enabled.
+static VALIDATION_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the validation layer was enabled on any instance so far, it is skipped with a warning
+/// when it isn't installed.
+pub fn validation_layer_enabled() -> bool
+{
+    VALIDATION_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Creates the instance, with the validation layer when `enable_validation` is set and it is
+/// installed.
+///
+/// `debug_printf` also makes the layer forward the output of `debugPrintfEXT` in shaders to the
+/// debug messenger, which `ext_validation_features` being enabled on the instance tells.
+pub fn create_instance(library: Arc<VulkanLibrary>, enable_validation: bool, debug_printf: bool) -> Result<Arc<Instance>>
+{
+    create_instance_with_extensions(library, enable_validation, debug_printf, InstanceExtensions::empty())
+}
+
+/// Like [`create_instance`], and also enables `required_extensions`, e.g. the ones a window
+/// surface needs.
+pub fn create_instance_with_extensions(
+    library: Arc<VulkanLibrary>,
+    enable_validation: bool,
+    debug_printf: bool,
+    required_extensions: InstanceExtensions,
+) -> Result<Arc<Instance>>
+{
+    let mut enabled_layers = Vec::new();
+    let mut enabled_extensions = required_extensions;
+    let mut enabled_validation_features = Vec::new();
+
+    if enable_validation || debug_printf {
+        let has_validation_layer = library
+            .layer_properties()
+            .context("failed to enumerate instance layers")?
+            .any(|layer| layer.name() == VALIDATION_LAYER);
+
+        if has_validation_layer {
+            enabled_layers.push(VALIDATION_LAYER.to_owned());
+            enabled_extensions.ext_debug_utils = true;
+        } else if debug_printf {
+            warn!("{VALIDATION_LAYER} is not available, continuing without validation and shader printf");
+        } else {
+            warn!("{VALIDATION_LAYER} is not available, continuing without validation");
+        }
+
+        if has_validation_layer && debug_printf {
+            let layer_extensions = library
+                .supported_layer_extensions(VALIDATION_LAYER)
+                .context("failed to enumerate the extensions of the validation layer")?;
+
+            if layer_extensions.ext_validation_features {
+                enabled_extensions.ext_validation_features = true;
+                enabled_validation_features.push(ValidationFeatureEnable::DebugPrintf);
+            } else {
+                warn!("{VALIDATION_LAYER} doesn't support VK_EXT_validation_features, continuing without shader printf");
+            }
+        }
+    }
+
+    // Portability drivers such as MoltenVK are only enumerated when both the flag and the
+    // extension are enabled
+    let mut flags = InstanceCreateFlags::empty();
+    if library.supported_extensions().khr_portability_enumeration {
+        enabled_extensions.khr_portability_enumeration = true;
+        flags |= InstanceCreateFlags::ENUMERATE_PORTABILITY;
+    }
+
+    let instance = Instance::new(
+        library,
+        InstanceCreateInfo {
+            flags,
+            enabled_layers,
+            enabled_extensions,
+            enabled_validation_features,
+            ..Default::default()
+        },
+    )
+    .context("failed to create instance")?;
+    if instance.enabled_layers().iter().any(|layer| layer == VALIDATION_LAYER) {
+        VALIDATION_ENABLED.store(true, Ordering::Relaxed);
+    }
+
+    Ok(instance)
+}
+
+pub fn setup_debug_messenger(instance: &Arc<Instance>) -> Result<Option<DebugUtilsMessenger>>
+{
+    if !instance.enabled_extensions().ext_debug_utils {
+        return Ok(None);
+    }
+
+    // SAFETY: the callback only forwards messages to `log` and counts the errors, it never calls
+    // into Vulkan
+    let user_callback = unsafe {
+        DebugUtilsMessengerCallback::new(|message_severity, message_type, callback_data| {
+            let message_type = format!("{message_type:?}");
+            let message = callback_data.message;
+
+            // The validation layer reports what shaders print as info messages of its own
+            if callback_data.message_id_name.is_some_and(|name| name.contains("DEBUG-PRINTF")) {
+                info!("[shader printf] {message}");
+            } else if message_severity.intersects(DebugUtilsMessageSeverity::ERROR) {
+                VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+                error!("[{message_type}] {message}");
+            } else if message_severity.intersects(DebugUtilsMessageSeverity::WARNING) {
+                warn!("[{message_type}] {message}");
+            } else if message_severity.intersects(DebugUtilsMessageSeverity::INFO) {
+                info!("[{message_type}] {message}");
+            } else {
+                debug!("[{message_type}] {message}");
+            }
+        })
+    };
+
+    let messenger = DebugUtilsMessenger::new(
+        instance.clone(),
+        DebugUtilsMessengerCreateInfo {
+            message_severity: DebugUtilsMessageSeverity::ERROR
+                | DebugUtilsMessageSeverity::WARNING
+                | DebugUtilsMessageSeverity::INFO
+                | DebugUtilsMessageSeverity::VERBOSE,
+            message_type: DebugUtilsMessageType::GENERAL
+                | DebugUtilsMessageType::VALIDATION
+                | DebugUtilsMessageType::PERFORMANCE,
+            ..DebugUtilsMessengerCreateInfo::user_callback(user_callback)
+        },
+    )
+    .context("failed to create a debug messenger")?;
+
+    Ok(Some(messenger))
+}
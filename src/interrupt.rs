@@ -0,0 +1,26 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes Ctrl-C ask frame and repeat loops to stop after the current frame rather than killing
+/// the process in the middle of a submission. A second Ctrl-C exits right away.
+pub fn install_handler() -> Result<()>
+{
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(130);
+        }
+
+        eprintln!("\nInterrupted, stopping after the current frame (press Ctrl-C again to exit immediately)");
+    })
+    .context("failed to install the Ctrl-C handler")
+}
+
+/// Whether Ctrl-C was pressed since [`install_handler`] was called.
+pub fn interrupted() -> bool
+{
+    INTERRUPTED.load(Ordering::SeqCst)
+}
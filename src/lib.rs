@@ -0,0 +1,3302 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::mem;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, ensure, Context, Ok, Result};
+use ash::vk;
+#[cfg(feature = "image-output")]
+use image::RgbaImage;
+use log::{info, warn};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, BlitImageInfo, BufferImageCopy, ClearColorImageInfo, CommandBuffer,
+    CommandBufferBeginInfo, CommandBufferInheritanceInfo, CommandBufferLevel, CommandBufferUsage, CopyBufferInfo,
+    CopyImageToBufferInfo, ImageBlit, PrimaryAutoCommandBuffer, RecordingCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::{StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo};
+use vulkano::descriptor_set::{DescriptorSet, WriteDescriptorSet};
+use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
+use vulkano::format::{ClearColorValue, Format};
+use vulkano::device::{Device, DeviceCreateInfo, DeviceFeatures, Queue, QueueCreateInfo};
+use vulkano::image::sampler::Filter;
+use vulkano::image::view::{ImageView, ImageViewCreateInfo};
+use vulkano::image::{
+    max_mip_levels, mip_level_extent, Image, ImageCreateInfo, ImageLayout, ImageSubresourceLayers, ImageType,
+    ImageUsage,
+};
+use vulkano::instance::debug::DebugUtilsMessenger;
+use vulkano::instance::Instance;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::memory::MemoryPropertyFlags;
+use vulkano::pipeline::cache::PipelineCache;
+use vulkano::pipeline::compute::ComputePipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo};
+use vulkano::shader::{ShaderModule, SpecializationConstant};
+use vulkano::sync::fence::{Fence, FenceCreateInfo};
+use vulkano::sync::semaphore::{Semaphore, SemaphoreCreateInfo};
+use vulkano::sync::{
+    AccessFlags, BufferMemoryBarrier, DependencyInfo, GpuFuture, ImageMemoryBarrier, PipelineStages, Sharing,
+};
+use vulkano::{sync, DeviceSize, Version, VulkanError, VulkanLibrary, VulkanObject};
+
+pub mod chain;
+#[cfg(feature = "image-output")]
+pub mod compare;
+pub mod device;
+mod fence;
+pub mod format;
+pub mod input;
+pub mod instance;
+pub mod interrupt;
+mod out_of_memory;
+pub mod palette;
+pub mod pipeline_cache;
+#[cfg(feature = "preview")]
+pub mod preview;
+mod resources;
+pub mod shader;
+pub mod timings;
+mod timestamp;
+
+use device::{
+    check_device_support, check_heap_size, check_image_memory_type, check_queue_priority, check_subgroup_support,
+    check_workgroup_size, enable_portability_subset, find_compute_queue_family, find_transfer_queue_family,
+    log_image_memory, log_memory_heaps, log_subgroup_properties, pick_physical_device, rank_physical_devices,
+};
+use fence::{block_on, wait_for_fence, wait_with_timeout, SubmittedFuture};
+use format::{
+    check_blit_support, check_storage_support, convert_to_rgba, output_bytes, select_storage_format, strip_row_padding,
+};
+pub use fence::FenceHandle;
+pub use format::{PixelFormat, RenderOutput, Swizzle};
+pub use out_of_memory::DeviceLost;
+use input::{load_input_image, upload_input_image, InputImage};
+use instance::{create_instance, setup_debug_messenger};
+use interrupt::interrupted;
+use out_of_memory::MemoryContext;
+use pipeline_cache::{load_pipeline_cache, save_pipeline_cache};
+use resources::ResourceReport;
+use palette::{load_palette, PaletteLut};
+use shader::{
+    check_descriptor_writes, check_entry_point, compile_glsl, load_histogram_shader, load_palette_lut_shader,
+    load_mandelbrot_iterations_shader, load_spirv, load_verify_shader,
+};
+pub use shader::ShaderKind;
+use timestamp::TimestampQuery;
+pub use timings::Timings;
+
+/// Values pushed to the compute shader before the dispatch.
+///
+/// Mirrors the `layout(push_constant)` block that every shader declares, so the field order and
+/// types have to match it exactly. Push constant blocks use std430 layout: scalars are aligned to 4 bytes,
+/// `vec2` to 8 and `vec3`/`vec4` to 16, and `#[repr(C)]` only agrees with that as long as every
+/// field is placed on its natural alignment. Add padding fields where it isn't.
+#[derive(BufferContents, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct PushConstants {
+    pub center: [f32; 2],
+    /// Position of the current tile within the full image
+    pub offset: [u32; 2],
+    /// Size of the full image, which may span several tiles
+    pub size: [u32; 2],
+    pub zoom: f32,
+    pub iterations: u32,
+    pub time: f32,
+    pub scale: f32,
+    pub radius: u32,
+    pub seed: u32,
+    /// Index of the current pass of an iterative shader
+    pub pass_index: u32,
+    /// Slope exaggeration of the normal map shader
+    pub strength: f32,
+}
+
+/// Number of colours in the palette of [`Params`].
+pub const PALETTE_SIZE: usize = 8;
+
+/// Colours the Mandelbrot shader blends between, the alpha channel is ignored.
+pub const DEFAULT_PALETTE: [[f32; 4]; PALETTE_SIZE] = [
+    [0.0, 0.0, 0.0, 1.0],
+    [0.5, 0.5, 0.5, 1.0],
+    [1.0, 0.5, 0.5, 1.0],
+    [0.5, 1.0, 0.5, 1.0],
+    [0.5, 0.5, 1.0, 1.0],
+    [0.5, 1.0, 1.0, 1.0],
+    [1.0, 0.5, 1.0, 1.0],
+    [1.0, 1.0, 0.5, 1.0],
+];
+
+/// Shader parameters that don't fit in push constants, bound as a uniform buffer at binding 2.
+///
+/// Mirrors the `Params` uniform block, which uses std140 layout: array elements are padded to 16
+/// bytes, so the palette is stored as `vec4` rather than `vec3`.
+#[derive(BufferContents, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct Params {
+    pub palette: [[f32; 4]; PALETTE_SIZE],
+}
+
+/// Events the shader counts with atomics, bound as a storage buffer at binding 3.
+///
+/// Mirrors the `Counters` buffer block of `mandelbrot.comp`, where it counts the pixels that
+/// never escaped.
+#[derive(BufferContents, Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Counters {
+    pub inside_pixels: u32,
+}
+
+/// Number of luminance bins the histogram of a render counts pixels in, one per 8-bit level.
+pub const HISTOGRAM_BINS: usize = 256;
+
+/// How the rendered image is downloaded to host memory.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DownloadMode {
+    /// Copy the image straight into a host-visible buffer
+    #[default]
+    Direct,
+    /// Copy the image into a device-local buffer first, then into the host-visible one
+    Staged,
+}
+
+/// Kind of memory the storage image is allocated from.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImageMemoryType {
+    /// Device-local memory, the fastest for the shader to write
+    #[default]
+    Device,
+    /// Host-visible memory, preferably not device-local
+    Host,
+    /// Host-visible device-local memory on integrated GPUs, device-local memory on the others
+    Auto,
+}
+
+impl ImageMemoryType {
+    /// Filter the image's memory type is picked with on `physical_device`.
+    fn memory_type_filter(self, physical_device: &PhysicalDevice) -> MemoryTypeFilter
+    {
+        match self {
+            Self::Device => MemoryTypeFilter::PREFER_DEVICE,
+            Self::Host => MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            // Integrated GPUs and software implementations share their memory with the host, so
+            // it usually costs nothing to make it host-visible too
+            Self::Auto => match physical_device.properties().device_type {
+                PhysicalDeviceType::IntegratedGpu | PhysicalDeviceType::Cpu => {
+                    MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_RANDOM_ACCESS
+                }
+                _ => MemoryTypeFilter::PREFER_DEVICE,
+            },
+        }
+    }
+}
+
+/// Parameters of a single compute render.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderConfig {
+    /// Width of the rendered image in pixels
+    pub width: u32,
+    /// Height of the rendered image in pixels
+    pub height: u32,
+    /// Enables `VK_LAYER_KHRONOS_validation` when it is installed
+    pub validation: bool,
+    /// Logs what the shader prints with `debugPrintfEXT`, through the validation layer
+    pub debug_printf: bool,
+    /// Animation time, cycles the colour palette
+    pub time: f32,
+    /// Scale factor of the gradient pattern
+    pub scale: f32,
+    /// Width and height of the compute workgroup
+    pub workgroup_size: [u32; 2],
+    /// Path the image takes from device to host memory
+    pub download_mode: DownloadMode,
+    /// Kind of memory the storage image is allocated from
+    pub memory_type: ImageMemoryType,
+    /// File the compiled pipeline is cached in between runs
+    pub pipeline_cache: Option<PathBuf>,
+    /// Storage format of the rendered image
+    pub pixel_format: PixelFormat,
+    /// Order the channels of the rendered pixels are returned in
+    pub swizzle: Swizzle,
+    /// Index of the device to render on, picked automatically when `None`
+    pub device_index: Option<usize>,
+    /// UUID of the device to render on, used when `device_index` is `None`
+    pub device_uuid: Option<[u8; 16]>,
+    /// Whether software (CPU) Vulkan implementations may be used
+    pub allow_software: bool,
+    /// Whether the readback buffers are filled with zeros when they are created, instead of being
+    /// left uninitialized until the first copy overwrites them
+    pub clear_buffer: bool,
+    /// Picture bound at binding 1 for the shader to read, its size overrides `width` and `height`
+    pub input: Option<PathBuf>,
+    /// Width and height the resize shader scales `input` to, overriding the input's size
+    pub resize: Option<[u32; 2]>,
+    /// Kernel that is dispatched
+    pub shader: ShaderKind,
+    /// Blur radius in pixels
+    pub radius: u32,
+    /// Factor the normal map shader scales the slopes of the heightfield by
+    pub strength: f32,
+    /// Seed of the noise shader, the same seed and size give the same image on the same device
+    pub seed: u32,
+    /// Point of the complex plane at the centre of the Mandelbrot view
+    pub center: [f32; 2],
+    /// Mandelbrot magnification, 1.0 shows the whole set
+    pub zoom: f32,
+    /// Mandelbrot iteration limit
+    pub iterations: u32,
+    /// How long a single GPU submission may take before the render is aborted
+    pub timeout: Duration,
+    /// Priority within `0.0..=1.0` the compute and transfer queues are created with, how much it
+    /// changes the scheduling next to other GPU work is up to the driver
+    pub queue_priority: f32,
+    /// Splits single renders between the two best devices
+    pub multi_gpu: bool,
+    /// Colour the image is cleared to before each dispatch, left uninitialised when `None`
+    pub clear_color: Option<[f32; 4]>,
+    /// Generates the full mip chain of the image by blitting after the dispatch
+    pub mipmaps: bool,
+    /// Number of slices of the 3D image that volume shaders write, 1 for every other shader
+    pub depth: u32,
+    /// Records each tile's dispatch into a secondary command buffer that the primary one executes
+    pub secondary_command_buffers: bool,
+    /// Precompiled SPIR-V module that replaces the built-in shader, which still decides the
+    /// features, input and dimensionality of the render
+    pub spirv: Option<PathBuf>,
+    /// GLSL compute shader compiled at runtime that replaces the built-in shader like `spirv` does
+    pub glsl: Option<PathBuf>,
+    /// Name of the entry point of `spirv` or `glsl` that is run, the built-in shaders only have `main`
+    pub entry_point: String,
+    /// Number of passes an iterative shader runs, each one reading the output of the one before
+    pub passes: u32,
+    /// Largest number of passes recorded into one submission, every pass of a tile goes into a
+    /// single one when `None`. Each batch but the last is waited for before the next is recorded.
+    pub iterations_per_submit: Option<u32>,
+    /// Waits for the device and reports allocations that are still alive when a renderer is dropped
+    pub debug_resources: bool,
+    /// Treats the shader's colours as linear and encodes them as sRGB, for 8 and 16 bit formats
+    pub srgb: bool,
+    /// Multiplies the colour channels of every pixel by its alpha when it's read back
+    pub premultiply_alpha: bool,
+    /// Records each tile with hand-written pipeline barriers instead of the ones the command
+    /// buffer builder inserts
+    pub explicit_barriers: bool,
+    /// Fails before rendering when the device can't run subgroup arithmetic in compute shaders
+    pub require_subgroup_ops: bool,
+    /// Largest width and height of a tile, renders are only tiled past `max_image_dimension2_d` when `None`
+    pub tile_size: Option<u32>,
+    /// Number of tiles recorded, submitted and read back at the same time by separate threads
+    pub threads: usize,
+    /// CSV file the luminance histogram of the last frame is written to, counted by a second dispatch
+    pub histogram: Option<PathBuf>,
+    /// Overwrites the last row of the image with a checksum pattern in a second dispatch and
+    /// fails the frame if the pattern read back doesn't match the one recomputed on the host
+    pub verify: bool,
+    /// Palette the luminance of every pixel is mapped through by a second dispatch, a CSV file or
+    /// a picture [`LUT_ENTRIES`](palette::LUT_ENTRIES) pixels wide
+    pub palette: Option<PathBuf>,
+    /// 16-bit grayscale PNG the Mandelbrot shader writes the iteration count of every pixel to,
+    /// through a second storage image written by the same dispatch
+    pub dump_iterations: Option<PathBuf>,
+}
+
+impl RenderConfig {
+    /// Push constants of a frame at `time`, `offset`, `size` and `pass_index` are filled in by the renderer.
+    pub fn push_constants(&self, time: f32) -> PushConstants
+    {
+        PushConstants {
+            center: self.center,
+            offset: [0, 0],
+            size: [0, 0],
+            zoom: self.zoom,
+            iterations: self.iterations,
+            time,
+            scale: self.scale,
+            radius: self.radius,
+            seed: self.seed,
+            pass_index: 0,
+            strength: self.strength,
+        }
+    }
+
+    /// Checks the values that are pushed as constants, for every config of a batch as only the
+    /// first of the configs sharing a renderer goes through [`RenderSetup::new`].
+    fn check_push_constants(&self) -> Result<()>
+    {
+        ensure!(self.scale > 0.0, "scale must be positive, got {}", self.scale);
+        ensure!(self.zoom > 0.0, "zoom must be positive, got {}", self.zoom);
+        ensure!(self.iterations > 0, "iteration count must be non-zero");
+
+        Ok(())
+    }
+
+    /// Whether a renderer created for `self` can also render `other`, which is the case when they
+    /// only differ in values that are pushed as constants.
+    #[cfg(feature = "image-output")]
+    fn shares_renderer(&self, other: &RenderConfig) -> bool
+    {
+        let without_push_constants = |config: &RenderConfig| RenderConfig {
+            time: 0.0,
+            scale: 0.0,
+            radius: 0,
+            strength: 0.0,
+            seed: 0,
+            center: [0.0, 0.0],
+            zoom: 0.0,
+            iterations: 0,
+            ..config.clone()
+        };
+
+        without_push_constants(self) == without_push_constants(other)
+    }
+}
+
+/// Creates the pipeline of a pass that runs after the render, through the render's pipeline cache.
+///
+/// `name` only shows up in the error contexts.
+fn create_post_pass_pipeline(
+    device: &Arc<Device>,
+    shader: Arc<ShaderModule>,
+    name: &str,
+    specialization_info: &[(u32, SpecializationConstant)],
+    pipeline_cache: Option<Arc<PipelineCache>>,
+) -> Result<Arc<ComputePipeline>>
+{
+    let stage = PipelineShaderStageCreateInfo::new(
+        shader
+            .specialize(specialization_info.iter().cloned().collect())
+            .with_context(|| format!("failed to specialize the {name} shader"))?
+            .entry_point("main")
+            .with_context(|| format!("{name} shader has no \"main\" entry point"))?,
+    );
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(device.clone())
+            .with_context(|| format!("failed to create the {name} PipelineLayoutCreateInfo"))?,
+    )
+    .with_context(|| format!("failed to create the {name} PipelineLayout"))?;
+
+    ComputePipeline::new(device.clone(), pipeline_cache, ComputePipelineCreateInfo::stage_layout(stage, layout))
+        .with_context(|| format!("failed to create the {name} ComputePipeline"))
+}
+
+/// Creates the pipeline that counts the luminance of a rendered tile, with the same workgroup
+/// size as the render.
+fn create_histogram_pipeline(
+    device: &Arc<Device>,
+    workgroup_size: [u32; 2],
+    pipeline_cache: Option<Arc<PipelineCache>>,
+) -> Result<Arc<ComputePipeline>>
+{
+    let bins_size = size_of::<[u32; HISTOGRAM_BINS]>() as u32;
+    let max_shared_memory_size = device.physical_device().properties().max_compute_shared_memory_size;
+    ensure!(
+        bins_size <= max_shared_memory_size,
+        "the histogram counts {bins_size} bytes of bins in shared memory, above the device's \
+         max_compute_shared_memory_size of {max_shared_memory_size}"
+    );
+
+    let shader = load_histogram_shader(device.clone()).context("failed to load the histogram shader")?;
+    create_post_pass_pipeline(device, shader, "histogram", &workgroup_size_constants(workgroup_size), pipeline_cache)
+}
+
+/// Creates the pipeline that recolours a rendered tile through a palette, with the same workgroup
+/// size as the render.
+fn create_palette_pipeline(
+    device: &Arc<Device>,
+    workgroup_size: [u32; 2],
+    pipeline_cache: Option<Arc<PipelineCache>>,
+) -> Result<Arc<ComputePipeline>>
+{
+    let shader = load_palette_lut_shader(device.clone()).context("failed to load the palette shader")?;
+    create_post_pass_pipeline(device, shader, "palette", &workgroup_size_constants(workgroup_size), pipeline_cache)
+}
+
+/// Creates the pipeline that writes the checksum pattern of [`RenderConfig::verify`] into the
+/// last row of a tile.
+fn create_verify_pipeline(
+    device: &Arc<Device>,
+    pipeline_cache: Option<Arc<PipelineCache>>,
+) -> Result<Arc<ComputePipeline>>
+{
+    let shader = load_verify_shader(device.clone()).context("failed to load the verify shader")?;
+    create_post_pass_pipeline(device, shader, "verify", &[], pipeline_cache)
+}
+
+/// The specialization constants 0 and 1 of the shaders whose workgroup size is chosen at runtime.
+fn workgroup_size_constants(workgroup_size: [u32; 2]) -> [(u32, SpecializationConstant); 2]
+{
+    [(0, SpecializationConstant::U32(workgroup_size[0])), (1, SpecializationConstant::U32(workgroup_size[1]))]
+}
+
+/// Width of the workgroups of `verify.comp`, which only covers a single row.
+const VERIFY_WORKGROUP_WIDTH: u32 = 64;
+
+/// The pattern `verify.comp` writes to the pixel at `x` of the last row, as RGBA bytes.
+fn verify_pattern(x: u32, push_constants: &PushConstants) -> [u8; 4]
+{
+    let [width, height] = push_constants.size;
+    let mut h = x.wrapping_mul(0x9E37_79B9);
+    h ^= push_constants.seed ^ (width << 16) ^ height ^ push_constants.time.to_bits();
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7FEB_352D);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846C_A68B);
+    h ^= h >> 16;
+
+    h.to_le_bytes()
+}
+
+pub(crate) fn div_ceil(n: u32, d: u32) -> u32
+{
+    n.div_ceil(d)
+}
+
+/// Instance and validated inputs shared by the renderers of every device taking part in a render.
+struct RenderSetup {
+    _debug_messenger: Option<DebugUtilsMessenger>,
+    instance: Arc<Instance>,
+    input_pixels: Option<InputImage>,
+    palette: Option<PaletteLut>,
+    width: u32,
+    height: u32,
+}
+
+impl RenderSetup {
+    fn new(config: &RenderConfig, timings: &mut Timings) -> Result<Self>
+    {
+        ensure!(
+            config.input.is_some() || !config.shader.needs_input(),
+            "the {:?} shader needs an --input image",
+            config.shader,
+        );
+
+        ensure!(
+            config.resize.is_some() == (config.shader == ShaderKind::Resize),
+            "--resize sets the output size of the resize shader, which needs one"
+        );
+
+        // The resize shader reads the whole input whatever size it writes
+        let input_pixels = config.input.as_deref().map(load_input_image).transpose()?;
+        let palette = config.palette.as_deref().map(load_palette).transpose()?;
+        let (width, height) = match (config.resize, &input_pixels) {
+            (Some([width, height]), _) => (width, height),
+            (None, Some(input_pixels)) => (input_pixels.width, input_pixels.height),
+            (None, None) => (config.width, config.height),
+        };
+        ensure!(width > 0 && height > 0, "image dimensions must be non-zero, got {width}x{height}");
+        ensure!(!config.workgroup_size.contains(&0), "workgroup size must be non-zero");
+        config.check_push_constants()?;
+        ensure!(config.depth > 0, "depth must be non-zero");
+        ensure!(config.tile_size != Some(0), "tile size must be non-zero");
+        ensure!(config.threads > 0, "thread count must be non-zero");
+        check_queue_priority(config.queue_priority)?;
+        ensure!(
+            config.depth == 1 || config.shader.is_volume(),
+            "the {:?} shader renders flat images, --depth needs a volume shader",
+            config.shader,
+        );
+        ensure!(!(config.mipmaps && config.shader.is_volume()), "mipmaps can't be generated for volumes");
+        ensure!(
+            config.spirv.is_none() || config.glsl.is_none(),
+            "--spirv and --glsl both replace the shader, only one of them can be given"
+        );
+        ensure!(config.passes > 0, "pass count must be non-zero");
+        ensure!(config.iterations_per_submit != Some(0), "iterations per submit must be non-zero");
+        ensure!(
+            config.entry_point == "main" || config.spirv.is_some() || config.glsl.is_some(),
+            "the built-in shaders are all called main, --entry-point only picks the one of a --spirv or --glsl shader"
+        );
+        ensure!(
+            config.passes == 1 || config.shader.is_iterative(),
+            "the {:?} shader doesn't read its previous output, --passes needs an iterative shader",
+            config.shader,
+        );
+        ensure!(
+            !(config.shader.is_iterative() && config.input.is_some()),
+            "the {:?} shader reads its previous pass from binding 1 and can't take an --input image",
+            config.shader,
+        );
+        ensure!(
+            !(config.srgb && config.shader == ShaderKind::Normalmap),
+            "normal maps hold directions rather than colours and can't be encoded as --srgb"
+        );
+        ensure!(
+            !(config.srgb && config.pixel_format == PixelFormat::Rgba32f),
+            "--srgb needs an 8 or 16 bit format, float output stays linear"
+        );
+        ensure!(
+            !config.explicit_barriers
+                || !(config.secondary_command_buffers
+                    || config.clear_color.is_some()
+                    || config.mipmaps
+                    || config.download_mode == DownloadMode::Staged
+                    || config.passes > 1),
+            "--explicit-barriers only records a single dispatch and copy, it can't be combined with \
+             --secondary-command-buffers, --clear-color, --mipmaps, a staged download or --passes",
+        );
+
+        ensure!(
+            config.histogram.is_none() || !(config.shader.is_volume() || config.multi_gpu || config.explicit_barriers),
+            "--histogram only counts flat images rendered on one device, it can't be combined with volumes, \
+             --multi-gpu or --explicit-barriers",
+        );
+        // The pattern is compared byte for byte, so nothing may change the bytes the shader wrote
+        ensure!(
+            !config.verify
+                || !(config.shader.is_volume()
+                    || config.explicit_barriers
+                    || config.srgb
+                    || config.premultiply_alpha
+                    || config.swizzle != Swizzle::Rgba),
+            "--verify checks the bytes of the last row of a flat image, it can't be combined with volumes, \
+             --explicit-barriers, --srgb, --premultiply-alpha or --swizzle",
+        );
+        // The render writes the ping-pong image for the palette pass to read, which nothing clears
+        ensure!(
+            config.palette.is_none()
+                || !(config.shader.is_volume() || config.explicit_barriers || config.clear_color.is_some()),
+            "--palette recolours flat images, it can't be combined with volumes, --explicit-barriers or --clear-color",
+        );
+        ensure!(
+            config.dump_iterations.is_none()
+                || (config.shader == ShaderKind::Mandelbrot && config.spirv.is_none() && config.glsl.is_none()),
+            "--dump-iterations needs the built-in Mandelbrot shader, the others don't count iterations"
+        );
+        ensure!(
+            config.dump_iterations.is_none() || !(config.multi_gpu || config.explicit_barriers || config.debug_printf),
+            "--dump-iterations can't be combined with --multi-gpu, --explicit-barriers or --debug-printf",
+        );
+        ensure!(
+            config.dump_iterations.is_none() || cfg!(feature = "image-output"),
+            "--dump-iterations writes a PNG, build with the image-output feature"
+        );
+
+        let start = Instant::now();
+        let library = VulkanLibrary::new().context("no local Vulkan library/DLL")?;
+        let instance = create_instance(library, config.validation, config.debug_printf)?;
+
+        // Kept alive with the setup so validation messages keep being reported
+        let debug_messenger = setup_debug_messenger(&instance)?;
+        timings.instance += start.elapsed();
+
+        Ok(Self {
+            _debug_messenger: debug_messenger,
+            instance,
+            input_pixels,
+            palette,
+            width,
+            height,
+        })
+    }
+}
+
+/// Image, buffers and descriptor sets that one tile at a time is rendered and read back through.
+struct TileSlot {
+    set: Arc<DescriptorSet>,
+    /// Set of the passes that write the second image, reading `image` in iterative shaders
+    pong_set: Option<Arc<DescriptorSet>>,
+    image: Arc<Image>,
+    buf: Subbuffer<[u8]>,
+    staging_buf: Option<Subbuffer<[u8]>>,
+    /// Counters of the shader, if it declares any, reset before every frame
+    counters_buf: Option<Subbuffer<Counters>>,
+    /// Set the histogram is counted with, reading `image` and writing `histogram_buf`
+    histogram_set: Option<Arc<DescriptorSet>>,
+    histogram_buf: Option<Subbuffer<[u32; HISTOGRAM_BINS]>>,
+    /// Set the checksum pattern is written into `image` with
+    verify_set: Option<Arc<DescriptorSet>>,
+    /// Set the palette pass reads the ping-pong image the render wrote and writes `image` with
+    palette_set: Option<Arc<DescriptorSet>>,
+    /// `R32_UINT` image the shader writes iteration counts to, and the buffer they are copied into
+    iterations: Option<(Arc<Image>, Subbuffer<[u32]>)>,
+}
+
+/// Fence a frame of [`Renderer::dispatch_frames`] signals once it completes, and the semaphore its
+/// copy on the transfer queue waits on.
+struct FrameSync {
+    fence: Fence,
+    copy_semaphore: Option<Semaphore>,
+}
+
+/// A frame of [`Renderer::dispatch_frames`] that was submitted and not read back yet.
+struct FrameInFlight {
+    fence: Fence,
+    /// Everything the submission uses until the fence signals
+    in_use: (Option<Semaphore>, Arc<PrimaryAutoCommandBuffer>, Option<Arc<PrimaryAutoCommandBuffer>>),
+    slot_index: usize,
+    push_constants: PushConstants,
+}
+
+/// GPU objects of one device that are created once and reused by every frame of a render.
+///
+/// The device, allocators, pipeline, descriptor set and buffers are only allocated when the
+/// renderer is created, every frame just records and submits new command buffers. Each renderer
+/// renders the band of rows `band_y..band_y + band_height` of the full image.
+pub struct Renderer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    /// Queue of a dedicated transfer family the copy is submitted to, if the device has one
+    transfer_queue: Option<Arc<Queue>>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    compute_pipeline: Arc<ComputePipeline>,
+    /// One slot for every tile or frame that is rendered at the same time, the first one for single tile renders
+    slots: Vec<TileSlot>,
+    /// Fence and semaphore of every slot when frames are kept in flight, see [`Renderer::dispatch_frames`]
+    frame_sync: Mutex<Vec<FrameSync>>,
+    /// Pipeline that counts the luminance of each tile after it is rendered
+    histogram_pipeline: Option<Arc<ComputePipeline>>,
+    /// File the histogram of the last frame is written to
+    histogram_csv: Option<PathBuf>,
+    /// Pipeline that writes the checksum pattern into the last row of the image
+    verify_pipeline: Option<Arc<ComputePipeline>>,
+    /// Pipeline that recolours each tile through the palette after it is rendered
+    palette_pipeline: Option<Arc<ComputePipeline>>,
+    /// File the iteration counts of the last frame are written to
+    iterations_png: Option<PathBuf>,
+    timestamp_query: Option<TimestampQuery>,
+    copy_timestamp_query: Option<TimestampQuery>,
+    width: u32,
+    height: u32,
+    band_y: u32,
+    band_height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    workgroup_size: [u32; 2],
+    pixel_format: PixelFormat,
+    /// Format the image was created with, which may differ from `pixel_format.format()`
+    storage_format: Format,
+    swizzle: Swizzle,
+    premultiply_alpha: bool,
+    /// Multiple of bytes that rows in the readback buffer are padded to
+    row_alignment: DeviceSize,
+    timeout: Duration,
+    clear_color: Option<[f32; 4]>,
+    mip_levels: u32,
+    depth: u32,
+    secondary_command_buffers: bool,
+    passes: u32,
+    /// Largest number of passes submitted at once, `passes` when they all go into one submission
+    iterations_per_submit: u32,
+    explicit_barriers: bool,
+    timings: Mutex<Timings>,
+    _setup: Arc<RenderSetup>,
+    resource_report: Option<ResourceReport>,
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self)
+    {
+        if let Some(resource_report) = &self.resource_report {
+            resource_report.wait_idle();
+        }
+    }
+}
+
+impl Renderer {
+    /// Creates the instance and every GPU object that `config` needs on the best available
+    /// device, or on `config.device_index` or `config.device_uuid` when one is set.
+    pub fn new(config: &RenderConfig) -> Result<Self>
+    {
+        Self::with_frames_in_flight(config, 1)
+    }
+
+    /// Like [`Renderer::new`], with a slot, a fence and, for a copy on a dedicated transfer queue, a
+    /// semaphore for each of `frames_in_flight` frames that [`dispatch_frames`](Self::dispatch_frames)
+    /// keeps on the GPU at once.
+    ///
+    /// Tiled renders only get the slots of `config.threads`.
+    pub fn with_frames_in_flight(config: &RenderConfig, frames_in_flight: usize) -> Result<Self>
+    {
+        let mut timings = Timings::default();
+        let setup = Arc::new(RenderSetup::new(config, &mut timings)?);
+        let physical_device = pick_physical_device(
+            &setup.instance,
+            config.device_index,
+            config.device_uuid,
+            config.allow_software,
+        )?;
+
+        Self::with_device(config, &setup, physical_device, 0..setup.height, frames_in_flight, &mut timings)
+    }
+
+    fn with_device(
+        config: &RenderConfig,
+        setup: &Arc<RenderSetup>,
+        physical_device: Arc<PhysicalDevice>,
+        rows: Range<u32>,
+        frames_in_flight: usize,
+        timings: &mut Timings,
+    ) -> Result<Self>
+    {
+        let RenderConfig { workgroup_size, pixel_format, .. } = *config;
+        let RenderSetup { width, height, .. } = **setup;
+        let input_pixels = setup.input_pixels.as_ref();
+        let band_y = rows.start;
+        let band_height = rows.len() as u32;
+        let depth = config.depth;
+
+        let image_type = if config.shader.is_volume() {
+            let max_image_dimension = physical_device.properties().max_image_dimension3_d;
+            ensure!(
+                width <= max_image_dimension && band_height <= max_image_dimension && depth <= max_image_dimension,
+                "{width}x{band_height}x{depth} volume exceeds the device's max_image_dimension3_d of {max_image_dimension}"
+            );
+
+            ImageType::Dim3d
+        } else {
+            ImageType::Dim2d
+        };
+
+        // Renders larger than the device allows, or than `tile_size`, are split into tiles that
+        // reuse the images of the slots
+        let max_image_dimension = physical_device.properties().max_image_dimension2_d;
+        let max_tile_size = config.tile_size.map_or(max_image_dimension, |tile_size| tile_size.min(max_image_dimension));
+        let tile_width = width.min(max_tile_size);
+        let tile_height = band_height.min(max_tile_size);
+        if width > max_image_dimension || band_height > max_image_dimension {
+            ensure!(
+                input_pixels.is_none(),
+                "input image of {width}x{height} exceeds the device's max_image_dimension2_d of {max_image_dimension}"
+            );
+            info!(
+                "{width}x{band_height} exceeds the device's max_image_dimension2_d of {max_image_dimension}, rendering a {}x{} grid of tiles",
+                div_ceil(width, tile_width),
+                div_ceil(band_height, tile_height),
+            );
+        } else if tile_width < width || tile_height < band_height {
+            info!(
+                "Rendering {width}x{band_height} as a {}x{} grid of {tile_width}x{tile_height} tiles",
+                div_ceil(width, tile_width),
+                div_ceil(band_height, tile_height),
+            );
+        }
+
+        // Each thread renders its share of the tiles through a slot of its own, and each frame in
+        // flight of an untiled render
+        let tile_count = div_ceil(width, tile_width) * div_ceil(band_height, tile_height);
+        let slot_count = match tile_count {
+            1 => frames_in_flight.max(1),
+            _ => config.threads.min(tile_count as usize),
+        };
+        if frames_in_flight > 1 && tile_count == 1 {
+            info!("Keeping {slot_count} frames in flight");
+        } else if slot_count > 1 {
+            info!("Rendering {slot_count} tiles at a time on separate threads");
+        }
+
+        ensure!(
+            !config.shader.is_iterative() || (tile_width == width && tile_height == band_height),
+            "iterative shaders read neighbouring pixels of the previous pass and can't be tiled"
+        );
+
+        ensure!(
+            config.dump_iterations.is_none() || (tile_width == width && tile_height == band_height),
+            "--dump-iterations only reads back untiled renders"
+        );
+
+        let mip_levels = if config.mipmaps {
+            ensure!(tile_width == width && tile_height == band_height, "mipmaps can't be generated for tiled renders");
+            max_mip_levels([width, band_height, 1])
+        } else {
+            1
+        };
+
+        check_workgroup_size(&physical_device, workgroup_size)?;
+        log_subgroup_properties(&physical_device);
+        if config.require_subgroup_ops {
+            check_subgroup_support(&physical_device)?;
+        }
+
+        let (queue_family_index, dedicated) = find_compute_queue_family(&physical_device)?;
+        if dedicated {
+            info!("Using dedicated compute queue family {queue_family_index}");
+        } else {
+            info!("Using combined graphics and compute queue family {queue_family_index}");
+        }
+
+        // The hand-written barriers only cover a copy on the compute queue
+        let transfer_queue_family_index = match config.explicit_barriers {
+            true => None,
+            false => find_transfer_queue_family(&physical_device),
+        };
+        match transfer_queue_family_index {
+            Some(transfer_queue_family_index) => {
+                info!("Copying on dedicated transfer queue family {transfer_queue_family_index}, after a semaphore")
+            }
+            None if config.explicit_barriers => info!("Copying on the compute queue behind explicit barriers"),
+            None => info!("No dedicated transfer queue family, copying on the compute queue"),
+        }
+
+        // Both queues access the image and the buffers, concurrent sharing saves transferring the
+        // ownership of them back and forth
+        let sharing = match transfer_queue_family_index {
+            Some(transfer_queue_family_index) => {
+                Sharing::Concurrent([queue_family_index, transfer_queue_family_index].into_iter().collect())
+            }
+            None => Sharing::Exclusive,
+        };
+
+        // The histogram and the palette pass read the output image back without knowing its format either
+        let enabled_features = DeviceFeatures {
+            shader_storage_image_read_without_format: config.histogram.is_some() || config.palette.is_some(),
+            ..DeviceFeatures::empty()
+        }
+        .union(&config.shader.required_features());
+        let mut enabled_extensions = config.shader.required_extensions();
+        check_device_support(&physical_device, &enabled_features, &enabled_extensions)?;
+
+        enable_portability_subset(&physical_device, &mut enabled_extensions);
+
+        // The instance only enables validation features when debug printf was asked for and the
+        // layer can do it. Printing shaders need `SPV_KHR_non_semantic_info`, which is core from
+        // Vulkan 1.3 on.
+        let mut debug_printf = setup.instance.enabled_extensions().ext_validation_features;
+        if debug_printf {
+            if physical_device.supported_extensions().khr_shader_non_semantic_info {
+                enabled_extensions.khr_shader_non_semantic_info = true;
+            } else if physical_device.api_version() < Version::V1_3 {
+                warn!(
+                    "{} doesn't support VK_KHR_shader_non_semantic_info, continuing without shader printf",
+                    physical_device.properties().device_name,
+                );
+                debug_printf = false;
+            }
+        }
+        // Runtime loaded shaders stand in for the built-in one
+        let runtime_shader = config.spirv.as_deref().or(config.glsl.as_deref());
+        if debug_printf && runtime_shader.is_none() && !config.shader.has_debug_printf() {
+            warn!("the {:?} shader doesn't print anything, --debug-printf only logs its validation messages", config.shader);
+        }
+
+        let mut image_usage = ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC;
+        if config.clear_color.is_some() || mip_levels > 1 {
+            image_usage |= ImageUsage::TRANSFER_DST;
+        }
+
+        // Stores to an sRGB image are encoded by the hardware, but few devices support sRGB storage
+        // images, so elsewhere the shader writes the encoded colour to a UNORM image itself
+        let srgb_storage = config.srgb
+            && pixel_format == PixelFormat::Rgba8
+            && check_storage_support(&physical_device, Format::R8G8B8A8_SRGB, image_type, image_usage).is_ok();
+        if config.srgb && !srgb_storage {
+            info!(
+                "{} has no {pixel_format:?} sRGB storage images, encoding sRGB in the shader instead",
+                physical_device.properties().device_name,
+            );
+        }
+        let format = if srgb_storage {
+            Format::R8G8B8A8_SRGB
+        } else {
+            select_storage_format(&physical_device, pixel_format, image_type, image_usage)?
+        };
+        if mip_levels > 1 {
+            check_blit_support(&physical_device, format)?;
+        }
+
+        // Mip levels are stored one after the other, largest first, with rows padded to the
+        // alignment the device copies fastest
+        let row_alignment = physical_device.properties().optimal_buffer_copy_row_pitch_alignment.as_devicesize();
+        let buffer_len = pixel_format
+            .buffer_size([tile_width, tile_height, depth], mip_levels, row_alignment)
+            .with_context(|| format!("a {tile_width}x{tile_height}x{depth} {pixel_format:?} image is too large to read back"))?;
+        let buffer_len_usize = usize::try_from(buffer_len)
+            .context("the readback buffer doesn't fit in the address space")?;
+
+        // Checked before creating the device, so a render that can't fit fails here instead of
+        // running out of memory deep in the driver. The image takes about as much device memory
+        // as the buffer it is read back into, and every slot has its own of both, as well as of
+        // the ping-pong image and the iteration counts and their buffer.
+        log_memory_heaps(&physical_device);
+        let images_per_slot: DeviceSize = match config.shader.is_iterative() || config.palette.is_some() {
+            true => 2,
+            false => 1,
+        };
+        let staging_buffers: DeviceSize = match config.download_mode {
+            DownloadMode::Direct => 0,
+            DownloadMode::Staged => 1,
+        };
+        let iterations_len = match config.dump_iterations {
+            Some(_) => tile_width as DeviceSize * tile_height as DeviceSize * size_of::<u32>() as DeviceSize,
+            None => 0,
+        };
+        let device_local_len = buffer_len
+            .checked_mul(images_per_slot + staging_buffers)
+            .and_then(|len| len.checked_add(iterations_len))
+            .and_then(|len| len.checked_mul(slot_count as DeviceSize))
+            .context("the images and staging buffer are too large")?;
+        let host_visible_len = buffer_len
+            .checked_add(iterations_len)
+            .and_then(|len| len.checked_mul(slot_count as DeviceSize))
+            .context("the readback buffers are too large")?;
+        check_heap_size(&physical_device, device_local_len, MemoryPropertyFlags::DEVICE_LOCAL)
+            .context("the image is too large for the device memory")?;
+        check_heap_size(&physical_device, host_visible_len, MemoryPropertyFlags::HOST_VISIBLE)
+            .context("the readback buffer is too large for the host-visible memory")?;
+
+        let device_start = Instant::now();
+        info!("Requesting queue priority {}", config.queue_priority);
+        let (device, mut queues) = Device::new(
+            physical_device,
+            DeviceCreateInfo {
+                queue_create_infos: [Some(queue_family_index), transfer_queue_family_index]
+                    .into_iter()
+                    .flatten()
+                    .map(|queue_family_index| QueueCreateInfo {
+                        queue_family_index,
+                        queues: vec![config.queue_priority],
+                        ..Default::default()
+                    })
+                    .collect(),
+                enabled_features,
+                enabled_extensions,
+                ..Default::default()
+            },
+        )
+        .context("failed to create device")?;
+
+        let queue = queues.next().context("device returned no queues")?;
+        let transfer_queue = match transfer_queue_family_index {
+            Some(_) => Some(queues.next().context("device returned no transfer queue")?),
+            None => None,
+        };
+        timings.device += device_start.elapsed();
+
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(
+            device.clone()
+        ));
+
+        let pipeline_start = Instant::now();
+        let shader = match (&config.spirv, &config.glsl) {
+            (Some(path), _) => load_spirv(device.clone(), path, &config.entry_point)?,
+            (None, Some(path)) => compile_glsl(device.clone(), path, debug_printf, &config.entry_point)?,
+            (None, None) if config.dump_iterations.is_some() => load_mandelbrot_iterations_shader(device.clone())
+                .context("failed to load the Mandelbrot shader that writes iteration counts")?,
+            (None, None) => config.shader.load(device.clone(), debug_printf).context("failed to load a compute shader")?,
+        };
+
+        // Constant IDs 0 and 1 are `local_size_x_id` and `local_size_y_id` in the shader, 2 and 3
+        // are `SRGB_ENCODE` and `SRGB_DECODE_INPUT`
+        let specialization_info = [
+            (0, SpecializationConstant::U32(workgroup_size[0])),
+            (1, SpecializationConstant::U32(workgroup_size[1])),
+            (2, SpecializationConstant::Bool(config.srgb && !srgb_storage)),
+            (3, SpecializationConstant::Bool(config.srgb)),
+        ]
+        .into_iter()
+        .collect();
+        let compute_shader = shader
+            .specialize(specialization_info)
+            .context("failed to specialize the compute shader")?
+            .entry_point(&config.entry_point)
+            .with_context(|| format!("compute shader has no {:?} entry point", config.entry_point))?;
+        if let Some(path) = runtime_shader {
+            check_entry_point(&compute_shader, format, path)?;
+        }
+        let stage = PipelineShaderStageCreateInfo::new(compute_shader);
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                .into_pipeline_layout_create_info(device.clone())
+                .context("failed to create PipelineLayoutCreateInfo")?
+        )
+        .context("failed to create a new PipelineLayout")?;
+
+        let pipeline_cache = config
+            .pipeline_cache
+            .as_deref()
+            .map(|path| load_pipeline_cache(&device, path))
+            .transpose()?;
+
+        let compute_pipeline = ComputePipeline::new(
+            device.clone(),
+            pipeline_cache.clone(),
+            ComputePipelineCreateInfo::stage_layout(stage, layout)
+        )
+        .context("failed to create a new ComputePipeline")?;
+
+        let histogram_pipeline = match config.histogram {
+            Some(_) => Some(create_histogram_pipeline(&device, workgroup_size, pipeline_cache.clone())?),
+            None => None,
+        };
+        let verify_pipeline =
+            if config.verify { Some(create_verify_pipeline(&device, pipeline_cache.clone())?) } else { None };
+        let palette_pipeline = match config.palette {
+            Some(_) => Some(create_palette_pipeline(&device, workgroup_size, pipeline_cache.clone())?),
+            None => None,
+        };
+
+        // Saved once every pipeline of the render is in it
+        if let (Some(pipeline_cache), Some(path)) = (&pipeline_cache, &config.pipeline_cache)
+            && let Err(error) = save_pipeline_cache(pipeline_cache, path)
+        {
+            warn!("{error:#}");
+        }
+        timings.pipeline += pipeline_start.elapsed();
+
+        // Every set is allocated once here and reused for every frame and pass: one per slot, and
+        // a second one for the ping-pong passes of iterative shaders and the palette. A pool of
+        // exactly that many sets holds all of them, where the default of 32 would waste most of its
+        // sets on a single slot and need a second pool past 32 threads. The layouts of the histogram,
+        // the checksum pattern and the palette get pools of the same size, of which they use one set
+        // per slot.
+        let sets_per_slot = if config.shader.is_iterative() || config.palette.is_some() { 2 } else { 1 };
+        let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
+            device.clone(),
+            StandardDescriptorSetAllocatorCreateInfo {
+                set_count: slot_count * sets_per_slot,
+                ..Default::default()
+            },
+        ));
+
+        let command_buffer_allocator = Arc::new(
+            StandardCommandBufferAllocator::new(
+                device.clone(),
+                StandardCommandBufferAllocatorCreateInfo::default(),
+        ));
+
+        let image_create_info = ImageCreateInfo {
+            image_type,
+            format,
+            extent: [tile_width, tile_height, depth],
+            mip_levels,
+            usage: image_usage,
+            sharing: sharing.clone(),
+            ..Default::default()
+        };
+        let image_allocation_info = AllocationCreateInfo {
+            memory_type_filter: config.memory_type.memory_type_filter(device.physical_device()),
+            ..Default::default()
+        };
+        check_image_memory_type(&device, &image_create_info, image_allocation_info.memory_type_filter)
+            .with_context(|| format!("the {:?} memory type doesn't fit the storage image", config.memory_type))?;
+
+        let layout = compute_pipeline
+            .layout()
+            .set_layouts()
+            .first()
+            .context("failed to return a layout")?;
+
+        // The parameters and the input image are only read, so every slot binds the same ones
+        let mut shared_writes = Vec::new();
+
+        if layout.bindings().contains_key(&2) {
+            let params = Params { palette: DEFAULT_PALETTE };
+            let max_uniform_buffer_range = device.physical_device().properties().max_uniform_buffer_range;
+            ensure!(
+                size_of::<Params>() as u64 <= max_uniform_buffer_range as u64,
+                "Params of {} bytes exceed the device's max_uniform_buffer_range of {max_uniform_buffer_range}",
+                size_of::<Params>(),
+            );
+
+            // Because of the UNIFORM_BUFFER usage vulkano aligns the buffer to
+            // min_uniform_buffer_offset_alignment, so it can be bound at offset 0 as is
+            let params_buffer = Buffer::from_data(
+                memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::UNIFORM_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                params,
+            )
+            .memory_context("failed to create a uniform buffer")?;
+            shared_writes.push(WriteDescriptorSet::buffer(2, params_buffer));
+        }
+
+        if let Some(input_pixels) = input_pixels {
+            if layout.bindings().contains_key(&1) {
+                let input_image = upload_input_image(
+                    &memory_allocator,
+                    &command_buffer_allocator,
+                    &queue,
+                    input_pixels,
+                    config.timeout,
+                )?;
+                let input_view = ImageView::new_default(input_image)
+                    .context("failed to create an input ImageView")?;
+                shared_writes.push(WriteDescriptorSet::image_view(1, input_view));
+            } else {
+                warn!("the compute shader doesn't read an input image, the input only sets the output size");
+            }
+        }
+
+        let shader_name = match runtime_shader {
+            Some(path) => path.display().to_string(),
+            None => format!("the {:?} shader", config.shader),
+        };
+
+        // The palette is only read, so every slot binds the same buffer
+        let palette_buf = match &setup.palette {
+            Some(palette) => Some(
+                Buffer::from_data(
+                    memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                        ..Default::default()
+                    },
+                    *palette,
+                )
+                .memory_context("failed to create the palette buffer")?,
+            ),
+            None => None,
+        };
+
+        let mut slots = Vec::with_capacity(slot_count);
+        let mut buffer_time = Duration::ZERO;
+        for slot_index in 0..slot_count {
+            let image = Image::new(memory_allocator.clone(), image_create_info.clone(), image_allocation_info.clone())
+                .memory_context("failed to create an image")?;
+            if slot_index == 0 {
+                log_image_memory(&image);
+            }
+
+            // The shader only writes the first mip level, the others are blitted from it
+            let mut view_create_info = ImageViewCreateInfo::from_image(&image);
+            view_create_info.subresource_range.mip_levels = 0..1;
+            let view = ImageView::new(image.clone(), view_create_info)
+                .context("failed to create an ImageView")?;
+
+            // Iterative shaders alternate between writing `image` and a second image of the same
+            // kind, reading the other one at binding 1. With a palette the render ends in the second
+            // image, which the palette pass reads to write `image`.
+            let pong_view = if config.shader.is_iterative() || config.palette.is_some() {
+                let pong_image = Image::new(memory_allocator.clone(), image_create_info.clone(), image_allocation_info.clone())
+                    .memory_context("failed to create the ping-pong image")?;
+                let mut pong_view_create_info = ImageViewCreateInfo::from_image(&pong_image);
+                pong_view_create_info.subresource_range.mip_levels = 0..1;
+                let pong_view = ImageView::new(pong_image, pong_view_create_info)
+                    .context("failed to create the ping-pong ImageView")?;
+
+                Some(pong_view)
+            } else {
+                None
+            };
+
+            let mut descriptor_writes = vec![WriteDescriptorSet::image_view(0, view.clone())];
+
+            // The iteration counts are a second output of the same dispatch, only ever used on
+            // the compute queue
+            let iterations = if config.dump_iterations.is_some() {
+                let iterations_image = Image::new(
+                    memory_allocator.clone(),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        format: Format::R32_UINT,
+                        extent: [tile_width, tile_height, 1],
+                        usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )
+                .memory_context("failed to create the iteration count image")?;
+                let iterations_view = ImageView::new_default(iterations_image.clone())
+                    .context("failed to create the iteration count ImageView")?;
+                descriptor_writes.push(WriteDescriptorSet::image_view(4, iterations_view));
+
+                let iterations_buf = Buffer::new_slice::<u32>(
+                    memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::TRANSFER_DST,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                        ..Default::default()
+                    },
+                    u64::from(tile_width) * u64::from(tile_height),
+                )
+                .memory_context("failed to create the iteration count buffer")?;
+
+                Some((iterations_image, iterations_buf))
+            } else {
+                None
+            };
+
+            // Storage buffer atomics only need a device feature in the vertex and fragment stages,
+            // compute shaders can always use them. Every slot counts its own tiles, so slots that
+            // render at the same time don't write the same buffer.
+            let counters_buf = if layout.bindings().contains_key(&3) {
+                let counters_buf = Buffer::from_data(
+                    memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                        ..Default::default()
+                    },
+                    Counters::default(),
+                )
+                .memory_context("failed to create a storage buffer")?;
+                descriptor_writes.push(WriteDescriptorSet::buffer(3, counters_buf.clone()));
+
+                Some(counters_buf)
+            } else {
+                None
+            };
+
+            descriptor_writes.extend(shared_writes.iter().cloned());
+
+            let pong_set = match &pong_view {
+                Some(pong_view) => {
+                    let mut pong_writes = descriptor_writes.clone();
+                    pong_writes[0] = WriteDescriptorSet::image_view(0, pong_view.clone());
+                    if config.shader.is_iterative() {
+                        pong_writes.push(WriteDescriptorSet::image_view(1, view.clone()));
+                        descriptor_writes.push(WriteDescriptorSet::image_view(1, pong_view.clone()));
+                    }
+
+                    let pong_set = DescriptorSet::new(descriptor_set_allocator.clone(), layout.clone(), pong_writes, [])
+                        .context("failed to create the ping-pong set")?;
+                    Some(pong_set)
+                }
+                None => None,
+            };
+
+            check_descriptor_writes(layout, &descriptor_writes, &shader_name)?;
+
+            let set = DescriptorSet::new(
+                descriptor_set_allocator.clone(),
+                layout.clone(),
+                descriptor_writes,
+                []
+            ).context("failed to create a set")?;
+
+            let buffer_start = Instant::now();
+            let buffer_create_info = BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                sharing: sharing.clone(),
+                ..Default::default()
+            };
+            let buffer_allocation_info = AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            };
+            // The copy overwrites every byte before the buffer is read, so filling it with zeros on
+            // the host first only costs time, which adds up for large images
+            let buf = if config.clear_buffer {
+                Buffer::from_iter(
+                    memory_allocator.clone(),
+                    buffer_create_info,
+                    buffer_allocation_info,
+                    (0..buffer_len_usize).map(|_| 0u8),
+                )
+            } else {
+                Buffer::new_slice::<u8>(memory_allocator.clone(), buffer_create_info, buffer_allocation_info, buffer_len)
+            }
+            .memory_context("failed to create the readback buffer")?;
+            buffer_time += buffer_start.elapsed();
+
+            let staging_buf = match config.download_mode {
+                DownloadMode::Direct => None,
+                DownloadMode::Staged => Some(
+                    Buffer::new_slice::<u8>(
+                        memory_allocator.clone(),
+                        BufferCreateInfo {
+                            usage: BufferUsage::TRANSFER_SRC | BufferUsage::TRANSFER_DST,
+                            sharing: sharing.clone(),
+                            ..Default::default()
+                        },
+                        AllocationCreateInfo {
+                            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                            ..Default::default()
+                        },
+                        buffer_len,
+                    )
+                    .memory_context("failed to create a staging buffer")?,
+                ),
+            };
+
+            let (histogram_set, histogram_buf) = match &histogram_pipeline {
+                Some(histogram_pipeline) => {
+                    let histogram_buf = Buffer::from_data(
+                        memory_allocator.clone(),
+                        BufferCreateInfo {
+                            usage: BufferUsage::STORAGE_BUFFER,
+                            ..Default::default()
+                        },
+                        AllocationCreateInfo {
+                            memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                            ..Default::default()
+                        },
+                        [0u32; HISTOGRAM_BINS],
+                    )
+                    .memory_context("failed to create the histogram buffer")?;
+                    let histogram_layout = histogram_pipeline
+                        .layout()
+                        .set_layouts()
+                        .first()
+                        .context("failed to return the histogram layout")?;
+                    let histogram_set = DescriptorSet::new(
+                        descriptor_set_allocator.clone(),
+                        histogram_layout.clone(),
+                        [
+                            WriteDescriptorSet::image_view(0, view.clone()),
+                            WriteDescriptorSet::buffer(1, histogram_buf.clone()),
+                        ],
+                        [],
+                    )
+                    .context("failed to create the histogram set")?;
+
+                    (Some(histogram_set), Some(histogram_buf))
+                }
+                None => (None, None),
+            };
+
+            let verify_set = match &verify_pipeline {
+                Some(verify_pipeline) => {
+                    let verify_layout = verify_pipeline
+                        .layout()
+                        .set_layouts()
+                        .first()
+                        .context("failed to return the verify layout")?;
+                    let verify_set = DescriptorSet::new(
+                        descriptor_set_allocator.clone(),
+                        verify_layout.clone(),
+                        [WriteDescriptorSet::image_view(0, view.clone())],
+                        [],
+                    )
+                    .context("failed to create the verify set")?;
+
+                    Some(verify_set)
+                }
+                None => None,
+            };
+
+            let palette_set = match (&palette_pipeline, &palette_buf, &pong_view) {
+                (Some(palette_pipeline), Some(palette_buf), Some(pong_view)) => {
+                    let palette_layout = palette_pipeline
+                        .layout()
+                        .set_layouts()
+                        .first()
+                        .context("failed to return the palette layout")?;
+                    let palette_set = DescriptorSet::new(
+                        descriptor_set_allocator.clone(),
+                        palette_layout.clone(),
+                        [
+                            WriteDescriptorSet::image_view(0, view.clone()),
+                            WriteDescriptorSet::image_view(1, pong_view.clone()),
+                            WriteDescriptorSet::buffer(2, palette_buf.clone()),
+                        ],
+                        [],
+                    )
+                    .context("failed to create the palette set")?;
+
+                    Some(palette_set)
+                }
+                _ => None,
+            };
+
+            slots.push(TileSlot {
+                set,
+                pong_set,
+                image,
+                buf,
+                staging_buf,
+                counters_buf,
+                histogram_set,
+                histogram_buf,
+                verify_set,
+                palette_set,
+                iterations,
+            });
+        }
+        info!(
+            "Created {slot_count} readback buffer(s) of {buffer_len} bytes in {buffer_time:.2?}, {}",
+            if config.clear_buffer { "filled with zeros" } else { "left uninitialized" },
+        );
+
+        // Created once and reset after every frame, like the slots they belong to
+        let mut frame_sync = Vec::new();
+        if frames_in_flight > 1 && tile_count == 1 {
+            for _ in 0..slot_count {
+                let copy_semaphore = match &transfer_queue {
+                    Some(_) => Some(
+                        Semaphore::new(device.clone(), SemaphoreCreateInfo::default())
+                            .context("failed to create a semaphore")?,
+                    ),
+                    None => None,
+                };
+                frame_sync.push(FrameSync {
+                    fence: Fence::new(device.clone(), FenceCreateInfo::default()).context("failed to create a fence")?,
+                    copy_semaphore,
+                });
+            }
+        }
+
+        // The timestamps are written through the command buffer builder, which the explicit
+        // barriers path doesn't record with, tiles that render at the same time would overwrite
+        // each other's queries, and the last of several batches of passes would only time itself
+        let batched = config.iterations_per_submit.is_some_and(|iterations| iterations < config.passes);
+        let (timestamp_query, copy_timestamp_query) = if config.explicit_barriers || slot_count > 1 || batched {
+            (None, None)
+        } else {
+            let copy_queue = transfer_queue.as_ref().unwrap_or(&queue);
+            (
+                TimestampQuery::new(&device, queue.queue_family_index())?,
+                TimestampQuery::new(&device, copy_queue.queue_family_index())?,
+            )
+        };
+
+        Ok(Self {
+            device,
+            queue,
+            transfer_queue,
+            command_buffer_allocator,
+            compute_pipeline,
+            slots,
+            frame_sync: Mutex::new(frame_sync),
+            histogram_pipeline,
+            histogram_csv: config.histogram.clone(),
+            verify_pipeline,
+            palette_pipeline,
+            iterations_png: config.dump_iterations.clone(),
+            timestamp_query,
+            copy_timestamp_query,
+            width,
+            height,
+            band_y,
+            band_height,
+            tile_width,
+            tile_height,
+            workgroup_size,
+            pixel_format,
+            storage_format: format,
+            swizzle: config.swizzle,
+            premultiply_alpha: config.premultiply_alpha,
+            row_alignment,
+            timeout: config.timeout,
+            clear_color: config.clear_color,
+            mip_levels,
+            depth,
+            secondary_command_buffers: config.secondary_command_buffers,
+            passes: config.passes,
+            iterations_per_submit: config.iterations_per_submit.unwrap_or(config.passes),
+            explicit_barriers: config.explicit_barriers,
+            timings: Mutex::new(*timings),
+            _setup: setup.clone(),
+            resource_report: config.debug_resources.then(|| ResourceReport::new(memory_allocator)),
+        })
+
+    }
+
+    /// Renders one frame with `push_constants` and returns it as an image.
+    ///
+    /// Only the command buffers are recorded again, everything else was allocated by [`Renderer::new`].
+    /// The histogram and iteration counts are written after the frame.
+    pub fn dispatch_frame(&self, push_constants: PushConstants) -> Result<RenderOutput>
+    {
+        let image = self.render_timed_frame(push_constants)?;
+        self.write_frame_outputs()?;
+
+        Ok(image)
+    }
+
+    /// Like [`dispatch_frame`](Self::dispatch_frame), without writing the histogram and iteration
+    /// counts.
+    fn render_timed_frame(&self, push_constants: PushConstants) -> Result<RenderOutput>
+    {
+        let mut frame_timings = Timings::default();
+        let image = block_on(self.render_frame(push_constants, &mut |_| {}, &mut frame_timings))?;
+        *self.timings.lock().unwrap_or_else(PoisonError::into_inner) += frame_timings;
+
+        Ok(image)
+    }
+
+    /// Submits a frame with `push_constants` and returns right away with the fence it signals, so
+    /// the caller can poll for completion from its own event loop.
+    ///
+    /// Only renders that fit in a single tile can be submitted this way. Once
+    /// [`FenceHandle::wait`] has returned, [`read_frame`](Self::read_frame) returns the frame. The
+    /// frame is rendered into the renderer's own image and buffer, so the renderer has to outlive
+    /// the handle, and another frame must not be submitted before this one has been read.
+    pub fn submit_and_get_fence(&self, push_constants: PushConstants) -> Result<FenceHandle>
+    {
+        let Self { width, height, band_y, band_height, tile_width, tile_height, .. } = *self;
+        ensure!(
+            tile_width == width && tile_height == band_height,
+            "a tiled render needs a submission for every tile and can't be submitted at once"
+        );
+        ensure!(!self.explicit_barriers, "frames with explicit barriers can only be rendered with dispatch_frame");
+        ensure!(
+            self.last_batch_start() == 0,
+            "passes split into several submissions are waited for in between and can't be submitted at once"
+        );
+
+        let slot = &self.slots[0];
+        if let Some(counters_buf) = &slot.counters_buf {
+            *counters_buf.write().context("failed to reset the counters")? = Counters::default();
+        }
+
+        let future = self.submit_tile(slot, PushConstants {
+            offset: [0, band_y],
+            size: [width, height],
+            ..push_constants
+        })?;
+
+        Ok(FenceHandle::new(future))
+    }
+
+    /// Reads back the frame of the last [`submit_and_get_fence`](Self::submit_and_get_fence)
+    /// after its [`FenceHandle::wait`] has returned.
+    ///
+    /// Fallback formats are converted in place, so each submitted frame can only be read once.
+    pub fn read_frame(&self) -> Result<RenderOutput>
+    {
+        let pixels = self.read_slot(&self.slots[0])?;
+
+        self.pixel_format.to_output(self.width, self.band_height, pixels)
+    }
+
+    /// Reads back the untiled frame the last submission rendered into `slot`.
+    fn read_slot(&self, slot: &TileSlot) -> Result<Vec<u8>>
+    {
+        self.convert_tile(slot)?;
+
+        let mut pixels = vec![0u8; self.pixels_len()];
+        self.copy_tile(slot, [0, self.band_y], &mut pixels)?;
+
+        Ok(pixels)
+    }
+
+    /// Renders a frame for each of `push_constants` and returns them in the same order.
+    ///
+    /// A renderer from [`with_frames_in_flight`](Self::with_frames_in_flight) submits the frame of
+    /// each slot before it waits for the frame of the slot before, so the GPU renders one frame
+    /// while the host reads back another one. Each frame signals the fence of its slot, which is
+    /// reset and reused by the next frame of the slot once the frame was read back. Renders that
+    /// can't be submitted at once, see [`submit_and_get_fence`](Self::submit_and_get_fence), and
+    /// renders that write a histogram or iteration counts are dispatched one frame at a time, with
+    /// the histogram and iteration counts of the last frame written once at the end.
+    pub fn dispatch_frames(&self, push_constants: &[PushConstants]) -> Result<Vec<RenderOutput>>
+    {
+        let mut frame_sync = self.frame_sync.lock().unwrap_or_else(PoisonError::into_inner);
+        if !self.keeps_frames_in_flight() {
+            let frames = push_constants
+                .iter()
+                .map(|&push_constants| self.render_timed_frame(push_constants))
+                .collect::<Result<Vec<_>>>()?;
+            self.write_frame_outputs()?;
+
+            return Ok(frames);
+        }
+        ensure!(
+            frame_sync.len() == self.slots.len(),
+            "frames of an earlier call are still in flight after it failed"
+        );
+
+        let mut in_flight = VecDeque::with_capacity(self.slots.len());
+        let frames = self.render_frames_in_flight(push_constants, &mut frame_sync, &mut in_flight);
+        if frames.is_err() {
+            // The frames still in flight may still be running, their fences and everything they
+            // use are leaked like after a timeout
+            mem::forget(in_flight);
+        }
+
+        frames
+    }
+
+    /// Whether [`dispatch_frames`](Self::dispatch_frames) can keep several frames in flight, each
+    /// one a single submission per queue into a slot of its own.
+    fn keeps_frames_in_flight(&self) -> bool
+    {
+        let Self { width, band_height, tile_width, tile_height, .. } = *self;
+
+        self.slots.len() > 1
+            && tile_width == width
+            && tile_height == band_height
+            && !self.explicit_barriers
+            && self.last_batch_start() == 0
+            && self.histogram_csv.is_none()
+            && self.iterations_png.is_none()
+    }
+
+    /// Submits a frame for each of `push_constants`, rendering frame `n` into slot `n % slots`
+    /// once the frame before it in the slot was read back.
+    fn render_frames_in_flight(
+        &self,
+        push_constants: &[PushConstants],
+        frame_sync: &mut Vec<FrameSync>,
+        in_flight: &mut VecDeque<FrameInFlight>,
+    ) -> Result<Vec<RenderOutput>>
+    {
+        let mut frames = Vec::with_capacity(push_constants.len());
+        for &push_constants in push_constants {
+            if in_flight.len() == self.slots.len() {
+                frames.push(self.finish_frame(in_flight, frame_sync)?);
+            }
+
+            let slot_index = (frames.len() + in_flight.len()) % self.slots.len();
+            let slot = &self.slots[slot_index];
+            if let Some(counters_buf) = &slot.counters_buf {
+                *counters_buf.write().context("failed to reset the counters")? = Counters::default();
+            }
+
+            let push_constants = PushConstants {
+                offset: [0, self.band_y],
+                size: [self.width, self.height],
+                ..push_constants
+            };
+            let (command_buffer, copy_command_buffer) = self.record_tile(slot, push_constants)?;
+            let FrameSync { fence, copy_semaphore } = frame_sync.pop().context("no fence is free")?;
+            let submitted = match (&self.transfer_queue, &copy_command_buffer, &copy_semaphore) {
+                (Some(transfer_queue), Some(copy_command_buffer), Some(copy_semaphore)) => self
+                    .submit_raw_to(&self.queue, command_buffer.handle(), None, Some(copy_semaphore), None)
+                    .and_then(|()| {
+                        self.submit_raw_to(
+                            transfer_queue,
+                            copy_command_buffer.handle(),
+                            Some(copy_semaphore),
+                            None,
+                            Some(&fence),
+                        )
+                    }),
+                _ => self.submit_raw_to(&self.queue, command_buffer.handle(), None, None, Some(&fence)),
+            };
+
+            // The dispatch may have been submitted even if the copy wasn't, so a failed frame is
+            // leaked together with the ones in flight
+            in_flight.push_back(FrameInFlight {
+                fence,
+                in_use: (copy_semaphore, command_buffer, copy_command_buffer),
+                slot_index,
+                push_constants,
+            });
+            submitted?;
+        }
+        while !in_flight.is_empty() {
+            frames.push(self.finish_frame(in_flight, frame_sync)?);
+        }
+
+        Ok(frames)
+    }
+
+    /// Waits for the oldest frame in flight, resets its fence for the next frame of its slot and
+    /// reads the frame back.
+    fn finish_frame(
+        &self,
+        in_flight: &mut VecDeque<FrameInFlight>,
+        frame_sync: &mut Vec<FrameSync>,
+    ) -> Result<RenderOutput>
+    {
+        let frame = in_flight.pop_front().context("no frame is in flight")?;
+        let slot = &self.slots[frame.slot_index];
+        let (fence, (copy_semaphore, ..)) =
+            block_on(wait_for_fence(frame.fence, frame.in_use, self.timeout, "render"))?;
+
+        // SAFETY: the fence is signaled, so no submission uses it anymore
+        unsafe { fence.reset() }.context("failed to reset a fence")?;
+        frame_sync.push(FrameSync { fence, copy_semaphore });
+
+        let pixels = self.read_slot(slot)?;
+        if self.verify_pipeline.is_some() && self.band_y + self.band_height == self.height {
+            self.verify_last_row(&pixels, &frame.push_constants)?;
+        }
+
+        self.pixel_format.to_output(self.width, self.band_height, pixels)
+    }
+
+    /// Waits until the device has finished all submitted work.
+    fn wait_idle(&self) -> Result<()>
+    {
+        // Only this renderer submits to its device, and it isn't submitting while it waits
+        unsafe { self.device.wait_idle() }.context("failed to wait for the device to become idle")
+    }
+
+    /// Time spent creating the renderer plus the time of every frame from [`Renderer::dispatch_frame`].
+    pub fn timings(&self) -> Timings
+    {
+        *self.timings.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Renders the band and returns it as an image.
+    async fn render_frame<P>(&self, push_constants: PushConstants, progress: &mut P, timings: &mut Timings) -> Result<RenderOutput>
+    where
+        P: FnMut(f32) + ?Sized,
+    {
+        let pixels = self.render_pixels(push_constants, progress, timings).await?;
+
+        let start = Instant::now();
+        let image = self.pixel_format.to_output(self.width, self.band_height, pixels);
+        timings.readback += start.elapsed();
+
+        image
+    }
+
+    /// Renders every tile of the band in turn and assembles them into tightly packed pixel bytes.
+    ///
+    /// `progress` is called with the finished fraction of the tiles after each one completes.
+    async fn render_pixels<P>(&self, push_constants: PushConstants, progress: &mut P, timings: &mut Timings) -> Result<Vec<u8>>
+    where
+        P: FnMut(f32) + ?Sized,
+    {
+        let mut pixels = vec![0u8; self.pixels_len()];
+        self.render_pixels_into(push_constants, &mut pixels, progress, timings).await?;
+
+        Ok(pixels)
+    }
+
+    /// Size in bytes of the tightly packed pixels of the band.
+    fn pixels_len(&self) -> usize
+    {
+        self.width as usize * self.band_height as usize * self.pixel_format.bytes_per_pixel() as usize
+    }
+
+    /// Like [`render_pixels`](Self::render_pixels), but writes the pixels into `pixels`, which has
+    /// to be [`pixels_len`](Self::pixels_len) bytes long.
+    async fn render_pixels_into<P>(
+        &self,
+        push_constants: PushConstants,
+        pixels: &mut [u8],
+        progress: &mut P,
+        timings: &mut Timings,
+    ) -> Result<()>
+    where
+        P: FnMut(f32) + ?Sized,
+    {
+        let Self { width, height, band_y, band_height, tile_width, tile_height, .. } = *self;
+        let band_end = band_y + band_height;
+
+        let mut dispatch_ms = 0.0;
+        let mut copy_ms = 0.0;
+
+        let tiles: Vec<[u32; 2]> = (band_y..band_end)
+            .step_by(tile_height as usize)
+            .flat_map(|tile_y| (0..width).step_by(tile_width as usize).map(move |tile_x| [tile_x, tile_y]))
+            .collect();
+
+        // Every tile adds to the counters of its slot, so together they hold the count of the
+        // whole band after
+        for counters_buf in self.slots.iter().filter_map(|slot| slot.counters_buf.as_ref()) {
+            *counters_buf.write().context("failed to reset the counters")? = Counters::default();
+        }
+        for histogram_buf in self.slots.iter().filter_map(|slot| slot.histogram_buf.as_ref()) {
+            *histogram_buf.write().context("failed to reset the histogram")? = [0; HISTOGRAM_BINS];
+        }
+
+        if self.slots.len() > 1 {
+            self.render_tiles_in_parallel(&tiles, push_constants, pixels, progress, timings)?;
+        } else {
+            let slot = &self.slots[0];
+            for (tiles_done, &[tile_x, tile_y]) in tiles.iter().enumerate() {
+                let dispatch_start = Instant::now();
+                let (tile_dispatch_ms, tile_copy_ms) = self.render_tile(slot, PushConstants {
+                    offset: [tile_x, tile_y],
+                    size: [width, height],
+                    ..push_constants
+                }).await?;
+                dispatch_ms += tile_dispatch_ms;
+                copy_ms += tile_copy_ms;
+                timings.dispatch += dispatch_start.elapsed();
+
+                let readback_start = Instant::now();
+                self.convert_tile(slot)?;
+                self.copy_tile(slot, [tile_x, tile_y], pixels)?;
+                timings.readback += readback_start.elapsed();
+
+                progress((tiles_done + 1) as f32 / tiles.len() as f32);
+            }
+        }
+
+        let mut inside_pixels = None;
+        for counters_buf in self.slots.iter().filter_map(|slot| slot.counters_buf.as_ref()) {
+            let counters = *counters_buf.read().context("failed to read the counters")?;
+            *inside_pixels.get_or_insert(0) += counters.inside_pixels;
+        }
+        if let Some(inside_pixels) = inside_pixels {
+            info!("{inside_pixels} of {} pixels are inside the set", width * band_height);
+        }
+
+        if self.verify_pipeline.is_some() && band_end == height {
+            self.verify_last_row(pixels, &PushConstants { size: [width, height], ..push_constants })?;
+        }
+
+        if self.timestamp_query.is_some() {
+            info!("Dispatch took {dispatch_ms:.3} ms on the GPU");
+            *timings.gpu_dispatch.get_or_insert_default() += Duration::from_secs_f64(dispatch_ms / 1000.0);
+        }
+
+        if self.copy_timestamp_query.is_some() {
+            let mode = if self.slots[0].staging_buf.is_some() { "staged" } else { "direct" };
+            info!("Copy ({mode}) took {copy_ms:.3} ms on the GPU");
+        }
+
+        Ok(())
+    }
+
+    /// Renders `tiles` on one thread per slot and copies them into `pixels`.
+    ///
+    /// The threads share the device, queues and allocators, and each one takes the next tile that
+    /// no other thread has taken yet. Progress is reported on the calling thread, and as the
+    /// threads run at the same time the slowest one counts towards the timings.
+    fn render_tiles_in_parallel<P>(
+        &self,
+        tiles: &[[u32; 2]],
+        push_constants: PushConstants,
+        pixels: &mut [u8],
+        progress: &mut P,
+        timings: &mut Timings,
+    ) -> Result<()>
+    where
+        P: FnMut(f32) + ?Sized,
+    {
+        let start = Instant::now();
+        let next_tile = AtomicUsize::new(0);
+        let pixels = Mutex::new(pixels);
+        let (done_sender, done_receiver) = mpsc::channel();
+
+        let results: Vec<Result<Timings>> = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .slots
+                .iter()
+                .map(|slot| {
+                    let (next_tile, pixels, done_sender) = (&next_tile, &pixels, done_sender.clone());
+                    scope.spawn(move || {
+                        let mut slot_timings = Timings::default();
+                        while let Some(&[tile_x, tile_y]) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                            let dispatch_start = Instant::now();
+                            block_on(self.render_tile(slot, PushConstants {
+                                offset: [tile_x, tile_y],
+                                size: [self.width, self.height],
+                                ..push_constants
+                            }))?;
+                            slot_timings.dispatch += dispatch_start.elapsed();
+
+                            // Only the copy into the shared pixels waits for the other threads
+                            let readback_start = Instant::now();
+                            self.convert_tile(slot)?;
+                            let mut pixels = pixels.lock().unwrap_or_else(PoisonError::into_inner);
+                            self.copy_tile(slot, [tile_x, tile_y], &mut pixels)?;
+                            drop(pixels);
+                            slot_timings.readback += readback_start.elapsed();
+
+                            // The receiver lives until every thread has finished
+                            let _ = done_sender.send(());
+                        }
+
+                        Ok(slot_timings)
+                    })
+                })
+                .collect();
+
+            // Ends once every thread has dropped its sender
+            drop(done_sender);
+            for (tiles_done, ()) in done_receiver.iter().enumerate() {
+                progress((tiles_done + 1) as f32 / tiles.len() as f32);
+            }
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|_| Err(anyhow!("a render thread panicked"))))
+                .collect()
+        });
+
+        let mut slowest = Timings::default();
+        for slot_timings in results {
+            let slot_timings = slot_timings?;
+            slowest.dispatch = slowest.dispatch.max(slot_timings.dispatch);
+            slowest.readback = slowest.readback.max(slot_timings.readback);
+        }
+        timings.dispatch += slowest.dispatch;
+        timings.readback += slowest.readback;
+        info!(
+            "Rendered {} tiles on {} threads in {:.3} ms",
+            tiles.len(),
+            self.slots.len(),
+            start.elapsed().as_secs_f64() * 1000.0,
+        );
+
+        Ok(())
+    }
+
+    /// Rearranges the last readback of `slot` in place into RGBA for fallback formats, premultiplies
+    /// its alpha if requested and then reorders it into the requested channel order, so every
+    /// reader of the buffer sees the same bytes.
+    fn convert_tile(&self, slot: &TileSlot) -> Result<()>
+    {
+        if self.storage_format != self.pixel_format.format() || self.premultiply_alpha || self.swizzle != Swizzle::Rgba {
+            let mut buffer_content = slot.buf.write().context("failed to write buffer")?;
+            convert_to_rgba(self.storage_format, &mut buffer_content);
+            if self.premultiply_alpha {
+                self.pixel_format.premultiply_alpha(&mut buffer_content);
+            }
+            self.swizzle.apply(self.pixel_format, &mut buffer_content);
+        }
+
+        Ok(())
+    }
+
+    /// Copies the last readback of `slot`, the tile at `tile_x`, `tile_y`, into the tightly packed
+    /// `pixels` of the band.
+    fn copy_tile(&self, slot: &TileSlot, [tile_x, tile_y]: [u32; 2], pixels: &mut [u8]) -> Result<()>
+    {
+        let Self { width, band_y, band_height, tile_width, tile_height, pixel_format, .. } = *self;
+        let bytes_per_pixel = pixel_format.bytes_per_pixel() as usize;
+        let band_end = band_y + band_height;
+
+        // Edge tiles only partly cover the image, the rest of the tile is left unwritten
+        let tile_content = slot.buf.read().context("failed to read buffer")?;
+        let row_len = tile_width.min(width - tile_x) as usize * bytes_per_pixel;
+        let row_pitch = self.row_pitch(tile_width);
+        let rows = tile_height.min(band_end - tile_y) as usize;
+        for row in 0..rows {
+            let src = row * row_pitch;
+            let dst = (((tile_y - band_y) as usize + row) * width as usize + tile_x as usize) * bytes_per_pixel;
+            pixels[dst..dst + row_len].copy_from_slice(&tile_content[src..src + row_len]);
+        }
+
+        Ok(())
+    }
+
+    /// Submits fresh command buffers that render one tile into the `buf` of `slot` and waits for them.
+    ///
+    /// Returns the GPU time of the dispatch and of the copy in milliseconds, zero when timestamps
+    /// aren't supported.
+    async fn render_tile(&self, slot: &TileSlot, push_constants: PushConstants) -> Result<(f64, f64)>
+    {
+        if self.explicit_barriers {
+            let command_buffer = self.record_tile_with_barriers(slot, push_constants)?;
+            let fence = self.submit_raw(&command_buffer)?;
+            wait_for_fence(fence, command_buffer, self.timeout, "render").await?;
+
+            return Ok((0.0, 0.0));
+        }
+
+        // Every batch but the last only runs its passes, the last one finishes the tile
+        let batch_len = self.iterations_per_submit;
+        for batch_start in (0..self.last_batch_start()).step_by(batch_len as usize) {
+            let future = self.submit_passes(slot, push_constants, batch_start..batch_start + batch_len)?;
+            wait_with_timeout(future, self.timeout, "batch of passes").await?;
+        }
+
+        let future = self.submit_tile(slot, push_constants)?;
+        wait_with_timeout(future, self.timeout, "render").await?;
+
+        let dispatch_ms = match &self.timestamp_query {
+            Some(timestamp_query) => timestamp_query.elapsed_ms()?,
+            None => 0.0,
+        };
+        let copy_ms = match &self.copy_timestamp_query {
+            Some(copy_timestamp_query) => copy_timestamp_query.elapsed_ms()?,
+            None => 0.0,
+        };
+
+        Ok((dispatch_ms, copy_ms))
+    }
+
+    /// Index of the first pass of the last batch, which [`record_tile`](Self::record_tile) records
+    /// with the rest of the tile.
+    fn last_batch_start(&self) -> u32
+    {
+        (self.passes - 1) / self.iterations_per_submit * self.iterations_per_submit
+    }
+
+    /// Records the `passes` of one tile on their own, submits them and returns the fence they
+    /// signal without waiting for it.
+    fn submit_passes(
+        &self,
+        slot: &TileSlot,
+        push_constants: PushConstants,
+        passes: Range<u32>,
+    ) -> Result<SubmittedFuture>
+    {
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .context("failed to create an AutoCommandBufferBuilder")?;
+        self.record_dispatch(slot, &mut command_buffer_builder, push_constants, passes)?;
+        let command_buffer = command_buffer_builder
+            .build()
+            .context("failed to build a PrimaryAutoCommandBuffer")?;
+
+        sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), command_buffer)
+            .context("failed to execute a command buffer after this future")?
+            .boxed_send_sync()
+            .then_signal_fence_and_flush()
+            .memory_context("failed to signal a fence after this future and flush")
+    }
+
+    /// Records one tile into the `buf` of `slot`, submits it and returns the fence it signals
+    /// without waiting for it.
+    fn submit_tile(
+        &self,
+        slot: &TileSlot,
+        push_constants: PushConstants,
+    ) -> Result<SubmittedFuture>
+    {
+        let (command_buffer, copy_command_buffer) = self.record_tile(slot, push_constants)?;
+
+        let dispatched = sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), command_buffer)
+            .context("failed to execute a command buffer after this future")?;
+
+        // The transfer queue waits on a semaphore the compute queue signals after the dispatch
+        let submitted = match (&self.transfer_queue, copy_command_buffer) {
+            (Some(transfer_queue), Some(copy_command_buffer)) => dispatched
+                .then_signal_semaphore_and_flush()
+                .memory_context("failed to signal a semaphore after the dispatch")?
+                .then_execute(transfer_queue.clone(), copy_command_buffer)
+                .context("failed to execute the copy on the transfer queue")?
+                .boxed_send_sync(),
+            _ => dispatched.boxed_send_sync(),
+        };
+
+        submitted
+            .then_signal_fence_and_flush()
+            .memory_context("failed to signal a fence after this future and flush")
+    }
+
+    /// Binds the pipeline and descriptor set, pushes `push_constants` and dispatches the passes in
+    /// `pass_range` of one tile.
+    fn record_dispatch<L>(
+        &self,
+        slot: &TileSlot,
+        command_buffer_builder: &mut AutoCommandBufferBuilder<L>,
+        push_constants: PushConstants,
+        pass_range: Range<u32>,
+    ) -> Result<()>
+    {
+        let Self { tile_width, tile_height, workgroup_size: [size_x, size_y], depth, passes, .. } = *self;
+
+        command_buffer_builder
+            .bind_pipeline_compute(self.compute_pipeline.clone())
+            .context("failed to bind a compute pipeline to a command buffer")?;
+
+        for pass_index in pass_range {
+            // Passes alternate so that the last one writes `image`, which is the one read back, or
+            // the second image when the palette pass recolours it into `image`. The builder sees
+            // each pass read what the one before wrote and inserts the barrier.
+            let ends_in_pong = self.palette_pipeline.is_some();
+            let set = match &slot.pong_set {
+                Some(pong_set) if ((passes - 1 - pass_index) % 2 == 1) != ends_in_pong => pong_set,
+                _ => &slot.set,
+            };
+
+            command_buffer_builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    self.compute_pipeline.layout().clone(),
+                    0,
+                    set.clone(),
+                )
+                .context("failed to bind descriptor sets to a command buffer")?
+                .push_constants(
+                    self.compute_pipeline.layout().clone(),
+                    0,
+                    PushConstants {
+                        pass_index,
+                        ..push_constants
+                    },
+                )
+                .context("failed to push constants to a command buffer")?;
+
+            unsafe {
+                command_buffer_builder
+                    .dispatch([div_ceil(tile_width, size_x), div_ceil(tile_height, size_y), depth])
+                    .context("failed to dispatch work_group_counts")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Dispatches `histogram_pipeline` over the tile, which adds the luminance of every pixel of
+    /// the tile that lies within the image to the histogram of `histogram_set`.
+    fn record_histogram<L>(
+        &self,
+        command_buffer_builder: &mut AutoCommandBufferBuilder<L>,
+        histogram_pipeline: &Arc<ComputePipeline>,
+        histogram_set: &Arc<DescriptorSet>,
+        push_constants: PushConstants,
+    ) -> Result<()>
+    {
+        let Self { tile_width, tile_height, workgroup_size: [size_x, size_y], .. } = *self;
+
+        command_buffer_builder
+            .bind_pipeline_compute(histogram_pipeline.clone())
+            .context("failed to bind the histogram pipeline")?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                histogram_pipeline.layout().clone(),
+                0,
+                histogram_set.clone(),
+            )
+            .context("failed to bind the histogram set")?
+            .push_constants(histogram_pipeline.layout().clone(), 0, push_constants)
+            .context("failed to push constants to the histogram")?;
+
+        unsafe {
+            command_buffer_builder
+                .dispatch([div_ceil(tile_width, size_x), div_ceil(tile_height, size_y), 1])
+                .context("failed to dispatch the histogram")?;
+        }
+
+        Ok(())
+    }
+
+    /// Dispatches `palette_pipeline` over the tile, which writes the palette colour of the
+    /// luminance of every pixel the render wrote into `image`.
+    fn record_palette<L>(
+        &self,
+        command_buffer_builder: &mut AutoCommandBufferBuilder<L>,
+        palette_pipeline: &Arc<ComputePipeline>,
+        palette_set: &Arc<DescriptorSet>,
+    ) -> Result<()>
+    {
+        let Self { tile_width, tile_height, workgroup_size: [size_x, size_y], .. } = *self;
+
+        command_buffer_builder
+            .bind_pipeline_compute(palette_pipeline.clone())
+            .context("failed to bind the palette pipeline")?
+            .bind_descriptor_sets(PipelineBindPoint::Compute, palette_pipeline.layout().clone(), 0, palette_set.clone())
+            .context("failed to bind the palette set")?;
+
+        unsafe {
+            command_buffer_builder
+                .dispatch([div_ceil(tile_width, size_x), div_ceil(tile_height, size_y), 1])
+                .context("failed to dispatch the palette shader")?;
+        }
+
+        Ok(())
+    }
+
+    /// Dispatches `verify_pipeline` over the last row of the image if the tile holds it, after
+    /// which the row holds [`verify_pattern`] instead of what the render wrote.
+    fn record_verify<L>(
+        &self,
+        command_buffer_builder: &mut AutoCommandBufferBuilder<L>,
+        verify_pipeline: &Arc<ComputePipeline>,
+        verify_set: &Arc<DescriptorSet>,
+        push_constants: PushConstants,
+    ) -> Result<()>
+    {
+        let [_, tile_y] = push_constants.offset;
+        let [_, height] = push_constants.size;
+        if !(tile_y..tile_y + self.tile_height).contains(&(height - 1)) {
+            return Ok(());
+        }
+
+        command_buffer_builder
+            .bind_pipeline_compute(verify_pipeline.clone())
+            .context("failed to bind the verify pipeline")?
+            .bind_descriptor_sets(PipelineBindPoint::Compute, verify_pipeline.layout().clone(), 0, verify_set.clone())
+            .context("failed to bind the verify set")?
+            .push_constants(verify_pipeline.layout().clone(), 0, push_constants)
+            .context("failed to push constants to the verify shader")?;
+
+        unsafe {
+            command_buffer_builder
+                .dispatch([div_ceil(self.tile_width, VERIFY_WORKGROUP_WIDTH), 1, 1])
+                .context("failed to dispatch the verify shader")?;
+        }
+
+        Ok(())
+    }
+
+    /// Compares the last row of `pixels`, the band that ends at the bottom of the image, with the
+    /// pattern recomputed for `push_constants`.
+    fn verify_last_row(&self, pixels: &[u8], push_constants: &PushConstants) -> Result<()>
+    {
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel() as usize;
+        let row = &pixels[pixels.len() - self.width as usize * bytes_per_pixel..];
+
+        let mut mismatched = 0;
+        let mut first_mismatch = None;
+        for (x, pixel) in row.chunks_exact(bytes_per_pixel).enumerate() {
+            let expected = verify_pattern(x as u32, push_constants);
+            // Unorm16 stores n / 255 exactly as n * 257 and floats round back to n, anything
+            // between two levels can't have been written by the shader
+            let actual: [u8; 4] = match self.pixel_format {
+                PixelFormat::Rgba8 => [pixel[0], pixel[1], pixel[2], pixel[3]],
+                PixelFormat::Rgba16 => std::array::from_fn(|channel| {
+                    let value = u16::from_ne_bytes([pixel[channel * 2], pixel[channel * 2 + 1]]);
+                    if value % 257 == 0 { (value / 257) as u8 } else { !expected[channel] }
+                }),
+                PixelFormat::Rgba32f => std::array::from_fn(|channel| {
+                    let bytes = &pixel[channel * 4..channel * 4 + 4];
+                    let value = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) * 255.0;
+                    if (value - value.round()).abs() < 0.01 { value.round() as u8 } else { !expected[channel] }
+                }),
+            };
+
+            if actual != expected {
+                mismatched += 1;
+                first_mismatch.get_or_insert((x, actual, expected));
+            }
+        }
+
+        if let Some((x, actual, expected)) = first_mismatch {
+            bail!(
+                "--verify found {mismatched} of {} pixels of the last row wrong, pixel {x} is {actual:?} \
+                 instead of {expected:?}",
+                self.width,
+            );
+        }
+        info!("The checksum pattern of the last row matches");
+
+        Ok(())
+    }
+
+    /// Writes the histogram and the iteration counts of the last frame to their files.
+    ///
+    /// The buffers keep their contents until the next frame starts, so renders of many frames
+    /// write the files once after the last one instead of after every frame, outside the time of
+    /// the frames.
+    fn write_frame_outputs(&self) -> Result<()>
+    {
+        if let Some(path) = &self.histogram_csv {
+            self.write_histogram(path)?;
+        }
+
+        if let Some(path) = &self.iterations_png {
+            self.write_iterations(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sums the histograms of every slot and writes them to `path` as CSV, one row per bin.
+    fn write_histogram(&self, path: &Path) -> Result<()>
+    {
+        let mut bins = [0u32; HISTOGRAM_BINS];
+        for histogram_buf in self.slots.iter().filter_map(|slot| slot.histogram_buf.as_ref()) {
+            let slot_bins = histogram_buf.read().context("failed to read the histogram")?;
+            for (bin, count) in bins.iter_mut().zip(slot_bins.iter()) {
+                *bin += count;
+            }
+        }
+
+        let mut csv = String::from("luminance,pixels\n");
+        for (bin, count) in bins.iter().enumerate() {
+            csv.push_str(&format!("{bin},{count}\n"));
+        }
+        fs::write(path, csv).with_context(|| format!("failed to write the histogram to {}", path.display()))?;
+
+        let peak = bins.iter().enumerate().max_by_key(|&(_, count)| count).map_or(0, |(bin, _)| bin);
+        info!(
+            "Histogram of {} pixels written to {}, the most common luminance is {peak}",
+            bins.iter().map(|&count| u64::from(count)).sum::<u64>(),
+            path.display(),
+        );
+
+        Ok(())
+    }
+
+    /// Writes the iteration counts of the untiled render to `path` as a 16-bit grayscale PNG,
+    /// counts above 65535 saturate.
+    #[cfg(feature = "image-output")]
+    fn write_iterations(&self, path: &Path) -> Result<()>
+    {
+        let (_, iterations_buf) = self.slots[0].iterations.as_ref().context("no iteration counts were rendered")?;
+        let counts: Vec<u16> = iterations_buf
+            .read()
+            .context("failed to read the iteration counts")?
+            .iter()
+            .map(|&count| count.min(u32::from(u16::MAX)) as u16)
+            .collect();
+        let max_count = counts.iter().copied().max().unwrap_or(0);
+
+        image::ImageBuffer::<image::Luma<u16>, _>::from_raw(self.width, self.band_height, counts)
+            .context("the iteration counts don't match the image size")?
+            .save(path)
+            .with_context(|| format!("failed to write the iteration counts to {}", path.display()))?;
+        info!("Iteration counts written to {}, the highest is {max_count}", path.display());
+
+        Ok(())
+    }
+
+    /// Fails, encoding PNGs needs the `image-output` feature.
+    #[cfg(not(feature = "image-output"))]
+    fn write_iterations(&self, path: &Path) -> Result<()>
+    {
+        bail!("can't write the iteration counts to {}, build with the image-output feature", path.display())
+    }
+
+    /// Records the dispatch of one tile followed by the copy into `buf`, which gets a command
+    /// buffer of its own when there is a transfer queue.
+    ///
+    /// Kept out of `render_tile` because the builder isn't `Send` and must not live across an
+    /// `.await`.
+    fn record_tile(
+        &self,
+        slot: &TileSlot,
+        push_constants: PushConstants,
+    ) -> Result<(Arc<PrimaryAutoCommandBuffer>, Option<Arc<PrimaryAutoCommandBuffer>>)>
+    {
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .context("failed to create an AutoCommandBufferBuilder")?;
+
+        // The builder tracks the image and inserts the barrier between the clear and the
+        // shader's writes itself
+        if let Some(clear_color) = self.clear_color {
+            command_buffer_builder
+                .clear_color_image(ClearColorImageInfo {
+                    clear_value: ClearColorValue::Float(clear_color),
+                    ..ClearColorImageInfo::image(slot.image.clone())
+                })
+                .context("failed to clear the image")?;
+        }
+
+        // Earlier batches of passes were already submitted on their own
+        let last_batch = self.last_batch_start()..self.passes;
+        let secondary_command_buffer = if self.secondary_command_buffers {
+            // Compute-only secondaries are never executed inside a render pass and inherit no
+            // queries, which is what the default inheritance info describes
+            let mut secondary_builder = AutoCommandBufferBuilder::secondary(
+                self.command_buffer_allocator.clone(),
+                self.queue.queue_family_index(),
+                CommandBufferUsage::OneTimeSubmit,
+                CommandBufferInheritanceInfo::default(),
+            )
+            .context("failed to create a secondary AutoCommandBufferBuilder")?;
+            self.record_dispatch(slot, &mut secondary_builder, push_constants, last_batch.clone())?;
+
+            Some(secondary_builder.build().context("failed to build a SecondaryAutoCommandBuffer")?)
+        } else {
+            None
+        };
+
+        if let Some(timestamp_query) = &self.timestamp_query {
+            timestamp_query.write_start(&mut command_buffer_builder)?;
+        }
+
+        // The primary buffer tracks the resources the secondary one uses and inserts the
+        // barriers around it, just like for commands recorded directly
+        match secondary_command_buffer {
+            Some(secondary_command_buffer) => {
+                command_buffer_builder
+                    .execute_commands(secondary_command_buffer)
+                    .context("failed to execute a secondary command buffer")?;
+            }
+            None => self.record_dispatch(slot, &mut command_buffer_builder, push_constants, last_batch)?,
+        }
+
+        if let Some(timestamp_query) = &self.timestamp_query {
+            timestamp_query.write_end(&mut command_buffer_builder)?;
+        }
+
+        // The builder sees the copy read what the dispatch wrote and inserts the barrier
+        if let Some((iterations_image, iterations_buf)) = &slot.iterations {
+            let copy_info = CopyImageToBufferInfo::image_buffer(iterations_image.clone(), iterations_buf.clone());
+            command_buffer_builder
+                .copy_image_to_buffer(copy_info)
+                .context("failed to copy the iteration counts")?;
+        }
+
+        // The builder sees the palette pass read the second image the render wrote and inserts
+        // the barrier, everything after it works on the recoloured `image`
+        if let (Some(palette_pipeline), Some(palette_set)) = (&self.palette_pipeline, &slot.palette_set) {
+            self.record_palette(&mut command_buffer_builder, palette_pipeline, palette_set)?;
+        }
+
+        // The builder sees the histogram read what the render wrote and inserts the barrier
+        if let (Some(histogram_pipeline), Some(histogram_set)) = (&self.histogram_pipeline, &slot.histogram_set) {
+            self.record_histogram(&mut command_buffer_builder, histogram_pipeline, histogram_set, push_constants)?;
+        }
+
+        // Written after the histogram so it counts what the render wrote, and before the mip
+        // levels are blitted from the row the host checks
+        if let (Some(verify_pipeline), Some(verify_set)) = (&self.verify_pipeline, &slot.verify_set) {
+            self.record_verify(&mut command_buffer_builder, verify_pipeline, verify_set, push_constants)?;
+        }
+
+        // Each level is downsampled from the one before it
+        for level in 1..self.mip_levels {
+            command_buffer_builder
+                .blit_image(BlitImageInfo {
+                    regions: [ImageBlit {
+                        src_subresource: ImageSubresourceLayers {
+                            mip_level: level - 1,
+                            ..slot.image.subresource_layers()
+                        },
+                        src_offsets: [[0; 3], self.mip_extent(level - 1)],
+                        dst_subresource: ImageSubresourceLayers {
+                            mip_level: level,
+                            ..slot.image.subresource_layers()
+                        },
+                        dst_offsets: [[0; 3], self.mip_extent(level)],
+                        ..Default::default()
+                    }]
+                    .into(),
+                    filter: Filter::Linear,
+                    ..BlitImageInfo::images(slot.image.clone(), slot.image.clone())
+                })
+                .with_context(|| format!("failed to blit mip level {level}"))?;
+        }
+
+        let copy_command_buffer = match &self.transfer_queue {
+            Some(transfer_queue) => {
+                let mut copy_builder = AutoCommandBufferBuilder::primary(
+                    self.command_buffer_allocator.clone(),
+                    transfer_queue.queue_family_index(),
+                    CommandBufferUsage::OneTimeSubmit,
+                )
+                .context("failed to create an AutoCommandBufferBuilder for the copy")?;
+                self.record_copy(slot, &mut copy_builder)?;
+
+                Some(copy_builder.build().context("failed to build the copy PrimaryAutoCommandBuffer")?)
+            }
+            None => {
+                self.record_copy(slot, &mut command_buffer_builder)?;
+                None
+            }
+        };
+
+        let command_buffer = command_buffer_builder
+            .build()
+            .context("failed to build a PrimaryAutoCommandBuffer")?;
+
+        Ok((command_buffer, copy_command_buffer))
+    }
+
+    /// Records the dispatch of one tile and the copy into `buf` with the barriers between them
+    /// written out by hand.
+    ///
+    /// `record_tile` leaves these to the builder, which tracks every resource a command uses and
+    /// inserts a barrier whenever one command accesses what an earlier one wrote. For this
+    /// command buffer it inserts the same two: the image from its previous contents to `General`
+    /// before the shader writes it, and from `General` to `TransferSrcOptimal` once the shader has
+    /// written it. The last barrier, from the device's writes to the host reading them, it leaves
+    /// to the fence wait, which is spelled out here too.
+    fn record_tile_with_barriers(&self, slot: &TileSlot, push_constants: PushConstants) -> Result<CommandBuffer>
+    {
+        let Self { tile_width, tile_height, workgroup_size: [size_x, size_y], depth, .. } = *self;
+        let layout = self.compute_pipeline.layout();
+
+        let mut builder = RecordingCommandBuffer::new(
+            self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferLevel::Primary,
+            CommandBufferBeginInfo {
+                usage: CommandBufferUsage::OneTimeSubmit,
+                ..Default::default()
+            },
+        )
+        .context("failed to create a RecordingCommandBuffer")?;
+
+        // Only stages and accesses that exist without `synchronization2` are used, the rest
+        // don't fit into the flags of `vkCmdPipelineBarrier`
+        let image_barrier = ImageMemoryBarrier {
+            subresource_range: slot.image.subresource_range(),
+            ..ImageMemoryBarrier::image(slot.image.clone())
+        };
+
+        // SAFETY: the renderer keeps the pipeline, descriptor set, image and buffer alive, and
+        // waits for the fence of the submission before anything else accesses them
+        unsafe {
+            // The previous tile was read back already, its contents can be discarded
+            builder
+                .pipeline_barrier(&DependencyInfo {
+                    image_memory_barriers: [ImageMemoryBarrier {
+                        dst_stages: PipelineStages::COMPUTE_SHADER,
+                        dst_access: AccessFlags::SHADER_READ | AccessFlags::SHADER_WRITE,
+                        old_layout: ImageLayout::Undefined,
+                        new_layout: ImageLayout::General,
+                        ..image_barrier.clone()
+                    }]
+                    .into_iter()
+                    .collect(),
+                    ..Default::default()
+                })
+                .context("failed to record the barrier before the dispatch")?
+                .bind_pipeline_compute(&self.compute_pipeline)
+                .context("failed to bind a compute pipeline to a command buffer")?
+                .bind_descriptor_sets(PipelineBindPoint::Compute, layout, 0, &[slot.set.as_raw()], &[])
+                .context("failed to bind descriptor sets to a command buffer")?
+                .push_constants(layout, 0, &push_constants)
+                .context("failed to push constants to a command buffer")?
+                .dispatch([div_ceil(tile_width, size_x), div_ceil(tile_height, size_y), depth])
+                .context("failed to dispatch work_group_counts")?;
+
+            // The copy has to wait for the shader's writes to become available to it
+            builder
+                .pipeline_barrier(&DependencyInfo {
+                    image_memory_barriers: [ImageMemoryBarrier {
+                        src_stages: PipelineStages::COMPUTE_SHADER,
+                        src_access: AccessFlags::SHADER_WRITE,
+                        dst_stages: PipelineStages::ALL_TRANSFER,
+                        dst_access: AccessFlags::TRANSFER_READ,
+                        old_layout: ImageLayout::General,
+                        new_layout: ImageLayout::TransferSrcOptimal,
+                        ..image_barrier
+                    }]
+                    .into_iter()
+                    .collect(),
+                    ..Default::default()
+                })
+                .context("failed to record the barrier before the copy")?
+                .copy_image_to_buffer(&self.copy_image_info(slot, slot.buf.clone()))
+                .context("failed to copy an image to a buffer")?;
+
+            // Makes the copied bytes and the shader's counters visible to the host once the
+            // fence is signaled
+            let counters_barrier = slot.counters_buf.as_ref().map(|counters_buf| BufferMemoryBarrier {
+                src_stages: PipelineStages::COMPUTE_SHADER,
+                src_access: AccessFlags::SHADER_WRITE,
+                dst_stages: PipelineStages::HOST,
+                dst_access: AccessFlags::HOST_READ,
+                range: counters_buf.offset()..counters_buf.offset() + counters_buf.size(),
+                ..BufferMemoryBarrier::buffer(counters_buf.buffer().clone())
+            });
+            builder
+                .pipeline_barrier(&DependencyInfo {
+                    buffer_memory_barriers: [Some(BufferMemoryBarrier {
+                        src_stages: PipelineStages::ALL_TRANSFER,
+                        src_access: AccessFlags::TRANSFER_WRITE,
+                        dst_stages: PipelineStages::HOST,
+                        dst_access: AccessFlags::HOST_READ,
+                        range: slot.buf.offset()..slot.buf.offset() + slot.buf.size(),
+                        ..BufferMemoryBarrier::buffer(slot.buf.buffer().clone())
+                    }), counters_barrier]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                    ..Default::default()
+                })
+                .context("failed to record the barrier before the readback")?;
+
+            builder.end().context("failed to end a RecordingCommandBuffer")
+        }
+    }
+
+    /// Submits `command_buffer` to the compute queue and returns the fence it signals.
+    ///
+    /// vulkano only submits the command buffers its builders create, so this goes through the
+    /// raw `vkQueueSubmit`.
+    fn submit_raw(&self, command_buffer: &CommandBuffer) -> Result<Fence>
+    {
+        let fence = Fence::from_pool(self.device.clone()).context("failed to create a fence")?;
+        self.submit_raw_to(&self.queue, command_buffer.handle(), None, None, Some(&fence))?;
+
+        Ok(fence)
+    }
+
+    /// Submits the command buffer `command_buffer` to `queue` through the raw `vkQueueSubmit`,
+    /// after `wait_semaphore` is signaled, and signals `signal_semaphore` and `fence` once it
+    /// completes.
+    ///
+    /// The command buffer has to stay alive until the fence signals, and the resources it uses
+    /// must not be accessed before. Command buffers of vulkano's builders transition every image
+    /// back to the layout it started in, so they can be submitted this way too.
+    fn submit_raw_to(
+        &self,
+        queue: &Arc<Queue>,
+        command_buffer: vk::CommandBuffer,
+        wait_semaphore: Option<&Semaphore>,
+        signal_semaphore: Option<&Semaphore>,
+        fence: Option<&Fence>,
+    ) -> Result<()>
+    {
+        let command_buffers = [command_buffer];
+        let wait_semaphores: Vec<_> = wait_semaphore.iter().map(|semaphore| semaphore.handle()).collect();
+        // The copy is the only work that waits on a semaphore
+        let wait_stages = vec![vk::PipelineStageFlags::TRANSFER; wait_semaphores.len()];
+        let signal_semaphores: Vec<_> = signal_semaphore.iter().map(|semaphore| semaphore.handle()).collect();
+        let submit_info = vk::SubmitInfo::default()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+        let fence = fence.map_or(vk::Fence::null(), |fence| fence.handle());
+
+        // SAFETY: holding the queue's guard keeps vulkano from submitting to it at the same time,
+        // and the command buffer was recorded for this queue's family
+        queue
+            .with(|_queue_guard| unsafe {
+                (self.device.fns().v1_0.queue_submit)(queue.handle(), 1, &submit_info, fence)
+            })
+            .result()
+            .map_err(VulkanError::from)
+            .memory_context("failed to submit a command buffer")
+    }
+
+    /// Records the copy of the image into `buf`, through the staging buffer if there is one.
+    fn record_copy<L>(&self, slot: &TileSlot, command_buffer_builder: &mut AutoCommandBufferBuilder<L>) -> Result<()>
+    {
+        if let Some(copy_timestamp_query) = &self.copy_timestamp_query {
+            copy_timestamp_query.write_start(command_buffer_builder)?;
+        }
+
+        if let Some(staging_buf) = &slot.staging_buf {
+            command_buffer_builder
+                .copy_image_to_buffer(self.copy_image_info(slot, staging_buf.clone()))
+                .context("failed to copy an image to the staging buffer")?
+                .copy_buffer(CopyBufferInfo::buffers(staging_buf.clone(), slot.buf.clone()))
+                .context("failed to copy the staging buffer to a buffer")?;
+        } else {
+            command_buffer_builder
+                .copy_image_to_buffer(self.copy_image_info(slot, slot.buf.clone()))
+                .context("failed to copy an image to a buffer")?;
+        }
+
+        if let Some(copy_timestamp_query) = &self.copy_timestamp_query {
+            copy_timestamp_query.write_end(command_buffer_builder)?;
+        }
+
+        Ok(())
+    }
+
+    fn mip_extent(&self, level: u32) -> [u32; 3]
+    {
+        mip_level_extent(self.slots[0].image.extent(), level).unwrap_or([1, 1, 1])
+    }
+
+    /// Bytes from one row of a `width` pixel wide image in the readback buffer to the next.
+    fn row_pitch(&self, width: u32) -> usize
+    {
+        self.pixel_format.row_pitch(width, self.row_alignment) as usize
+    }
+
+    /// Copies every mip level of the image into `buffer`, one after the other with padded rows.
+    fn copy_image_info(&self, slot: &TileSlot, buffer: Subbuffer<[u8]>) -> CopyImageToBufferInfo
+    {
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel() as DeviceSize;
+
+        let mut buffer_offset = 0;
+        let regions = (0..self.mip_levels)
+            .map(|level| {
+                let image_extent @ [level_width, level_height, level_depth] = self.mip_extent(level);
+                let row_pitch = self.row_pitch(level_width) as DeviceSize;
+                let region = BufferImageCopy {
+                    buffer_offset,
+                    // Both are measured in texels, slices follow each other without extra rows
+                    buffer_row_length: (row_pitch / bytes_per_pixel) as u32,
+                    buffer_image_height: level_height,
+                    image_subresource: ImageSubresourceLayers {
+                        mip_level: level,
+                        ..slot.image.subresource_layers()
+                    },
+                    image_extent,
+                    ..Default::default()
+                };
+                buffer_offset += row_pitch * level_height as DeviceSize * level_depth as DeviceSize;
+
+                region
+            })
+            .collect();
+
+        CopyImageToBufferInfo {
+            regions,
+            ..CopyImageToBufferInfo::image_buffer(slot.image.clone(), buffer)
+        }
+    }
+
+    /// Splits the last readback of a 3D image into one image per Z slice, front to back.
+    fn read_slices(&self) -> Result<Vec<RenderOutput>>
+    {
+        let Self { width, band_height, pixel_format, .. } = *self;
+        let row_len = width as usize * pixel_format.bytes_per_pixel() as usize;
+        let row_pitch = self.row_pitch(width);
+        let buffer_content = self.slots[0].buf.read().context("failed to read buffer")?;
+
+        buffer_content
+            .chunks_exact(row_pitch * band_height as usize)
+            .map(|slice| {
+                let pixels = strip_row_padding(slice, row_len, row_pitch, band_height as usize);
+                pixel_format.to_output(width, band_height, pixels)
+            })
+            .collect()
+    }
+
+    /// Splits the last readback into one image per mip level, largest first.
+    fn read_mip_levels(&self) -> Result<Vec<RenderOutput>>
+    {
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel() as usize;
+        let buffer_content = self.slots[0].buf.read().context("failed to read buffer")?;
+
+        let mut offset = 0;
+        (0..self.mip_levels)
+            .map(|level| {
+                let [level_width, level_height, _] = self.mip_extent(level);
+                let row_pitch = self.row_pitch(level_width);
+                let len = row_pitch * level_height as usize;
+                let pixels = strip_row_padding(
+                    &buffer_content[offset..offset + len],
+                    level_width as usize * bytes_per_pixel,
+                    row_pitch,
+                    level_height as usize,
+                );
+                let image = self.pixel_format.to_output(level_width, level_height, pixels);
+                offset += len;
+
+                image
+            })
+            .collect()
+    }
+}
+
+/// Runs the compute shader on the best available device and returns the rendered image.
+pub fn render_compute(config: &RenderConfig) -> Result<RenderOutput>
+{
+    render_compute_with_progress(config, &mut |_| {})
+}
+
+/// Like [`render_compute`], but calls `progress` with the finished fraction from 0.0 to 1.0 after
+/// every tile, once the GPU work for it has completed.
+pub fn render_compute_with_progress(config: &RenderConfig, progress: &mut dyn FnMut(f32)) -> Result<RenderOutput>
+{
+    render_compute_timed(config, progress, &mut Timings::default())
+}
+
+/// Like [`render_compute_with_progress`], but also adds the time spent in every stage to `timings`.
+pub fn render_compute_timed(
+    config: &RenderConfig,
+    progress: &mut dyn FnMut(f32),
+    timings: &mut Timings,
+) -> Result<RenderOutput>
+{
+    block_on(render_compute_inner(config, progress, timings))
+}
+
+/// Renders the image with its full mip chain and returns every level, largest first.
+pub fn render_mipmaps(config: &RenderConfig, progress: &mut dyn FnMut(f32)) -> Result<Vec<RenderOutput>>
+{
+    ensure!(!config.multi_gpu, "--multi-gpu doesn't support mipmaps");
+
+    let config = RenderConfig {
+        mipmaps: true,
+        ..config.clone()
+    };
+
+    let renderer = Renderer::new(&config)?;
+    let mut timings = renderer.timings();
+
+    block_on(renderer.render_pixels(config.push_constants(config.time), progress, &mut timings))?;
+    renderer.write_frame_outputs()?;
+
+    renderer.read_mip_levels()
+}
+
+/// Renders a volume shader into a 3D image of `depth` slices and returns each slice.
+pub fn render_volume(config: &RenderConfig, progress: &mut dyn FnMut(f32)) -> Result<Vec<RenderOutput>>
+{
+    ensure!(config.shader.is_volume(), "the {:?} shader doesn't render volumes", config.shader);
+    ensure!(!config.multi_gpu, "--multi-gpu doesn't support volumes");
+
+    let renderer = Renderer::new(config)?;
+    let mut timings = renderer.timings();
+
+    block_on(renderer.render_pixels(config.push_constants(config.time), progress, &mut timings))?;
+    renderer.write_frame_outputs()?;
+
+    renderer.read_slices()
+}
+
+/// Renders the image straight into `out`, which has to hold exactly `width * height *
+/// bytes_per_pixel` bytes, without allocating an image for it.
+///
+/// The pixels are tightly packed rows in the layout [`PixelFormat::to_output`] reads, with
+/// 16 and 32 bit channels in native byte order. With an input image its size is the one that
+/// counts.
+pub fn render_into(config: &RenderConfig, out: &mut [u8]) -> Result<()>
+{
+    ensure!(!config.multi_gpu, "--multi-gpu doesn't support rendering into a buffer");
+    ensure!(!config.mipmaps && !config.shader.is_volume(), "only single images can be rendered into a buffer");
+
+    let renderer = Renderer::new(config)?;
+    ensure!(
+        out.len() == renderer.pixels_len(),
+        "the output buffer holds {} bytes, but a {}x{} {:?} image needs {}",
+        out.len(),
+        renderer.width,
+        renderer.height,
+        config.pixel_format,
+        renderer.pixels_len(),
+    );
+
+    let mut timings = renderer.timings();
+    block_on(renderer.render_pixels_into(config.push_constants(config.time), out, &mut |_| {}, &mut timings))?;
+
+    renderer.write_frame_outputs()
+}
+
+/// Colour the `Solid` shader writes to every pixel.
+pub const SOLID_COLOR: [u8; 4] = [51, 102, 153, 255];
+
+/// Side length of the image [`self_test`] renders.
+const SELF_TEST_SIZE: u32 = 8;
+
+/// Renders a tiny image with the `Solid` shader and checks that every pixel read back has
+/// [`SOLID_COLOR`], which exercises the instance, device, pipeline, dispatch, copy and readback.
+///
+/// The device, validation, memory and download settings are taken from `config`, everything
+/// else is replaced by what the test needs.
+pub fn self_test(config: &RenderConfig) -> Result<()>
+{
+    let config = RenderConfig {
+        width: SELF_TEST_SIZE,
+        height: SELF_TEST_SIZE,
+        workgroup_size: [SELF_TEST_SIZE; 2],
+        pipeline_cache: None,
+        pixel_format: PixelFormat::Rgba8,
+        swizzle: Swizzle::Rgba,
+        input: None,
+        resize: None,
+        shader: ShaderKind::Solid,
+        multi_gpu: false,
+        clear_color: None,
+        mipmaps: false,
+        depth: 1,
+        spirv: None,
+        glsl: None,
+        entry_point: "main".to_owned(),
+        histogram: None,
+        verify: false,
+        palette: None,
+        dump_iterations: None,
+        passes: 1,
+        iterations_per_submit: None,
+        srgb: false,
+        premultiply_alpha: false,
+        ..config.clone()
+    };
+
+    let mut pixels = vec![0u8; (SELF_TEST_SIZE * SELF_TEST_SIZE * 4) as usize];
+    render_into(&config, &mut pixels)?;
+
+    let wrong_pixels = pixels.chunks_exact(4).filter(|&pixel| pixel != SOLID_COLOR).count();
+    if let Some(index) = pixels.chunks_exact(4).position(|pixel| pixel != SOLID_COLOR) {
+        bail!(
+            "{wrong_pixels} of {} pixels were read back wrong, e.g. pixel ({}, {}) is {:?} instead of {SOLID_COLOR:?}",
+            SELF_TEST_SIZE * SELF_TEST_SIZE,
+            index as u32 % SELF_TEST_SIZE,
+            index as u32 / SELF_TEST_SIZE,
+            &pixels[index * 4..index * 4 + 4],
+        );
+    }
+
+    Ok(())
+}
+
+/// Async version of [`render_compute`] for callers running on an async runtime.
+///
+/// Waiting for the GPU polls the fence instead of blocking the thread, with a timer of its own,
+/// so any executor works and no runtime features are needed. Creating the device and compiling
+/// the pipeline still block while the future is first polled, as does waiting for the input upload
+/// and the whole render with `multi_gpu`.
+pub async fn render_compute_async(config: &RenderConfig) -> Result<RenderOutput>
+{
+    render_compute_inner(config, &mut |_| {}, &mut Timings::default()).await
+}
+
+async fn render_compute_inner<P>(config: &RenderConfig, progress: &mut P, timings: &mut Timings) -> Result<RenderOutput>
+where
+    P: FnMut(f32) + ?Sized,
+{
+    if config.multi_gpu {
+        let setup = Arc::new(RenderSetup::new(config, timings)?);
+        return render_multi_gpu(config, &setup, progress, timings);
+    }
+
+    let renderer = Renderer::new(config)?;
+    *timings += renderer.timings();
+
+    let image = renderer.render_frame(config.push_constants(config.time), progress, timings).await?;
+    renderer.write_frame_outputs()?;
+
+    Ok(image)
+}
+
+/// Renders the top half of the image on the best device and the bottom half on the second best
+/// at the same time, then stitches them together.
+///
+/// Each device reads its half back into host memory on its own, so the devices don't need to
+/// share any memory. Both halves render at the same time, so the longer of the two counts
+/// towards the dispatch and readback timings.
+fn render_multi_gpu<P>(config: &RenderConfig, setup: &Arc<RenderSetup>, progress: &mut P, timings: &mut Timings) -> Result<RenderOutput>
+where
+    P: FnMut(f32) + ?Sized,
+{
+    ensure!(
+        config.device_index.is_none() && config.device_uuid.is_none(),
+        "--device and --device-uuid can't be combined with --multi-gpu"
+    );
+    ensure!(!config.shader.is_volume(), "--multi-gpu doesn't support volumes");
+    ensure!(!config.shader.is_iterative(), "--multi-gpu doesn't support iterative shaders");
+    ensure!(setup.height >= 2, "--multi-gpu needs an image at least 2 pixels high");
+
+    let physical_devices = rank_physical_devices(&setup.instance, config.allow_software)?;
+    let [first, second, ..] = physical_devices.as_slice() else {
+        bail!("--multi-gpu needs two devices with a compute queue, found {}", physical_devices.len());
+    };
+
+    // Pipeline caches only work on the device that wrote them, so only the first device uses it
+    let second_config = RenderConfig {
+        pipeline_cache: None,
+        ..config.clone()
+    };
+
+    let split = setup.height / 2;
+    let renderers = [
+        Renderer::with_device(config, setup, first.clone(), 0..split, 1, timings)?,
+        Renderer::with_device(&second_config, setup, second.clone(), split..setup.height, 1, timings)?,
+    ];
+
+    let push_constants = config.push_constants(config.time);
+    let halves: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = renderers
+            .iter()
+            .map(|renderer| {
+                scope.spawn(move || {
+                    let start = Instant::now();
+                    let mut half_timings = Timings::default();
+                    let pixels = block_on(renderer.render_pixels(push_constants, &mut |_| {}, &mut half_timings))?;
+                    info!(
+                        "{} rendered rows {}..{} in {:.3} ms",
+                        renderer.device.physical_device().properties().device_name,
+                        renderer.band_y,
+                        renderer.band_y + renderer.band_height,
+                        start.elapsed().as_secs_f64() * 1000.0,
+                    );
+
+                    Ok((pixels, half_timings))
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err(anyhow!("a render thread panicked"))))
+            .collect()
+    });
+
+    let mut pixels = Vec::new();
+    for (index, half) in halves.into_iter().enumerate() {
+        let (half_pixels, half_timings) = half?;
+        pixels.extend(half_pixels);
+        timings.dispatch = timings.dispatch.max(half_timings.dispatch);
+        timings.readback = timings.readback.max(half_timings.readback);
+        progress((index + 1) as f32 / renderers.len() as f32);
+    }
+
+    let start = Instant::now();
+    let image = config.pixel_format.to_output(setup.width, setup.height, pixels);
+    timings.readback += start.elapsed();
+
+    image
+}
+
+/// Median of non-empty `samples` that are sorted in ascending order.
+fn median(samples: &[f64]) -> f64
+{
+    let count = samples.len();
+    if count.is_multiple_of(2) {
+        (samples[count / 2 - 1] + samples[count / 2]) / 2.0
+    } else {
+        samples[count / 2]
+    }
+}
+
+/// Logs min, median, mean, max and standard deviation of `samples` in milliseconds.
+fn log_statistics(samples: &mut [f64])
+{
+    samples.sort_by(f64::total_cmp);
+
+    let count = samples.len();
+    let median = median(samples);
+    let mean = samples.iter().sum::<f64>() / count as f64;
+    let variance = samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / count as f64;
+
+    info!(
+        "{count} runs: min {:.3} ms, median {median:.3} ms, mean {mean:.3} ms, max {:.3} ms, std dev {:.3} ms",
+        samples[0],
+        samples[count - 1],
+        variance.sqrt(),
+    );
+}
+
+/// Renders the same image `repeats` times after one warmup run and logs statistics of the
+/// dispatch times, then returns the image of the last run.
+///
+/// GPU times from timestamp queries are used when the queue supports them, otherwise the
+/// wall-clock time of submitting and waiting for the dispatch.
+pub fn render_benchmark(config: &RenderConfig, repeats: u32, progress: &mut dyn FnMut(f32)) -> Result<RenderOutput>
+{
+    ensure!(repeats > 0, "repeat count must be non-zero");
+    ensure!(!config.multi_gpu, "--multi-gpu doesn't support benchmarking");
+
+    let renderer = Renderer::new(config)?;
+    let (image, mut samples) = benchmark_runs(&renderer, config, repeats, &mut |run| {
+        progress((run + 1) as f32 / repeats as f32)
+    })?;
+    log_statistics(&mut samples);
+    renderer.write_frame_outputs()?;
+
+    Ok(image)
+}
+
+/// Renders `repeats` frames with `renderer` after an untimed warmup frame and returns the image
+/// of the last run with the dispatch time of every run in milliseconds.
+///
+/// The times come from timestamp queries when the queue supports them, otherwise they are the
+/// wall-clock time of submitting and waiting for the dispatch. `progress` gets the index of every
+/// finished run. When interrupted only the finished runs are returned.
+fn benchmark_runs(
+    renderer: &Renderer,
+    config: &RenderConfig,
+    repeats: u32,
+    progress: &mut dyn FnMut(u32),
+) -> Result<(RenderOutput, Vec<f64>)>
+{
+    let push_constants = config.push_constants(config.time);
+
+    // The warmup run pays for lazy driver initialisation and clock ramp-up, so it isn't counted
+    let mut image = block_on(renderer.render_frame(push_constants, &mut |_| {}, &mut Timings::default()))?;
+
+    let mut samples = Vec::with_capacity(repeats as usize);
+    let mut gpu_timed = true;
+    for run in 0..repeats {
+        let mut run_timings = Timings::default();
+        image = block_on(renderer.render_frame(push_constants, &mut |_| {}, &mut run_timings))?;
+
+        let sample = match run_timings.gpu_dispatch {
+            Some(gpu_dispatch) => gpu_dispatch,
+            None => {
+                gpu_timed = false;
+                run_timings.dispatch
+            }
+        };
+        samples.push(sample.as_secs_f64() * 1000.0);
+        progress(run);
+
+        if interrupted() && run + 1 < repeats {
+            renderer.wait_idle()?;
+            warn!("Interrupted after {} of {repeats} runs", run + 1);
+            break;
+        }
+    }
+
+    if !gpu_timed {
+        warn!("timestamps aren't supported, reporting wall-clock dispatch times instead");
+    }
+
+    Ok((image, samples))
+}
+
+/// Workgroup sizes [`benchmark_dispatch_shapes`] tries, the ones exceeding the device's limits
+/// are skipped.
+pub const DISPATCH_SHAPES: [[u32; 2]; 12] = [
+    [8, 8],
+    [16, 16],
+    [32, 32],
+    [16, 8],
+    [8, 16],
+    [32, 8],
+    [8, 32],
+    [32, 16],
+    [16, 32],
+    [64, 4],
+    [4, 64],
+    [64, 8],
+];
+
+/// Benchmarks every one of the [`DISPATCH_SHAPES`] that fits the device with `repeats` runs and
+/// returns each workgroup size with its median dispatch time in milliseconds, fastest first.
+///
+/// The pipeline is rebuilt with new specialization constants for every shape. They all have to
+/// render the same image, so each shape is checked against the first one before it counts.
+pub fn benchmark_dispatch_shapes(
+    config: &RenderConfig,
+    repeats: u32,
+    progress: &mut dyn FnMut(f32),
+) -> Result<Vec<([u32; 2], f64)>>
+{
+    ensure!(repeats > 0, "repeat count must be non-zero");
+    ensure!(!config.multi_gpu, "--multi-gpu doesn't support benchmarking");
+
+    let setup = Arc::new(RenderSetup::new(config, &mut Timings::default())?);
+    let physical_device = pick_physical_device(
+        &setup.instance,
+        config.device_index,
+        config.device_uuid,
+        config.allow_software,
+    )?;
+
+    let shapes: Vec<_> = DISPATCH_SHAPES
+        .into_iter()
+        .filter(|&shape| check_workgroup_size(&physical_device, shape).is_ok())
+        .collect();
+    ensure!(!shapes.is_empty(), "none of the workgroup sizes fit the device's limits");
+
+    let mut reference: Option<([u32; 2], RenderOutput)> = None;
+    let mut results = Vec::with_capacity(shapes.len());
+    for (index, &shape) in shapes.iter().enumerate() {
+        let shape_config = RenderConfig {
+            workgroup_size: shape,
+            ..config.clone()
+        };
+        let renderer = Renderer::with_device(
+            &shape_config,
+            &setup,
+            physical_device.clone(),
+            0..setup.height,
+            1,
+            &mut Timings::default(),
+        )?;
+
+        let (image, mut samples) = benchmark_runs(&renderer, &shape_config, repeats, &mut |run| {
+            progress((index as f32 + (run + 1) as f32 / repeats as f32) / shapes.len() as f32)
+        })?;
+        samples.sort_by(f64::total_cmp);
+        // Every shape renders the same image, so the outputs of the first one stand for all
+        if index == 0 {
+            renderer.write_frame_outputs()?;
+        }
+
+        match &reference {
+            Some(([reference_x, reference_y], reference_image)) => ensure!(
+                output_bytes(&image) == output_bytes(reference_image),
+                "workgroup size {}x{} rendered a different image than {reference_x}x{reference_y}",
+                shape[0],
+                shape[1],
+            ),
+            None => reference = Some((shape, image)),
+        }
+
+        results.push((shape, median(&samples)));
+
+        if interrupted() && index + 1 < shapes.len() {
+            warn!("Interrupted after {} of {} workgroup sizes", index + 1, shapes.len());
+            break;
+        }
+    }
+
+    results.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    info!("Median dispatch times by workgroup size, fastest first:");
+    let fastest = results[0].1;
+    for &([size_x, size_y], median) in &results {
+        info!(
+            "  {:>9} {median:>10.3} ms {:>6.2}x",
+            format!("{size_x}x{size_y}"),
+            median / fastest.max(f64::EPSILON),
+        );
+    }
+
+    Ok(results)
+}
+
+/// Renders `frames` images, advancing the `time` push constant by `time_step` every frame.
+///
+/// The pipeline, descriptor set and image are set up once and shared by all frames, only the
+/// command buffer is recorded again. Each image is handed to `on_frame` together with its index,
+/// and `progress` is called with the finished fraction of the whole animation after every tile.
+/// Returns the time spent in every stage, where creating the device and pipeline is only counted
+/// once and dispatch and readback add up over all frames. Stops early, once the device is idle,
+/// when [`interrupt::interrupted`] after a frame was handed to `on_frame`.
+pub fn render_frames(
+    config: &RenderConfig,
+    frames: u32,
+    time_step: f32,
+    mut on_frame: impl FnMut(u32, RenderOutput) -> Result<()>,
+    progress: &mut dyn FnMut(f32),
+) -> Result<Timings>
+{
+    ensure!(frames > 0, "frame count must be non-zero");
+    ensure!(!config.multi_gpu, "--multi-gpu doesn't support rendering frames");
+
+    let renderer = Renderer::new(config)?;
+    let mut timings = renderer.timings();
+
+    let start = Instant::now();
+    let mut rendered = 0;
+    for frame in 0..frames {
+        let push_constants = config.push_constants(config.time + frame as f32 * time_step);
+        let image = block_on(renderer.render_frame(
+            push_constants,
+            &mut |fraction| progress((frame as f32 + fraction) / frames as f32),
+            &mut timings,
+        ))?;
+        on_frame(frame, image)?;
+        rendered += 1;
+
+        // The frame that was just rendered is saved before stopping
+        if interrupted() && rendered < frames {
+            renderer.wait_idle()?;
+            warn!("Interrupted after {rendered} of {frames} frames");
+            break;
+        }
+    }
+
+    let total = start.elapsed();
+    info!(
+        "Rendered {rendered} frames in {:.3} s ({:.3} ms per frame)",
+        total.as_secs_f64(),
+        total.as_secs_f64() * 1000.0 / rendered as f64,
+    );
+    renderer.write_frame_outputs()?;
+
+    Ok(timings)
+}
+
+/// Number of renders [`render_many`] keeps on the GPU at once.
+#[cfg(feature = "image-output")]
+const RENDERS_IN_FLIGHT: usize = 2;
+
+/// Renders every config in turn and returns the images in the same order.
+///
+/// Configs that only differ in push constants from the one before share a renderer, which keeps
+/// `RENDERS_IN_FLIGHT` of them on the GPU at once through [`Renderer::dispatch_frames`], so one
+/// is dispatched while another one is read back. Throughput is logged at the end, with how many
+/// of the images were rendered that way and how many one at a time.
+#[cfg(feature = "image-output")]
+pub fn render_many(configs: &[RenderConfig]) -> Result<Vec<RgbaImage>>
+{
+    for config in configs {
+        ensure!(!config.multi_gpu, "--multi-gpu doesn't support batch renders");
+        ensure!(!config.mipmaps && !config.shader.is_volume(), "batch renders only return one image per config");
+        config.check_push_constants()?;
+    }
+
+    let start = Instant::now();
+    let mut images = Vec::with_capacity(configs.len());
+    let mut pipelined = 0;
+
+    for group in configs.chunk_by(|previous, config| previous.shares_renderer(config)) {
+        // Dropped before the next one is created, so only one device is alive at a time
+        let renderer = Renderer::with_frames_in_flight(&group[0], RENDERS_IN_FLIGHT.min(group.len()))?;
+        if renderer.keeps_frames_in_flight() {
+            pipelined += group.len();
+        }
+
+        let push_constants: Vec<_> = group.iter().map(|config| config.push_constants(config.time)).collect();
+        let frames = renderer.dispatch_frames(&push_constants)?;
+        images.extend(frames.into_iter().map(RenderOutput::into_rgba8));
+    }
+
+    let total = start.elapsed();
+    info!(
+        "Rendered {} images in {:.3} s ({:.1} images per second), {pipelined} of them with {RENDERS_IN_FLIGHT} in \
+         flight and {} one at a time",
+        configs.len(),
+        total.as_secs_f64(),
+        configs.len() as f64 / total.as_secs_f64(),
+        configs.len() - pipelined,
+    );
+
+    Ok(images)
+}
@@ -1,210 +1,894 @@
-use std::sync::Arc;
-
-use anyhow::{Context, Ok, Result};
-use image::{ImageBuffer, Rgba};
-use log::info;
-use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
-use vulkano::command_buffer::allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo};
-use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo};
-use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
-use vulkano::descriptor_set::{DescriptorSet, WriteDescriptorSet};
-use vulkano::device::{Device, DeviceCreateInfo, QueueCreateInfo, QueueFlags};
-use vulkano::format::Format;
-use vulkano::image::view::ImageView;
-use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
-use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
-use vulkano::pipeline::compute::ComputePipelineCreateInfo;
-use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
-use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo};
-use vulkano::sync::GpuFuture;
-use vulkano::{sync, VulkanLibrary};
-use vulkano::instance::{Instance, InstanceCreateFlags, InstanceCreateInfo};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
 
-fn main() -> Result<()>
+use anyhow::{bail, ensure, Context, Ok, Result};
+use clap::Parser;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, ImageFormat};
+use log::{info, LevelFilter};
+use sha2::{Digest, Sha256};
+use vulkan_tutorial::chain::{load_chain, render_chain};
+use vulkan_tutorial::compare::compare_with_reference;
+use vulkan_tutorial::device::{parse_uuid, pick_physical_device, print_compute_limits, print_devices};
+use vulkan_tutorial::format::print_storage_formats;
+use vulkan_tutorial::instance::{create_instance, validation_error_count, validation_layer_enabled};
+use vulkan_tutorial::interrupt::install_handler;
+use vulkan_tutorial::{benchmark_dispatch_shapes, render_benchmark, render_compute_timed, render_frames, render_mipmaps, render_volume, self_test, DownloadMode, ImageMemoryType, PixelFormat, RenderConfig, Renderer, ShaderKind, Swizzle, Timings};
+use vulkano::VulkanLibrary;
+
+/// Renders a compute shader into an image file.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Width of the rendered image in pixels
+    #[arg(long, default_value_t = 4096)]
+    width: u32,
+
+    /// Height of the rendered image in pixels
+    #[arg(long, default_value_t = 4096)]
+    height: u32,
+
+    /// Path the rendered image is saved to
+    #[arg(long, value_name = "PATH", default_value = "image.png")]
+    output: PathBuf,
+
+    /// Directory --output, the frames, slices, mip levels and --histogram are written into,
+    /// created if it doesn't exist
+    #[arg(long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+
+    /// Enable the Khronos validation layer if it is installed
+    #[arg(long)]
+    validation: bool,
+
+    /// Fail once the render is done if the validation layer reported any errors, or if it isn't
+    /// installed and so couldn't check the render, needs --validation or --debug-printf
+    #[arg(long)]
+    strict: bool,
+
+    /// Animation time passed to the shader
+    #[arg(long, default_value_t = 0.0)]
+    time: f32,
+
+    /// Scale factor of the gradient pattern
+    #[arg(long, default_value_t = 1.0)]
+    scale: f32,
+
+    /// Side length of the square compute workgroup
+    #[arg(long, value_name = "N", default_value_t = 8)]
+    workgroup_size: u32,
+
+    /// Render an animation of N frames saved as frame_0000.png, frame_0001.png, ...
+    /// or as a single animated GIF when --output ends in .gif
+    #[arg(long, value_name = "N")]
+    frames: Option<u32>,
+
+    /// Amount the time push constant advances by between frames
+    #[arg(long, default_value_t = 0.1)]
+    time_step: f32,
+
+    /// Frames per second of an animated GIF (25 by default), or the rate --preview is capped at
+    #[arg(long, value_name = "N")]
+    fps: Option<u32>,
+
+    /// How the image is copied from device to host memory
+    #[arg(long, value_enum, default_value_t = DownloadMode::Direct)]
+    download_mode: DownloadMode,
+
+    /// Kind of memory the storage image is allocated from, the chosen memory type is logged
+    #[arg(long, value_enum, default_value_t = ImageMemoryType::Device)]
+    memory_type: ImageMemoryType,
+
+    /// File the compiled pipeline is cached in between runs
+    #[arg(long, value_name = "PATH", default_value = "pipeline_cache.bin")]
+    pipeline_cache: PathBuf,
+
+    /// Storage format of the rendered image, use an .exr or .hdr output to keep float colours
+    #[arg(long, value_enum, default_value_t = PixelFormat::Rgba8)]
+    format: PixelFormat,
+
+    /// Channel order of the saved pixels, for tools that expect e.g. BGRA
+    #[arg(long, value_enum, default_value_t = Swizzle::Rgba)]
+    swizzle: Swizzle,
+
+    /// List the available devices and exit
+    #[arg(long)]
+    list_devices: bool,
+
+    /// Print the compute limits of the device that renders before starting
+    #[arg(long)]
+    show_limits: bool,
+
+    /// List which storage image formats the device supports and exit
+    #[arg(long)]
+    format_list: bool,
+
+    /// Render on the device with this index from --list-devices
+    #[arg(long, value_name = "INDEX")]
+    device: Option<usize>,
+
+    /// Render on the device with this UUID from --list-devices, which stays the same between runs
+    #[arg(long, value_name = "UUID", value_parser = parse_device_uuid, conflicts_with = "device")]
+    device_uuid: Option<[u8; 16]>,
+
+    /// Accept software (CPU) Vulkan implementations such as llvmpipe
+    #[arg(long)]
+    allow_software: bool,
+
+    /// Fill the readback buffers with zeros when they are created, the copy overwrites them anyway
+    #[arg(long)]
+    clear_buffer: bool,
+
+    /// Split the render between the two best devices, top half and bottom half
+    #[arg(long)]
+    multi_gpu: bool,
+
+    /// Split the render into tiles of at most this many pixels in width and height
+    #[arg(long, value_name = "PIXELS")]
+    tile_size: Option<u32>,
+
+    /// Render this many tiles at the same time, each on its own thread
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+
+    /// Count the luminance of the rendered pixels in 256 bins and write them to this CSV file
+    #[arg(long, value_name = "CSV")]
+    histogram: Option<PathBuf>,
+
+    /// Overwrite the last row with a checksum pattern in a second dispatch and fail if the
+    /// pattern read back doesn't match, which catches partial dispatches and missing barriers
+    #[arg(long)]
+    verify: bool,
+
+    /// Recolour the render by mapping the luminance of every pixel through a palette of 256
+    /// colours, a CSV file of r,g,b[,a] lines or a picture 256 pixels wide
+    #[arg(long, value_name = "PATH")]
+    palette: Option<PathBuf>,
+
+    /// Write the iteration count of every pixel of the Mandelbrot shader to a 16-bit grayscale
+    /// PNG, for analysing or recolouring the set offline
+    #[arg(long, value_name = "PATH")]
+    dump_iterations: Option<PathBuf>,
+
+    /// Log how long each stage of the render took, summed over all frames with --frames
+    #[arg(long)]
+    timings: bool,
+
+    /// Benchmark the dispatch over N runs after a warmup run, saving only the last image
+    #[arg(long, value_name = "N", conflicts_with_all = ["frames", "timings"])]
+    repeat: Option<u32>,
+
+    /// Time every workgroup size that fits the device over --repeat runs (10 by default) and list the fastest
+    #[arg(long, conflicts_with_all = ["frames", "mipmaps", "multi_gpu", "dry_run", "compare"])]
+    benchmark_dispatch_shapes: bool,
+
+    /// Clear the image to this colour before the dispatch, channels from 0.0 to 1.0
+    #[arg(long, value_name = "R,G,B,A", value_parser = parse_color)]
+    clear_color: Option<[f32; 4]>,
+
+    /// Number of slices the volume shader renders, each saved as slice_<N>.png
+    #[arg(long, value_name = "D", default_value_t = 1)]
+    depth: u32,
+
+    /// Generate the full mip chain and save each level as <output>_mip<N> instead
+    #[arg(long, conflicts_with_all = ["frames", "repeat", "multi_gpu"])]
+    mipmaps: bool,
+
+    /// Encode the shader's linear colours as sRGB, so PNGs and other 8/16 bit outputs look right
+    #[arg(long)]
+    srgb: bool,
+
+    /// Multiply the colour channels of every pixel by its alpha before saving, for compositing
+    /// tools that expect premultiplied alpha
+    #[arg(long)]
+    premultiply_alpha: bool,
+
+    /// Record each tile's dispatch into a secondary command buffer
+    #[arg(long)]
+    secondary_command_buffers: bool,
+
+    /// Record the pipeline barriers of each tile by hand instead of letting vulkano insert them
+    #[arg(long)]
+    explicit_barriers: bool,
+
+    /// Fail unless the device supports subgroup arithmetic operations in compute shaders
+    #[arg(long)]
+    require_subgroup_ops: bool,
+
+    /// Compare the rendered image with this reference and fail if it differs by more than --tolerance
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["frames", "mipmaps"])]
+    compare: Option<PathBuf>,
+
+    /// Largest error of any channel --compare accepts, channels range from 0.0 to 1.0
+    #[arg(long, default_value_t = 0.01, requires = "compare")]
+    tolerance: f32,
+
+    /// Print the SHA-256 of the rendered pixels, to check in scripts that the output stays the same
+    #[arg(long, conflicts_with_all = ["frames", "mipmaps", "benchmark_dispatch_shapes"])]
+    hash: bool,
+
+    /// Don't write --output, for runs that only need --hash or --compare
+    #[arg(long, conflicts_with_all = ["frames", "mipmaps", "benchmark_dispatch_shapes"])]
+    no_save: bool,
+
+    /// Show the render animating in a window until it's closed instead of saving it
+    #[cfg(feature = "preview")]
+    #[arg(long, conflicts_with_all = ["frames", "repeat", "mipmaps", "compare", "hash", "no_save", "benchmark_dispatch_shapes", "verify", "pipeline", "palette", "dump_iterations"])]
+    preview: bool,
+
+    /// Create the device, pipeline, image and descriptor set, then exit without rendering or saving
+    #[arg(long, conflicts_with_all = ["frames", "repeat", "compare", "multi_gpu"])]
+    dry_run: bool,
+
+    /// Render a tiny image of a known colour, check every pixel that is read back and print PASS or FAIL
+    #[arg(long, conflicts_with_all = ["frames", "repeat", "compare", "multi_gpu", "dry_run", "mipmaps"])]
+    self_test: bool,
+
+    /// Print the shader's debugPrintfEXT output through the validation layer, which gets enabled for it
+    #[arg(long)]
+    debug_printf: bool,
+
+    /// Report device memory that is still allocated when the renderer is torn down
+    #[arg(long)]
+    debug_resources: bool,
+
+    /// Log more, -v for debug and -vv for trace messages
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only log warnings and errors
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Picture the shader reads from binding 1, its size replaces --width and --height
+    #[arg(long, value_name = "PATH")]
+    input: Option<PathBuf>,
+
+    /// Scale --input to this size with the bilinear resize shader, which replaces --shader
+    #[arg(long, value_name = "WxH", value_parser = parse_size, requires = "input", conflicts_with = "shader")]
+    resize: Option<[u32; 2]>,
+
+    /// Run this precompiled SPIR-V compute shader, built like the --shader kind it stands in for
+    #[arg(long, value_name = "PATH")]
+    spirv: Option<PathBuf>,
+
+    /// Compile and run this GLSL compute shader at startup, built like the --shader kind it stands in for
+    #[arg(long, value_name = "PATH", conflicts_with = "spirv")]
+    glsl: Option<PathBuf>,
+
+    /// Entry point of the --spirv or --glsl shader to run
+    #[arg(long, value_name = "NAME", default_value = "main")]
+    entry_point: String,
+
+    /// Number of passes of an iterative shader such as life, each reading the previous result
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    passes: u32,
+
+    /// Submit the --passes in batches of K, waiting for each batch before recording the next,
+    /// instead of all of them at once
+    #[arg(long, value_name = "K")]
+    iterations_per_submit: Option<u32>,
+
+    /// JSON file listing the passes to chain, each naming a shader and the push constants it sets,
+    /// every pass reading the image the one before it wrote
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "shader", "spirv", "glsl", "entry_point", "passes", "iterations_per_submit", "resize", "frames", "repeat",
+            "mipmaps", "multi_gpu", "histogram", "verify", "palette", "dump_iterations", "benchmark_dispatch_shapes",
+            "dry_run", "self_test",
+            // The chain renders the whole image at once through its own device and readback
+            "tile_size", "threads", "memory_type", "download_mode", "clear_buffer", "clear_color", "depth",
+            "explicit_barriers", "secondary_command_buffers", "require_subgroup_ops", "premultiply_alpha",
+            "swizzle", "pipeline_cache", "timings", "debug_resources", "debug_printf",
+        ],
+    )]
+    pipeline: Option<PathBuf>,
+
+    /// Compute kernel to run
+    #[arg(long, value_enum, default_value_t = ShaderKind::Mandelbrot)]
+    shader: ShaderKind,
+
+    /// Blur radius in pixels
+    #[arg(long, default_value_t = 2)]
+    radius: u32,
+
+    /// Slope exaggeration of the normal map shader
+    #[arg(long, default_value_t = 1.0)]
+    strength: f32,
+
+    /// Seed of the noise shader, the output is bit-for-bit reproducible on the same GPU
+    #[arg(long, default_value_t = 0)]
+    seed: u32,
+
+    /// Real part of the point at the centre of the Mandelbrot view
+    #[arg(long, default_value_t = -0.75, allow_negative_numbers = true)]
+    center_x: f32,
+
+    /// Imaginary part of the point at the centre of the Mandelbrot view
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    center_y: f32,
+
+    /// Mandelbrot magnification, 1.0 shows the whole set
+    #[arg(long, default_value_t = 1.0)]
+    zoom: f32,
+
+    /// Mandelbrot iteration limit
+    #[arg(long, default_value_t = 200)]
+    iterations: u32,
+
+    /// Abort when a GPU submission takes longer than this many milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 5000)]
+    timeout_ms: u64,
+
+    /// Priority of the compute and transfer queues from 0.0 to 1.0, its effect is up to the driver
+    #[arg(long, value_name = "PRIORITY", default_value_t = 1.0)]
+    queue_priority: f32,
+}
+
+const SUPPORTED_EXTENSIONS: &str = "png, jpg, jpeg, bmp, tif, tiff, exr, hdr, raw, bin";
+
+/// Name, size in bytes and bytes of the pixels of `image`.
+///
+/// Rows are tightly packed from the top, channels are RGBA and multi-byte values little-endian.
+fn raw_pixels(image: &DynamicImage) -> (&'static str, u32, Vec<u8>)
 {
-    env_logger::init();
+    match image {
+        DynamicImage::ImageRgba8(image) => ("rgba8", 4, image.as_raw().clone()),
+        DynamicImage::ImageRgba16(image) => {
+            ("rgba16", 8, image.as_raw().iter().flat_map(|channel| channel.to_le_bytes()).collect())
+        }
+        DynamicImage::ImageRgba32F(image) => {
+            ("rgba32f", 16, image.as_raw().iter().flat_map(|channel| channel.to_le_bytes()).collect())
+        }
+        _ => ("rgba8", 4, image.to_rgba8().into_raw()),
+    }
+}
 
-    info!("Start of the program");
+/// SHA-256 of the [`raw_pixels`] of `image` in lowercase hex, which is the same on every host for
+/// the same pixels.
+fn pixel_hash(image: &DynamicImage) -> String
+{
+    let (_, _, data) = raw_pixels(image);
+
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Writes the pixels of `image` unencoded to `path`, plus a `<path>.json` sidecar describing them.
+///
+/// The pixels are laid out like [`raw_pixels`] returns them.
+fn save_raw(image: &DynamicImage, path: &Path) -> Result<()>
+{
+    let (format, bytes_per_pixel, data) = raw_pixels(image);
+
+    fs::write(path, data).with_context(|| format!("failed to write raw pixels to {}", path.display()))?;
+
+    let mut sidecar_path = path.as_os_str().to_owned();
+    sidecar_path.push(".json");
+    let sidecar_path = PathBuf::from(sidecar_path);
+    let sidecar = format!(
+        "{{\n  \"width\": {},\n  \"height\": {},\n  \"format\": \"{format}\",\n  \"bytes_per_pixel\": {bytes_per_pixel}\n}}\n",
+        image.width(),
+        image.height(),
+    );
+
+    fs::write(&sidecar_path, sidecar)
+        .with_context(|| format!("failed to write the raw sidecar to {}", sidecar_path.display()))
+}
+
+/// The parameters of a render at `time` as the keywords and texts of PNG tEXt chunks, so the
+/// image records how it was made.
+fn render_metadata(config: &RenderConfig, time: f32) -> Vec<(&'static str, String)>
+{
+    let shader = match (&config.spirv, &config.glsl) {
+        (Some(path), _) | (None, Some(path)) => path.display().to_string(),
+        (None, None) => format!("{:?}", config.shader),
+    };
+
+    let mut metadata = vec![
+        ("Software", format!("vulkan_tutorial {}", env!("CARGO_PKG_VERSION"))),
+        ("Shader", shader),
+        ("Format", format!("{:?}", config.pixel_format)),
+        ("Time", time.to_string()),
+        ("Scale", config.scale.to_string()),
+        ("Seed", config.seed.to_string()),
+        ("Center", format!("{}, {}", config.center[0], config.center[1])),
+        ("Zoom", config.zoom.to_string()),
+        ("Iterations", config.iterations.to_string()),
+        ("Radius", config.radius.to_string()),
+        ("Strength", config.strength.to_string()),
+        ("Passes", config.passes.to_string()),
+    ];
+    if let Some(input) = &config.input {
+        metadata.push(("Input", input.display().to_string()));
+    }
+    if let Some(palette) = &config.palette {
+        metadata.push(("Palette", palette.display().to_string()));
+    }
+
+    metadata
+}
+
+/// Encodes an 8 or 16-bit RGBA `image` as a PNG with a tEXt chunk for every entry of `metadata`.
+fn save_png_with_metadata(image: &DynamicImage, path: &Path, metadata: &[(&'static str, String)]) -> Result<()>
+{
+    let (bit_depth, data) = match image {
+        DynamicImage::ImageRgba8(image) => (png::BitDepth::Eight, image.as_raw().clone()),
+        // PNG stores 16-bit samples big-endian
+        DynamicImage::ImageRgba16(image) => {
+            (png::BitDepth::Sixteen, image.as_raw().iter().flat_map(|sample| sample.to_be_bytes()).collect())
+        }
+        _ => (png::BitDepth::Eight, image.to_rgba8().into_raw()),
+    };
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(bit_depth);
+    encoder
+        .add_text_chunk("Size".to_owned(), format!("{}x{}", image.width(), image.height()))
+        .context("failed to add the size to the PNG")?;
+    // tEXt only holds Latin-1, paths with other characters go into a UTF-8 iTXt chunk instead
+    for (keyword, text) in metadata {
+        let added = if text.is_ascii() {
+            encoder.add_text_chunk((*keyword).to_owned(), text.clone())
+        } else {
+            encoder.add_itxt_chunk((*keyword).to_owned(), text.clone())
+        };
+        added.with_context(|| format!("failed to add the {keyword} to the PNG"))?;
+    }
+
+    let mut writer = encoder.write_header().context("failed to write the PNG header")?;
+    writer.write_image_data(&data).context("failed to encode the PNG")?;
+    writer.finish().context("failed to finish the PNG")?;
+
+    fs::write(path, png).with_context(|| format!("failed to save an image to {}", path.display()))
+}
+
+fn save_image(image: &DynamicImage, path: &Path, metadata: &[(&'static str, String)]) -> Result<()>
+{
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+        .with_context(|| format!("output path has no extension, expected one of: {SUPPORTED_EXTENSIONS}"))?;
+
+    let format = match extension.as_str() {
+        "raw" | "bin" => return save_raw(image, path),
+        "png" => ImageFormat::Png,
+        "jpg" | "jpeg" => ImageFormat::Jpeg,
+        "bmp" => ImageFormat::Bmp,
+        "tif" | "tiff" => ImageFormat::Tiff,
+        "exr" => ImageFormat::OpenExr,
+        "hdr" => ImageFormat::Hdr,
+        _ => bail!("unsupported output extension {extension:?}, expected one of: {SUPPORTED_EXTENSIONS}"),
+    };
+
+    // Convert to the closest channel type each encoder accepts
+    let image = match (format, image) {
+        // JPEG has no alpha channel, so it has to be dropped before encoding
+        (ImageFormat::Jpeg, _) => DynamicImage::ImageRgb8(image.to_rgb8()),
+        (ImageFormat::Bmp, _) => DynamicImage::ImageRgba8(image.to_rgba8()),
+        (ImageFormat::Png | ImageFormat::Tiff, DynamicImage::ImageRgba32F(_)) => {
+            DynamicImage::ImageRgba16(image.to_rgba16())
+        }
+        (ImageFormat::OpenExr, _) => DynamicImage::ImageRgba32F(image.to_rgba32f()),
+        // Radiance RGBE has no alpha channel either
+        (ImageFormat::Hdr, _) => DynamicImage::ImageRgb32F(image.to_rgb32f()),
+        _ => image.clone(),
+    };
+
+    if format == ImageFormat::Png {
+        return save_png_with_metadata(&image, path, metadata);
+    }
+    info!("Only PNG files hold the render parameters, saving {} without them", path.display());
+
+    let result = image.save_with_format(path, format);
+
+    result.with_context(|| format!("failed to save an image to {}", path.display()))
+}
 
-    let library = VulkanLibrary::new().context("no local Vulkan library/DLL")?;
-    let instance = Instance::new(
-        library,
-        InstanceCreateInfo {
-            flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
-            ..Default::default()
-        },
-    )
-    .context("failed to create instance")?;
-
-    let physical_device = instance
-        .enumerate_physical_devices()
-        .context("could not enumerate physical devices")?
-        .next()
-        .context("no devices available")?;
-
-    let queue_family_index = physical_device
-        .queue_family_properties()
-        .iter()
-        .enumerate()
-        .position(|(_queue_family_index, queue_family_properties)| {
-            queue_family_properties.queue_flags.contains(QueueFlags::GRAPHICS)
+/// Trades the quality of the palette GIF frames are quantized to for encoding speed, from 1
+/// (best) to 30 (fastest), 10 is what the `gif` crate recommends.
+const GIF_SPEED: i32 = 10;
+
+/// Encodes each frame handed to it into one animated GIF that loops forever.
+struct GifAnimation<'a> {
+    encoder: GifEncoder<&'a mut Vec<u8>>,
+    delay: Delay,
+}
+
+impl<'a> GifAnimation<'a> {
+    fn new(gif: &'a mut Vec<u8>, fps: u32) -> Result<Self>
+    {
+        ensure!(fps > 0, "--fps must be non-zero");
+
+        let mut encoder = GifEncoder::new_with_speed(gif, GIF_SPEED);
+        encoder.set_repeat(Repeat::Infinite).context("failed to make the GIF loop")?;
+
+        Ok(Self {
+            encoder,
+            delay: Delay::from_numer_denom_ms(1000, fps),
         })
-        .context("couldn't find a graphical queue family")? as u32;
-
-    let (device, mut queues) = Device::new(
-        physical_device,
-        DeviceCreateInfo {
-            queue_create_infos: vec![QueueCreateInfo {
-                queue_family_index,
-                ..Default::default()
-            }],
-            ..Default::default()
-        },
-    )
-    .context("failed to create device")?;
-
-    let queue = queues.next().unwrap();
-
-    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(
-        device.clone()
-    ));
-
-    mod compute_shader {
-        vulkano_shaders::shader!{
-            ty: "compute",
-            path: "src/shaders/compute.comp"
-        }
-    }
-
-    let shader = compute_shader::load(device.clone())
-        .context("failed to load a compute shader")?;
-
-    let compute_shader = shader.entry_point("main").unwrap();
-    let stage = PipelineShaderStageCreateInfo::new(compute_shader);
-    let layout = PipelineLayout::new(
-        device.clone(),
-        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
-            .into_pipeline_layout_create_info(device.clone())
-            .context("failed to create PipelineLayoutCreateInfo")?
-    )
-    .context("failed to create a new PipelineLayout")?;
-
-    let compute_pipeline = ComputePipeline::new(
-        device.clone(),
-        None,
-        ComputePipelineCreateInfo::stage_layout(stage, layout)
-    )
-    .context("failed to create a new ComputePipeline")?;
-
-    let descriptor_set_allocator = Arc::new(
-        StandardDescriptorSetAllocator::new(device.clone(), Default::default())
+    }
+
+    /// Quantizes `image` to the 256 colour palette GIF frames are limited to and appends it.
+    fn add_frame(&mut self, image: &DynamicImage) -> Result<()>
+    {
+        self.encoder
+            .encode_frame(Frame::from_parts(image.to_rgba8(), 0, 0, self.delay))
+            .context("failed to encode a GIF frame")
+    }
+}
+
+/// Inserts `_mip{level}` before the extension of `output`, so `out.png` becomes `out_mip1.png`.
+fn mip_path(output: &Path, level: usize) -> PathBuf
+{
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match output.extension() {
+        Some(extension) => format!("{stem}_mip{level}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_mip{level}"),
+    };
+
+    output.with_file_name(file_name)
+}
+
+/// Logs how many errors the validation layer reported when it was enabled, and fails with
+/// `--strict` if there were any or the layer wasn't enabled or installed. Errors from the layer don't stop
+/// the render by themselves.
+fn report_validation_errors(args: &Args) -> Result<()>
+{
+    // Either flag enables the layer, so --strict is checked here rather than by clap's `requires`
+    let validating = args.validation || args.debug_printf;
+    ensure!(
+        !args.strict || validating,
+        "--strict fails on the validation layer's errors, it needs --validation or --debug-printf to enable the layer"
     );
+    if !validating {
+        return Ok(());
+    }
+
+    ensure!(
+        !args.strict || validation_layer_enabled(),
+        "--strict needs the validation layer, which isn't installed, so nothing was validated"
+    );
+
+    let errors = validation_error_count();
+    info!("The validation layer reported {errors} errors");
+    if args.strict && errors > 0 {
+        bail!("the validation layer reported {errors} errors, failing because of --strict");
+    }
+
+    Ok(())
+}
+
+/// Creates `dir` if it's missing and checks that files can be written into it, so a long render
+/// doesn't fail only once it's saved.
+fn prepare_output_dir(dir: &Path) -> Result<()>
+{
+    fs::create_dir_all(dir).with_context(|| format!("failed to create the output directory {}", dir.display()))?;
+
+    let probe = dir.join(format!(".write_test_{}", process::id()));
+    fs::write(&probe, []).with_context(|| format!("the output directory {} isn't writable", dir.display()))?;
+    fs::remove_file(&probe).with_context(|| format!("failed to remove {}", probe.display()))
+}
+
+/// Parses an `R,G,B,A` colour with channels from 0.0 to 1.0.
+fn parse_color(value: &str) -> Result<[f32; 4], String>
+{
+    let channels = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<f32>().map_err(|error| format!("invalid channel {channel:?}: {error}")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    channels
+        .try_into()
+        .map_err(|channels: Vec<f32>| format!("expected 4 channels as R,G,B,A, got {}", channels.len()))
+}
+
+fn parse_size(value: &str) -> Result<[u32; 2], String>
+{
+    let (width, height) = value.split_once('x').ok_or_else(|| format!("expected a size as WxH, got {value:?}"))?;
+    let parse = |side: &str| side.trim().parse::<u32>().map_err(|error| format!("invalid side {side:?}: {error}"));
+
+    parse(width).and_then(|width| parse(height).map(|height| [width, height]))
+}
+
+fn parse_device_uuid(value: &str) -> Result<[u8; 16], String>
+{
+    parse_uuid(value).map_err(|error| error.to_string())
+}
+
+/// Logs the error of `image` against the `reference` image and fails when it exceeds `tolerance`.
+fn check_against_reference(image: &DynamicImage, reference: &Path, tolerance: f32) -> Result<()>
+{
+    let diff = compare_with_reference(image, reference)?;
+    info!("Mean error (RGBA): {:.5?}, max error: {:.5?}", diff.mean_error, diff.max_error);
+
+    if diff.max() > tolerance {
+        bail!(
+            "the image differs from {} by up to {:.5}, more than the tolerance of {tolerance}",
+            reference.display(),
+            diff.max(),
+        );
+    }
+    info!("The image matches {} within {tolerance}", reference.display());
+
+    Ok(())
+}
+
+/// Draws a progress bar on stderr, finishing the line once `fraction` reaches 1.0.
+fn print_progress(fraction: f32)
+{
+    const BAR_WIDTH: usize = 40;
+
+    let filled = (fraction * BAR_WIDTH as f32).round() as usize;
+    eprint!("\r[{}{}] {:3.0}%", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled), fraction * 100.0);
+    if fraction >= 1.0 {
+        eprintln!();
+    }
+}
+
+fn main() -> Result<()>
+{
+    let args = Args::parse();
+
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+
+    // RUST_LOG is parsed last, so it still overrides the flags
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .init();
+
+    info!("Start of the program");
+
+    if args.list_devices {
+        let library = VulkanLibrary::new().context("no local Vulkan library/DLL")?;
+        let instance = create_instance(library, false, false)?;
+
+        return print_devices(&instance);
+    }
+
+    if args.show_limits {
+        let library = VulkanLibrary::new().context("no local Vulkan library/DLL")?;
+        let instance = create_instance(library, false, false)?;
+        let physical_device = pick_physical_device(&instance, args.device, args.device_uuid, args.allow_software)?;
+
+        print_compute_limits(&physical_device);
+    }
+
+    if args.format_list {
+        let library = VulkanLibrary::new().context("no local Vulkan library/DLL")?;
+        let instance = create_instance(library, false, false)?;
+        let physical_device = pick_physical_device(&instance, args.device, args.device_uuid, args.allow_software)?;
+
+        return print_storage_formats(&physical_device);
+    }
+
+    // Relative paths are resolved against the output directory, a missing one leaves them as they are
+    let output_dir = args.output_dir.as_deref().unwrap_or(Path::new(""));
+    let output = output_dir.join(&args.output);
+
+    let config = RenderConfig {
+        width: args.width,
+        height: args.height,
+        validation: args.validation,
+        debug_printf: args.debug_printf,
+        time: args.time,
+        scale: args.scale,
+        workgroup_size: [args.workgroup_size; 2],
+        download_mode: args.download_mode,
+        memory_type: args.memory_type,
+        pipeline_cache: Some(args.pipeline_cache.clone()),
+        pixel_format: args.format,
+        swizzle: args.swizzle,
+        device_index: args.device,
+        device_uuid: args.device_uuid,
+        allow_software: args.allow_software,
+        clear_buffer: args.clear_buffer,
+        input: args.input.clone(),
+        resize: args.resize,
+        shader: if args.resize.is_some() { ShaderKind::Resize } else { args.shader },
+        radius: args.radius,
+        strength: args.strength,
+        seed: args.seed,
+        center: [args.center_x, args.center_y],
+        zoom: args.zoom,
+        iterations: args.iterations,
+        timeout: Duration::from_millis(args.timeout_ms),
+        queue_priority: args.queue_priority,
+        multi_gpu: args.multi_gpu,
+        clear_color: args.clear_color,
+        mipmaps: args.mipmaps,
+        depth: args.depth,
+        secondary_command_buffers: args.secondary_command_buffers,
+        srgb: args.srgb,
+        premultiply_alpha: args.premultiply_alpha,
+        explicit_barriers: args.explicit_barriers,
+        require_subgroup_ops: args.require_subgroup_ops,
+        tile_size: args.tile_size,
+        threads: args.threads,
+        histogram: args.histogram.as_ref().map(|path| output_dir.join(path)),
+        verify: args.verify,
+        palette: args.palette.clone(),
+        dump_iterations: args.dump_iterations.as_ref().map(|path| output_dir.join(path)),
+        debug_resources: args.debug_resources,
+        passes: args.passes,
+        iterations_per_submit: args.iterations_per_submit,
+        spirv: args.spirv.clone(),
+        glsl: args.glsl.clone(),
+        entry_point: args.entry_point.clone(),
+    };
+
+    // PNGs and the other 8/16 bit outputs are written without a colour profile, which viewers read
+    // as sRGB, while EXR and Radiance HDR store linear colours
+    let output_is_exr = output.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("exr"));
+    let output_is_hdr = output.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+    if args.srgb && (output_is_exr || output_is_hdr) {
+        bail!("--srgb output can't be saved as .exr or .hdr, which store linear colours");
+    }
+    if output_is_hdr && args.format != PixelFormat::Rgba32f {
+        bail!("a .hdr output holds float colours, render it with --format rgba32f");
+    }
+
+    if args.compare.is_some() && args.shader.is_volume() {
+        bail!("--compare doesn't support volumes");
+    }
+    if (args.hash || args.no_save) && args.shader.is_volume() {
+        bail!("--hash and --no-save don't support volumes");
+    }
+
+    if args.self_test {
+        if let Err(error) = self_test(&config) {
+            println!("FAIL");
+            return Err(error);
+        }
+
+        println!("PASS");
+        return report_validation_errors(&args);
+    }
+
+    if args.dry_run {
+        let renderer = Renderer::new(&config)?;
+        info!("Dry run: setup succeeded, skipping the dispatch and the output");
+
+        if args.timings {
+            renderer.timings().log();
+        }
+        drop(renderer);
+
+        return report_validation_errors(&args);
+    }
+
+    #[cfg(feature = "preview")]
+    if args.preview {
+        vulkan_tutorial::preview::run_preview(&config, args.fps)?;
+        return report_validation_errors(&args);
+    }
+
+    if let Some(dir) = &args.output_dir {
+        prepare_output_dir(dir)?;
+    }
+
+    // Long runs stop cleanly between frames, everything else finishes too quickly to bother
+    if args.frames.is_some() || args.repeat.is_some() || args.benchmark_dispatch_shapes {
+        install_handler()?;
+    }
+
+    let metadata = render_metadata(&config, config.time);
+
+    if let Some(frames) = args.frames {
+        let output_is_gif = output.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gif"));
+
+        // The encoder writes into memory, dropping a file writer would swallow the errors of
+        // its last write
+        let mut gif = Vec::new();
+        let mut animation = output_is_gif.then(|| GifAnimation::new(&mut gif, args.fps.unwrap_or(25))).transpose()?;
+
+        let mut save = Duration::ZERO;
+        let mut timings = render_frames(&config, frames, args.time_step, |frame, image| {
+            let save_start = Instant::now();
+            match &mut animation {
+                Some(animation) => animation.add_frame(&image)?,
+                None => {
+                    let metadata = render_metadata(&config, config.time + frame as f32 * args.time_step);
+                    save_image(&image, &output_dir.join(format!("frame_{frame:04}.png")), &metadata)?
+                }
+            }
+            save += save_start.elapsed();
+
+            Ok(())
+        }, &mut print_progress)?;
+
+        // Dropping the encoder writes the GIF's trailer
+        if output_is_gif {
+            let save_start = Instant::now();
+            drop(animation);
+            fs::write(&output, &gif)
+                .with_context(|| format!("failed to save the animation to {}", output.display()))?;
+            save += save_start.elapsed();
+        }
+        timings.save = save;
+
+        if args.timings {
+            timings.log();
+        }
+    } else if let Some(path) = &args.pipeline {
+        let passes = load_chain(path)?;
+        let image = render_chain(&config, &passes)?;
+
+        if !args.no_save {
+            // The shader and push constants of each pass are in the pipeline file
+            let mut metadata = render_metadata(&config, config.time);
+            metadata.retain(|&(keyword, _)| matches!(keyword, "Software" | "Format" | "Input"));
+            metadata.push(("Pipeline", path.display().to_string()));
+            save_image(&image, &output, &metadata)?;
+        }
+        if args.hash {
+            println!("{}", pixel_hash(&image));
+        }
+
+        if let Some(reference) = &args.compare {
+            check_against_reference(&image, reference, args.tolerance)?;
+        }
+    } else if args.shader.is_volume() {
+        let slices = render_volume(&config, &mut print_progress)?;
+        for (slice, image) in slices.iter().enumerate() {
+            save_image(image, &output_dir.join(format!("slice_{slice:03}.png")), &metadata)?;
+        }
+    } else if args.mipmaps {
+        let levels = render_mipmaps(&config, &mut print_progress)?;
+        for (level, image) in levels.iter().enumerate() {
+            save_image(image, &mip_path(&output, level), &metadata)?;
+        }
+    } else if args.benchmark_dispatch_shapes {
+        benchmark_dispatch_shapes(&config, args.repeat.unwrap_or(10), &mut print_progress)?;
+    } else if let Some(repeats) = args.repeat {
+        let image = render_benchmark(&config, repeats, &mut print_progress)?;
+        if !args.no_save {
+            save_image(&image, &output, &metadata)?;
+        }
+        if args.hash {
+            println!("{}", pixel_hash(&image));
+        }
+
+        if let Some(reference) = &args.compare {
+            check_against_reference(&image, reference, args.tolerance)?;
+        }
+    } else {
+        let mut timings = Timings::default();
+        let image = render_compute_timed(&config, &mut print_progress, &mut timings)?;
+
+        if !args.no_save {
+            let save_start = Instant::now();
+            save_image(&image, &output, &metadata)?;
+            timings.save = save_start.elapsed();
+        }
+        if args.hash {
+            println!("{}", pixel_hash(&image));
+        }
+
+        if args.timings {
+            timings.log();
+        }
+
+        if let Some(reference) = &args.compare {
+            check_against_reference(&image, reference, args.tolerance)?;
+        }
+    }
 
-    let width = 960 * 4;
-    let height = 720 * 4;
-
-    let image = Image::new(
-        memory_allocator.clone(),
-        ImageCreateInfo {
-            image_type: ImageType::Dim2d,
-            format: Format::R8G8B8A8_UNORM,
-            extent: [width, height, 1],
-            usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
-            ..Default::default()
-        },
-        AllocationCreateInfo {
-            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
-            ..Default::default()
-        },
-    )
-    .context("failed to create an image")?;
-
-    let view = ImageView::new_default(image.clone())
-        .context("failed to create an ImageView")?;
-
-    let layout = compute_pipeline
-        .layout()
-        .set_layouts()
-        .get(0)
-        .context("failed to return a layout")?;
-    let set = DescriptorSet::new(
-        descriptor_set_allocator,
-        layout.clone(),
-        [WriteDescriptorSet::image_view(0, view.clone())],
-        []
-    ).context("failed to create a set")?;
-
-    let buf = Buffer::from_iter(
-        memory_allocator.clone(),
-        BufferCreateInfo {
-            usage: BufferUsage::TRANSFER_DST,
-            ..Default::default()
-        },
-        AllocationCreateInfo {
-            memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
-            ..Default::default()
-        },
-        (0..width * height * 4).map(|_| 0u8),
-    )
-    .context("failed to create a buffer from an iterator")?;
-
-    let command_buffer_allocator = Arc::new(
-        StandardCommandBufferAllocator::new(
-            device.clone(),
-            StandardCommandBufferAllocatorCreateInfo::default(),
-    ));
-
-    let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
-        command_buffer_allocator,
-        queue.queue_family_index(),
-        CommandBufferUsage::OneTimeSubmit,
-    )
-    .context("failed to create an AutoCommandBufferBuilder")?;
-
-    command_buffer_builder
-        .bind_pipeline_compute(compute_pipeline.clone())
-        .context("failed to bind a compute pipeline to a command buffer")?
-        .bind_descriptor_sets(
-            PipelineBindPoint::Compute,
-            compute_pipeline.layout().clone(),
-            0,
-            set,
-        )
-        .context("failed to bind descriptor sets to a command buffer")?;
-
-    unsafe {
-        command_buffer_builder
-            .dispatch([width / 8, height / 8, 1])
-            .context("failed to dispatch work_group_counts")?;
-    }
-
-    command_buffer_builder
-        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
-            image.clone(),
-            buf.clone(),
-        ))
-        .context("failed to copy an image to a buffer")?;
-    
-    let command_buffer = command_buffer_builder
-        .build()
-        .context("failed to build a PrimaryAutoCommandBuffer")?;
-
-    let future = sync::now(device.clone())
-        .then_execute(queue.clone(), command_buffer.clone())
-        .context("failed to execute a command buffer after this future")?
-        .then_signal_fence_and_flush()
-        .context("failed to signal a fence after this future and flush")?;
-
-    future.wait(None).context("failed to block current thread")?;
-
-    let buffer_content = buf.read().context("failed to read buffer")?;
-    let image = 
-        ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, &buffer_content[..])
-    .context("failed to construct an ImageBuffer")?;
-
-    image.save("image.png").context("failed to save an image")?;
+    report_validation_errors(&args)?;
 
     info!("Everything succeeded!");
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,139 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::{anyhow, Result};
+use vulkano::buffer::AllocateBufferError;
+use vulkano::image::AllocateImageError;
+use vulkano::memory::allocator::MemoryAllocatorError;
+use vulkano::{Validated, VulkanError};
+
+/// What to try when the resources of a render don't fit in memory.
+const ADVICE: &str = "lower --width and --height (or --depth of a volume), or use a smaller --format such as rgba8";
+
+/// What to try when the device was lost while running a shader.
+const DEVICE_LOST_ADVICE: &str = "lower --iterations, --passes or --radius, render smaller tiles with --tile-size, \
+                                  or check the shader for loops that never end";
+
+/// The device was lost during a submission, usually because the driver reset a GPU that ran a
+/// shader for too long.
+///
+/// Returned instead of the generic submission error, so callers can tell it apart with
+/// `anyhow::Error::downcast_ref`. The device can't be used afterwards, a new renderer has to be
+/// created.
+#[derive(Debug)]
+pub struct DeviceLost {
+    /// What was being done when the device was lost
+    pub context: String,
+}
+
+impl Display for DeviceLost {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        write!(
+            f,
+            "{}: the device was lost, the driver probably reset the GPU because a shader ran too long or \
+             never finished, {DEVICE_LOST_ADVICE}",
+            self.context,
+        )
+    }
+}
+
+impl Error for DeviceLost {}
+
+/// Vulkano errors that may have been caused by running out of memory.
+pub trait OutOfMemory {
+    /// The memory that ran out, `None` when the error has another cause.
+    fn exhausted_memory(&self) -> Option<&'static str>;
+
+    /// Whether the error means the device was lost, which only submissions and waits report.
+    fn device_lost(&self) -> bool
+    {
+        false
+    }
+}
+
+impl OutOfMemory for VulkanError {
+    fn exhausted_memory(&self) -> Option<&'static str>
+    {
+        match self {
+            VulkanError::OutOfDeviceMemory => Some("GPU"),
+            VulkanError::OutOfHostMemory => Some("host"),
+            _ => None,
+        }
+    }
+
+    fn device_lost(&self) -> bool
+    {
+        matches!(self, VulkanError::DeviceLost)
+    }
+}
+
+impl<E: OutOfMemory> OutOfMemory for Validated<E> {
+    fn exhausted_memory(&self) -> Option<&'static str>
+    {
+        match self {
+            Validated::Error(error) => error.exhausted_memory(),
+            Validated::ValidationError(_) => None,
+        }
+    }
+
+    fn device_lost(&self) -> bool
+    {
+        matches!(self, Validated::Error(error) if error.device_lost())
+    }
+}
+
+impl OutOfMemory for MemoryAllocatorError {
+    fn exhausted_memory(&self) -> Option<&'static str>
+    {
+        match self {
+            MemoryAllocatorError::AllocateDeviceMemory(error) => error.exhausted_memory(),
+            _ => None,
+        }
+    }
+}
+
+impl OutOfMemory for AllocateImageError {
+    fn exhausted_memory(&self) -> Option<&'static str>
+    {
+        match self {
+            AllocateImageError::CreateImage(error) | AllocateImageError::BindMemory(error) => error.exhausted_memory(),
+            AllocateImageError::AllocateMemory(error) => error.exhausted_memory(),
+        }
+    }
+}
+
+impl OutOfMemory for AllocateBufferError {
+    fn exhausted_memory(&self) -> Option<&'static str>
+    {
+        match self {
+            AllocateBufferError::CreateBuffer(error) | AllocateBufferError::BindMemory(error) => error.exhausted_memory(),
+            AllocateBufferError::AllocateMemory(error) => error.exhausted_memory(),
+        }
+    }
+}
+
+/// Adds context to allocation and submission errors like [`anyhow::Context`], replacing the
+/// vulkano error with advice when it ran out of memory, and with a [`DeviceLost`] when the device
+/// was lost.
+pub trait MemoryContext<T> {
+    fn memory_context<C>(self, context: C) -> Result<T>
+    where
+        C: Display + Send + Sync + 'static;
+}
+
+impl<T, E> MemoryContext<T> for Result<T, E>
+where
+    E: OutOfMemory + Error + Send + Sync + 'static,
+{
+    fn memory_context<C>(self, context: C) -> Result<T>
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        self.map_err(|error| match error.exhausted_memory() {
+            Some(memory) => anyhow!("{context}: the {memory} ran out of memory, {ADVICE}"),
+            None if error.device_lost() => anyhow::Error::new(DeviceLost { context: context.to_string() }),
+            None => anyhow::Error::new(error).context(context),
+        })
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Ok, Result};
+
+/// Number of colours in the lookup table of `--palette`, one per 8-bit luminance level.
+pub const LUT_ENTRIES: usize = 256;
+
+/// Colours the luminance of each pixel is mapped to by the `--palette` pass, as RGBA in 0.0..=1.0.
+pub type PaletteLut = [[f32; 4]; LUT_ENTRIES];
+
+/// Parses a palette of [`LUT_ENTRIES`] lines of `r,g,b` or `r,g,b,a` bytes, the first line for
+/// black and the last for white.
+///
+/// Blank lines and lines starting with `#` are skipped, a missing alpha is opaque.
+pub fn parse_palette_csv(text: &str) -> Result<PaletteLut>
+{
+    let mut lut = [[0.0; 4]; LUT_ENTRIES];
+    let mut entries = 0;
+
+    let lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+    for (line_index, line) in lines {
+        let channels = line
+            .split(',')
+            .map(|channel| channel.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("line {} of the palette isn't a list of bytes: {line:?}", line_index + 1))?;
+        ensure!(
+            matches!(channels.len(), 3 | 4),
+            "line {} of the palette has {} channels, expected r,g,b or r,g,b,a",
+            line_index + 1,
+            channels.len(),
+        );
+        ensure!(entries < LUT_ENTRIES, "the palette has more than {LUT_ENTRIES} colours");
+
+        let alpha = channels.get(3).copied().unwrap_or(u8::MAX);
+        lut[entries] = [channels[0], channels[1], channels[2], alpha].map(|channel| channel as f32 / 255.0);
+        entries += 1;
+    }
+    ensure!(entries == LUT_ENTRIES, "the palette has {entries} colours, it needs {LUT_ENTRIES}");
+
+    Ok(lut)
+}
+
+/// Loads the palette at `path`, either a CSV file for [`parse_palette_csv`] or a picture whose
+/// first row holds the [`LUT_ENTRIES`] colours from left to right.
+pub fn load_palette(path: &Path) -> Result<PaletteLut>
+{
+    let is_csv = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    if is_csv {
+        let text = fs::read_to_string(path).with_context(|| format!("failed to read the palette {}", path.display()))?;
+        return parse_palette_csv(&text).with_context(|| format!("invalid palette {}", path.display()));
+    }
+
+    load_palette_image(path)
+}
+
+#[cfg(feature = "image-output")]
+fn load_palette_image(path: &Path) -> Result<PaletteLut>
+{
+    let image = image::open(path)
+        .with_context(|| format!("failed to open the palette {}", path.display()))?
+        .to_rgba8();
+    ensure!(
+        image.width() as usize == LUT_ENTRIES,
+        "the palette {} is {} pixels wide, it needs one pixel for each of the {LUT_ENTRIES} colours",
+        path.display(),
+        image.width(),
+    );
+
+    Ok(std::array::from_fn(|x| image.get_pixel(x as u32, 0).0.map(|channel| channel as f32 / 255.0)))
+}
+
+#[cfg(not(feature = "image-output"))]
+fn load_palette_image(path: &Path) -> Result<PaletteLut>
+{
+    anyhow::bail!("can't decode the palette {}, build with the image-output feature or pass a CSV file", path.display())
+}
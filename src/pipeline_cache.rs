@@ -0,0 +1,88 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::Device;
+use vulkano::pipeline::cache::{PipelineCache, PipelineCacheCreateInfo};
+
+/// Size of the `VkPipelineCacheHeaderVersionOne` header every cache blob starts with.
+const HEADER_SIZE: usize = 32;
+const HEADER_VERSION_ONE: u32 = 1;
+
+/// Checks that `data` starts with a cache header written by this exact device and driver.
+fn is_compatible(data: &[u8], physical_device: &PhysicalDevice) -> bool
+{
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+
+    let read_u32 = |offset: usize| {
+        u32::from_ne_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+    };
+    let properties = physical_device.properties();
+
+    read_u32(0) as usize >= HEADER_SIZE
+        && read_u32(4) == HEADER_VERSION_ONE
+        && read_u32(8) == properties.vendor_id
+        && read_u32(12) == properties.device_id
+        && data[16..32] == properties.pipeline_cache_uuid
+}
+
+/// Creates a pipeline cache seeded from `path`.
+///
+/// A missing, corrupt or incompatible file is not an error, the cache just starts out empty.
+pub fn load_pipeline_cache(device: &Arc<Device>, path: &Path) -> Result<Arc<PipelineCache>>
+{
+    let initial_data = match fs::read(path) {
+        Ok(data) if is_compatible(&data, device.physical_device()) => {
+            info!("Loaded pipeline cache from {}", path.display());
+            data
+        }
+        Ok(_) => {
+            warn!("pipeline cache {} is corrupt or from another device, starting fresh", path.display());
+            Vec::new()
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => Vec::new(),
+        Err(error) => {
+            warn!("failed to read pipeline cache {}: {error}, starting fresh", path.display());
+            Vec::new()
+        }
+    };
+
+    // SAFETY: the header was checked against this device above, and the driver validates the
+    // rest of the blob itself
+    let cache = unsafe {
+        PipelineCache::new(
+            device.clone(),
+            PipelineCacheCreateInfo {
+                initial_data,
+                ..Default::default()
+            },
+        )
+    };
+
+    match cache {
+        Ok(cache) => Ok(cache),
+        Err(error) => {
+            warn!("driver rejected pipeline cache {}: {error}, starting fresh", path.display());
+
+            // SAFETY: there is no initial data
+            unsafe { PipelineCache::new(device.clone(), PipelineCacheCreateInfo::default()) }
+                .context("failed to create an empty PipelineCache")
+        }
+    }
+}
+
+/// Writes the contents of `cache` to `path` so the next run can reuse them.
+pub fn save_pipeline_cache(cache: &PipelineCache, path: &Path) -> Result<()>
+{
+    let data = cache.get_data().context("failed to get pipeline cache data")?;
+    fs::write(path, data)
+        .with_context(|| format!("failed to write pipeline cache to {}", path.display()))?;
+
+    Ok(())
+}
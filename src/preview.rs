@@ -0,0 +1,641 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::{info, warn};
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, BlitImageInfo, CommandBufferUsage};
+use vulkano::descriptor_set::allocator::{StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo};
+use vulkano::descriptor_set::{DescriptorSet, WriteDescriptorSet};
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags};
+use vulkano::format::Format;
+use vulkano::image::sampler::Filter;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::instance::debug::DebugUtilsMessenger;
+use vulkano::instance::Instance;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::compute::ComputePipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo};
+use vulkano::shader::SpecializationConstant;
+use vulkano::swapchain::{
+    acquire_next_image, Surface, SurfaceInfo, Swapchain, SwapchainCreateInfo, SwapchainPresentInfo,
+};
+use vulkano::sync::{self, GpuFuture};
+use vulkano::{Validated, VulkanError, VulkanLibrary};
+use winit::application::ApplicationHandler;
+use winit::dpi::PhysicalSize;
+use winit::event::{ElementState, KeyEvent, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{Key, NamedKey};
+use winit::window::{Window, WindowId};
+
+use crate::device::{
+    check_device_support, check_queue_priority, check_workgroup_size, enable_portability_subset, pick_physical_device,
+};
+use crate::format::select_storage_format;
+use crate::instance::{create_instance_with_extensions, setup_debug_messenger};
+use crate::out_of_memory::MemoryContext;
+use crate::{div_ceil, Counters, Params, PixelFormat, PushConstants, RenderConfig, DEFAULT_PALETTE};
+
+/// How often the preview logs its frame rate and shows it in the title bar.
+const FPS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Opens a window and renders the shader of `config` into it every frame until the window is
+/// closed or Escape is pressed.
+///
+/// Each frame is dispatched at the size of the window into a storage image, which is blitted to
+/// the swapchain image and presented. The time push constant starts at `config.time` and advances
+/// in seconds, everything else is taken from `config` like for a headless render.
+///
+/// With a `target_fps`, each frame waits out what's left of its share of the second, otherwise
+/// frames are rendered as fast as the GPU and the present mode allow.
+pub fn run_preview(config: &RenderConfig, target_fps: Option<u32>) -> Result<()>
+{
+    ensure!(target_fps != Some(0), "the preview's --fps must be non-zero");
+    check_queue_priority(config.queue_priority)?;
+
+    ensure!(
+        !config.shader.needs_input() && !config.shader.is_volume() && !config.shader.is_iterative(),
+        "the preview can't show the {:?} shader, it only supports 2D shaders that render a single pass without an input",
+        config.shader,
+    );
+    ensure!(
+        config.spirv.is_none() && config.glsl.is_none(),
+        "the preview only shows the built-in shaders"
+    );
+
+    let event_loop = EventLoop::new().context("failed to create an event loop")?;
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let library = VulkanLibrary::new().context("no local Vulkan library/DLL")?;
+    let surface_extensions = Surface::required_extensions(&event_loop)
+        .context("failed to query the instance extensions of a window surface")?;
+    let instance = create_instance_with_extensions(library, config.validation, false, surface_extensions)?;
+    let debug_messenger = setup_debug_messenger(&instance)?;
+
+    let mut app = PreviewApp {
+        config: config.clone(),
+        instance,
+        _debug_messenger: debug_messenger,
+        frame_interval: target_fps.map(|fps| Duration::from_secs(1) / fps),
+        preview: None,
+        error: None,
+    };
+    event_loop.run_app(&mut app).context("the event loop failed")?;
+
+    match app.error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Handles the window events, the window and everything drawing into it is only created once the
+/// event loop resumes.
+struct PreviewApp {
+    config: RenderConfig,
+    instance: Arc<Instance>,
+    _debug_messenger: Option<DebugUtilsMessenger>,
+    frame_interval: Option<Duration>,
+    preview: Option<Preview>,
+    /// The first error a handler ran into, which ends the event loop
+    error: Option<anyhow::Error>,
+}
+
+impl PreviewApp {
+    fn fail(&mut self, event_loop: &ActiveEventLoop, error: anyhow::Error)
+    {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+        event_loop.exit();
+    }
+}
+
+impl ApplicationHandler for PreviewApp {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop)
+    {
+        if self.preview.is_some() {
+            return;
+        }
+
+        match Preview::new(event_loop, &self.instance, &self.config, self.frame_interval) {
+            Ok(preview) => self.preview = Some(preview),
+            Err(error) => self.fail(event_loop, error),
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent)
+    {
+        let Some(preview) = &mut self.preview else {
+            return;
+        };
+
+        match event {
+            WindowEvent::CloseRequested
+            | WindowEvent::KeyboardInput {
+                event: KeyEvent { logical_key: Key::Named(NamedKey::Escape), state: ElementState::Pressed, .. },
+                ..
+            } => event_loop.exit(),
+            WindowEvent::Resized(_) => preview.recreate_swapchain = true,
+            WindowEvent::RedrawRequested => {
+                if let Err(error) = preview.draw(&self.config) {
+                    self.fail(event_loop, error);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop)
+    {
+        if let Some(preview) = &self.preview {
+            preview.window.request_redraw();
+        }
+    }
+}
+
+/// The window, its swapchain and the storage image the compute shader renders into before it's
+/// blitted to the swapchain image.
+struct Preview {
+    window: Arc<Window>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    compute_pipeline: Arc<ComputePipeline>,
+    /// Writes of the buffers the shader reads besides the storage image
+    shared_writes: Vec<WriteDescriptorSet>,
+    storage_format: Format,
+    workgroup_size: [u32; 2],
+    swapchain: Arc<Swapchain>,
+    swapchain_images: Vec<Arc<Image>>,
+    image: Arc<Image>,
+    set: Arc<DescriptorSet>,
+    /// Set when the window was resized or the swapchain no longer matches the surface
+    recreate_swapchain: bool,
+    previous_frame_end: Option<Box<dyn GpuFuture>>,
+    /// Shortest time from the start of one frame to the next, `None` leaves the rate uncapped
+    frame_interval: Option<Duration>,
+    title: String,
+    start: Instant,
+    last_frame: Instant,
+    frames_since_log: u32,
+    last_log: Instant,
+}
+
+impl Preview {
+    fn new(
+        event_loop: &ActiveEventLoop,
+        instance: &Arc<Instance>,
+        config: &RenderConfig,
+        frame_interval: Option<Duration>,
+    ) -> Result<Self>
+    {
+        let title = format!("vulkan_tutorial - {:?}", config.shader);
+        let window = Arc::new(
+            event_loop
+                .create_window(
+                    Window::default_attributes()
+                        .with_title(&title)
+                        .with_inner_size(initial_window_size(event_loop, config)),
+                )
+                .context("failed to create a window")?,
+        );
+        let surface = Surface::from_window(instance.clone(), window.clone()).context("failed to create a window surface")?;
+
+        let physical_device = pick_physical_device(
+            instance,
+            config.device_index,
+            config.device_uuid,
+            config.allow_software,
+        )?;
+        check_workgroup_size(&physical_device, config.workgroup_size)?;
+        let queue_family_index = find_present_queue_family(&physical_device, &surface)?;
+
+        let mut enabled_extensions = DeviceExtensions {
+            khr_swapchain: true,
+            ..config.shader.required_extensions()
+        };
+        let enabled_features = config.shader.required_features();
+        check_device_support(&physical_device, &enabled_features, &enabled_extensions)?;
+        enable_portability_subset(&physical_device, &mut enabled_extensions);
+
+        info!("Requesting queue priority {}", config.queue_priority);
+
+        let (device, mut queues) = Device::new(
+            physical_device,
+            DeviceCreateInfo {
+                queue_create_infos: vec![QueueCreateInfo {
+                    queue_family_index,
+                    queues: vec![config.queue_priority],
+                    ..Default::default()
+                }],
+                enabled_features,
+                enabled_extensions,
+                ..Default::default()
+            },
+        )
+        .context("failed to create device")?;
+        let queue = queues.next().context("device returned no queues")?;
+
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        // A resize allocates a new set while the old one may still be used by a frame in flight
+        let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
+            device.clone(),
+            StandardDescriptorSetAllocatorCreateInfo {
+                set_count: 2,
+                ..Default::default()
+            },
+        ));
+        let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(device.clone(), Default::default()));
+
+        // Same constants as a headless render, the swapchain image is UNORM so an sRGB render
+        // is encoded by the shader
+        let specialization_info = [
+            (0, SpecializationConstant::U32(config.workgroup_size[0])),
+            (1, SpecializationConstant::U32(config.workgroup_size[1])),
+            (2, SpecializationConstant::Bool(config.srgb)),
+            (3, SpecializationConstant::Bool(config.srgb)),
+        ]
+        .into_iter()
+        .collect();
+        let compute_shader = config
+            .shader
+            .load(device.clone(), false)
+            .context("failed to load a compute shader")?
+            .specialize(specialization_info)
+            .context("failed to specialize the compute shader")?
+            .entry_point("main")
+            .context("compute shader has no \"main\" entry point")?;
+        let stage = PipelineShaderStageCreateInfo::new(compute_shader);
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                .into_pipeline_layout_create_info(device.clone())
+                .context("failed to create PipelineLayoutCreateInfo")?,
+        )
+        .context("failed to create a new PipelineLayout")?;
+        let compute_pipeline = ComputePipeline::new(device.clone(), None, ComputePipelineCreateInfo::stage_layout(stage, layout))
+            .context("failed to create a new ComputePipeline")?;
+
+        let set_layout = compute_pipeline.layout().set_layouts().first().context("failed to return a layout")?;
+        let mut shared_writes = Vec::new();
+        if set_layout.bindings().contains_key(&2) {
+            let params_buffer = Buffer::from_data(
+                memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::UNIFORM_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                Params { palette: DEFAULT_PALETTE },
+            )
+            .memory_context("failed to create a uniform buffer")?;
+            shared_writes.push(WriteDescriptorSet::buffer(2, params_buffer));
+        }
+        if set_layout.bindings().contains_key(&3) {
+            // Nothing reads the counters back, the shader just needs somewhere to count
+            let counters_buf = Buffer::from_data(
+                memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::STORAGE_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                    ..Default::default()
+                },
+                Counters::default(),
+            )
+            .memory_context("failed to create a storage buffer")?;
+            shared_writes.push(WriteDescriptorSet::buffer(3, counters_buf));
+        }
+
+        let storage_usage = ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC;
+        let storage_format = select_storage_format(device.physical_device(), PixelFormat::Rgba8, ImageType::Dim2d, storage_usage)?;
+
+        let (swapchain, swapchain_images) = create_swapchain(&device, &surface, window.inner_size().into())?;
+        let (image, set) = create_target(
+            &memory_allocator,
+            &descriptor_set_allocator,
+            &compute_pipeline,
+            &shared_writes,
+            storage_format,
+            swapchain.image_extent(),
+        )?;
+        info!(
+            "Previewing in a {}x{} {:?} swapchain, close the window or press Escape to stop",
+            swapchain.image_extent()[0],
+            swapchain.image_extent()[1],
+            swapchain.image_format(),
+        );
+
+        let previous_frame_end = Some(sync::now(device.clone()).boxed());
+
+        Ok(Self {
+            window,
+            device,
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            compute_pipeline,
+            shared_writes,
+            storage_format,
+            workgroup_size: config.workgroup_size,
+            swapchain,
+            swapchain_images,
+            image,
+            set,
+            recreate_swapchain: false,
+            previous_frame_end,
+            frame_interval,
+            title,
+            start: Instant::now(),
+            last_frame: Instant::now(),
+            frames_since_log: 0,
+            last_log: Instant::now(),
+        })
+    }
+
+    /// Recreates the swapchain and the storage image at the current size of the window.
+    fn recreate(&mut self, extent: [u32; 2]) -> Result<()>
+    {
+        let (swapchain, swapchain_images) = self
+            .swapchain
+            .recreate(SwapchainCreateInfo {
+                image_extent: extent,
+                ..self.swapchain.create_info()
+            })
+            .context("failed to recreate the swapchain")?;
+        let (image, set) = create_target(
+            &self.memory_allocator,
+            &self.descriptor_set_allocator,
+            &self.compute_pipeline,
+            &self.shared_writes,
+            self.storage_format,
+            swapchain.image_extent(),
+        )?;
+
+        self.swapchain = swapchain;
+        self.swapchain_images = swapchain_images;
+        self.image = image;
+        self.set = set;
+        self.recreate_swapchain = false;
+
+        Ok(())
+    }
+
+    /// Renders and presents one frame, skipping it while the window is minimized or the
+    /// swapchain is out of date.
+    fn draw(&mut self, config: &RenderConfig) -> Result<()>
+    {
+        let extent: [u32; 2] = self.window.inner_size().into();
+        if extent.contains(&0) {
+            return Ok(());
+        }
+
+        // Measured from the start of the last frame, so the time spent rendering and presenting
+        // it counts towards the interval
+        if let Some(frame_interval) = self.frame_interval {
+            let frame_time = self.last_frame.elapsed();
+            if frame_time < frame_interval {
+                thread::sleep(frame_interval - frame_time);
+            }
+        }
+        self.last_frame = Instant::now();
+
+        if let Some(previous_frame_end) = &mut self.previous_frame_end {
+            previous_frame_end.cleanup_finished();
+        }
+
+        if self.recreate_swapchain {
+            self.recreate(extent)?;
+        }
+
+        let (image_index, suboptimal, acquire_future) =
+            match acquire_next_image(self.swapchain.clone(), None).map_err(Validated::unwrap) {
+                Ok(acquired) => acquired,
+                Err(VulkanError::OutOfDate) => {
+                    self.recreate_swapchain = true;
+                    return Ok(());
+                }
+                Err(error) => return Err(error).context("failed to acquire a swapchain image"),
+            };
+        if suboptimal {
+            self.recreate_swapchain = true;
+        }
+
+        let [width, height, _] = self.image.extent();
+        let time = config.time + self.start.elapsed().as_secs_f32();
+        let push_constants = PushConstants {
+            size: [width, height],
+            ..config.push_constants(time)
+        };
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .context("failed to create an AutoCommandBufferBuilder")?;
+        command_buffer_builder
+            .bind_pipeline_compute(self.compute_pipeline.clone())
+            .context("failed to bind a compute pipeline to a command buffer")?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.compute_pipeline.layout().clone(),
+                0,
+                self.set.clone(),
+            )
+            .context("failed to bind descriptor sets to a command buffer")?
+            .push_constants(self.compute_pipeline.layout().clone(), 0, push_constants)
+            .context("failed to push constants to a command buffer")?;
+        unsafe {
+            command_buffer_builder
+                .dispatch([div_ceil(width, self.workgroup_size[0]), div_ceil(height, self.workgroup_size[1]), 1])
+                .context("failed to dispatch work_group_counts")?;
+        }
+        // Both images have the extent of the swapchain, so nothing is scaled
+        command_buffer_builder
+            .blit_image(BlitImageInfo {
+                filter: Filter::Nearest,
+                ..BlitImageInfo::images(self.image.clone(), self.swapchain_images[image_index as usize].clone())
+            })
+            .context("failed to blit the render to the swapchain image")?;
+        let command_buffer = command_buffer_builder
+            .build()
+            .context("failed to build a PrimaryAutoCommandBuffer")?;
+
+        let future = self
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| sync::now(self.device.clone()).boxed())
+            .join(acquire_future)
+            .then_execute(self.queue.clone(), command_buffer)
+            .context("failed to execute the command buffer")?
+            .then_swapchain_present(
+                self.queue.clone(),
+                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_index),
+            )
+            .then_signal_fence_and_flush();
+
+        match future.map_err(Validated::unwrap) {
+            Ok(future) => self.previous_frame_end = Some(future.boxed()),
+            Err(VulkanError::OutOfDate) => {
+                self.recreate_swapchain = true;
+                self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+            }
+            Err(error) => return Err(error).memory_context("failed to present the frame"),
+        }
+
+        self.frames_since_log += 1;
+        if self.last_log.elapsed() >= FPS_INTERVAL {
+            let fps = self.frames_since_log as f64 / self.last_log.elapsed().as_secs_f64();
+            info!("Preview: {fps:.1} frames per second at {width}x{height}");
+            self.window.set_title(&format!("{} - {fps:.1} fps", self.title));
+            self.frames_since_log = 0;
+            self.last_log = Instant::now();
+        }
+
+        Ok(())
+    }
+}
+
+/// `config`'s size, scaled down to fit on the primary monitor since the default renders are
+/// larger than most screens.
+fn initial_window_size(event_loop: &ActiveEventLoop, config: &RenderConfig) -> PhysicalSize<u32>
+{
+    let Some(monitor) = event_loop.primary_monitor() else {
+        return PhysicalSize::new(config.width, config.height);
+    };
+
+    let monitor_size = monitor.size();
+    let scale = (monitor_size.width as f64 * 0.8 / config.width as f64)
+        .min(monitor_size.height as f64 * 0.8 / config.height as f64)
+        .min(1.0);
+
+    PhysicalSize::new(
+        ((config.width as f64 * scale) as u32).max(1),
+        ((config.height as f64 * scale) as u32).max(1),
+    )
+}
+
+/// Finds a queue family that can both run compute shaders and present to `surface`.
+///
+/// Compute families can always transfer, which the blit needs.
+fn find_present_queue_family(physical_device: &PhysicalDevice, surface: &Surface) -> Result<u32>
+{
+    for (queue_family_index, queue_family_properties) in physical_device.queue_family_properties().iter().enumerate() {
+        let queue_family_index = queue_family_index as u32;
+        if !queue_family_properties.queue_flags.contains(QueueFlags::COMPUTE) {
+            continue;
+        }
+
+        let presents = physical_device
+            .surface_support(queue_family_index, surface)
+            .context("failed to query the surface support of a queue family")?;
+        if presents {
+            info!("Using queue family {queue_family_index} for compute and presentation");
+            return Ok(queue_family_index);
+        }
+    }
+
+    bail!(
+        "{} has no queue family that can both compute and present to the window",
+        physical_device.properties().device_name,
+    )
+}
+
+/// Creates a swapchain whose images the render is blitted to, preferring a UNORM format so the
+/// window shows the same values as a saved PNG.
+fn create_swapchain(device: &Arc<Device>, surface: &Arc<Surface>, extent: [u32; 2]) -> Result<(Arc<Swapchain>, Vec<Arc<Image>>)>
+{
+    let physical_device = device.physical_device();
+    let capabilities = physical_device
+        .surface_capabilities(surface, SurfaceInfo::default())
+        .context("failed to query the surface capabilities")?;
+    ensure!(
+        capabilities.supported_usage_flags.contains(ImageUsage::TRANSFER_DST),
+        "the window surface can't be blitted to, its images don't support TRANSFER_DST"
+    );
+
+    let formats = physical_device
+        .surface_formats(surface, SurfaceInfo::default())
+        .context("failed to query the surface formats")?;
+    let (image_format, image_color_space) = formats
+        .iter()
+        .copied()
+        .find(|(format, _)| matches!(format, Format::B8G8R8A8_UNORM | Format::R8G8B8A8_UNORM))
+        .or_else(|| formats.first().copied())
+        .context("the window surface supports no formats")?;
+    if !matches!(image_format, Format::B8G8R8A8_UNORM | Format::R8G8B8A8_UNORM) {
+        warn!("the window surface has no UNORM format, the preview uses {image_format:?} and may look different from a saved PNG");
+    }
+
+    let composite_alpha = capabilities
+        .supported_composite_alpha
+        .into_iter()
+        .next()
+        .context("the window surface supports no composite alpha modes")?;
+
+    Swapchain::new(
+        device.clone(),
+        surface.clone(),
+        SwapchainCreateInfo {
+            min_image_count: capabilities.min_image_count.max(2),
+            image_format,
+            image_color_space,
+            image_extent: extent,
+            image_usage: ImageUsage::TRANSFER_DST,
+            composite_alpha,
+            ..Default::default()
+        },
+    )
+    .context("failed to create the swapchain")
+}
+
+/// Creates the storage image the shader renders a frame of `extent` into, and its descriptor set.
+fn create_target(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: &Arc<StandardDescriptorSetAllocator>,
+    compute_pipeline: &Arc<ComputePipeline>,
+    shared_writes: &[WriteDescriptorSet],
+    format: Format,
+    extent: [u32; 2],
+) -> Result<(Arc<Image>, Arc<DescriptorSet>)>
+{
+    let image = Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format,
+            extent: [extent[0], extent[1], 1],
+            usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+    )
+    .memory_context("failed to create an image")?;
+    let view = ImageView::new_default(image.clone()).context("failed to create an ImageView")?;
+
+    let layout = compute_pipeline.layout().set_layouts().first().context("failed to return a layout")?;
+    let mut descriptor_writes = vec![WriteDescriptorSet::image_view(0, view)];
+    descriptor_writes.extend(shared_writes.iter().cloned());
+    let set = DescriptorSet::new(descriptor_set_allocator.clone(), layout.clone(), descriptor_writes, [])
+        .context("failed to create a set")?;
+
+    Ok((image, set))
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use log::{info, warn};
+use vulkano::device::DeviceOwned;
+use vulkano::memory::allocator::suballocator::Suballocator;
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::DeviceSize;
+
+/// Reports the memory allocations of a renderer that are still alive when it is torn down, for
+/// `--debug-resources`.
+///
+/// It has to be the last field of its owner, so that everything else the owner holds is freed
+/// before it is dropped and anything still allocated then is held elsewhere or leaked. Resources
+/// large enough to get a dedicated allocation bypass the allocator's pools and aren't counted.
+pub struct ResourceReport {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+}
+
+impl ResourceReport {
+    pub fn new(memory_allocator: Arc<StandardMemoryAllocator>) -> Self
+    {
+        Self { memory_allocator }
+    }
+
+    /// Waits for all work on the device to finish, called before the owner starts tearing down.
+    pub fn wait_idle(&self)
+    {
+        let device = self.memory_allocator.device();
+
+        // Only the owner submits to its device, and it is being dropped
+        match unsafe { device.wait_idle() } {
+            Ok(()) => info!("{} is idle, releasing its resources", device.physical_device().properties().device_name),
+            Err(error) => warn!("failed to wait for the device to become idle: {error}"),
+        }
+    }
+}
+
+impl Drop for ResourceReport {
+    fn drop(&mut self)
+    {
+        let mut blocks = 0;
+        let mut allocations = 0;
+        let mut used_size: DeviceSize = 0;
+        for pool in self.memory_allocator.pools() {
+            for block in pool.blocks() {
+                blocks += 1;
+                allocations += block.allocation_count();
+                used_size += block.device_memory().allocation_size() - block.suballocator().free_size();
+            }
+        }
+
+        if allocations == 0 {
+            info!("All pooled device memory was freed, {blocks} empty blocks remain in the allocator");
+        } else {
+            warn!(
+                "{allocations} allocations of {used_size} bytes in {blocks} device memory blocks are still alive after the renderer was dropped",
+            );
+        }
+    }
+}
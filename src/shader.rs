@@ -0,0 +1,427 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, ensure, Context, Ok, Result};
+use log::warn;
+use vulkano::descriptor_set::layout::{DescriptorSetLayout, DescriptorType};
+use vulkano::descriptor_set::WriteDescriptorSet;
+use vulkano::device::{Device, DeviceExtensions, DeviceFeatures};
+use vulkano::format::Format;
+use vulkano::shader::spirv::{bytes_to_words, ExecutionModel, Instruction, Spirv};
+use vulkano::shader::{EntryPoint, ShaderModule, ShaderModuleCreateInfo};
+use vulkano::{Validated, VulkanError};
+
+use crate::PushConstants;
+
+mod mandelbrot_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/mandelbrot.comp"
+    }
+}
+
+// `mandelbrot.comp` with its `debugPrintfEXT` call compiled in
+mod mandelbrot_printf_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/mandelbrot.comp",
+        define: [("DEBUG_PRINTF", "")]
+    }
+}
+
+// `mandelbrot.comp` that also writes the iteration count of every pixel to binding 4
+mod mandelbrot_iterations_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/mandelbrot.comp",
+        define: [("DUMP_ITERATIONS", "")]
+    }
+}
+
+mod gradient_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/gradient.comp"
+    }
+}
+
+mod volume_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/volume.comp"
+    }
+}
+
+mod noise_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/noise.comp"
+    }
+}
+
+mod life_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/life.comp"
+    }
+}
+
+mod solid_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/solid.comp"
+    }
+}
+
+mod blur_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/blur.comp"
+    }
+}
+
+mod normalmap_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/normalmap.comp"
+    }
+}
+
+mod resize_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/resize.comp"
+    }
+}
+
+// Runs after any of the kernels to count the luminance of what it rendered
+mod histogram_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/histogram.comp"
+    }
+}
+
+// Runs after any of the kernels to overwrite the last row with a pattern the host checks
+mod verify_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/verify.comp"
+    }
+}
+
+// Runs after any of the kernels to recolour what it rendered through the `--palette` lookup table
+mod palette_lut_shader {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        path: "src/shaders/palette_lut.comp"
+    }
+}
+
+/// The compute kernels that can be dispatched.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShaderKind {
+    /// Colourful Mandelbrot set
+    #[default]
+    Mandelbrot,
+    /// Horizontal and vertical colour gradient
+    Gradient,
+    /// Box blur of the input image
+    Blur,
+    /// Tangent-space normal map of the input image read as a heightfield
+    Normalmap,
+    /// Bilinear resize of the input image to the size given with `--resize`
+    Resize,
+    /// Rippled sphere distance field written to a 3D image
+    Volume,
+    /// Greyscale value noise from an integer hash of the seed, bit-for-bit reproducible on the
+    /// same GPU
+    Noise,
+    /// Conway's Game of Life, one generation per pass starting from a board seeded by the seed
+    Life,
+    /// Every pixel in the same known colour, which `--self-test` checks the readback against
+    Solid,
+}
+
+impl ShaderKind {
+    /// Whether the shader reads the input image at binding 1.
+    pub fn needs_input(self) -> bool
+    {
+        matches!(self, ShaderKind::Blur | ShaderKind::Normalmap | ShaderKind::Resize)
+    }
+
+    /// Whether the shader writes a 3D image, with one invocation per voxel.
+    pub fn is_volume(self) -> bool
+    {
+        matches!(self, ShaderKind::Volume)
+    }
+
+    /// Whether the shader reads its own previous output at binding 1, so it can run several
+    /// passes that alternate between two images.
+    pub fn is_iterative(self) -> bool
+    {
+        matches!(self, ShaderKind::Life)
+    }
+
+    /// Whether the shader has a variant that prints with `debugPrintfEXT`.
+    pub fn has_debug_printf(self) -> bool
+    {
+        matches!(self, ShaderKind::Mandelbrot)
+    }
+
+    /// Device features that have to be enabled for the shader to run.
+    pub fn required_features(self) -> DeviceFeatures
+    {
+        // Every shader leaves out the format qualifier on the output image so one module works
+        // for every `PixelFormat`, iterative ones also read it back that way
+        DeviceFeatures {
+            shader_storage_image_write_without_format: true,
+            shader_storage_image_read_without_format: self.is_iterative(),
+            ..DeviceFeatures::empty()
+        }
+    }
+
+    /// Device extensions that have to be enabled for the shader to run.
+    pub fn required_extensions(self) -> DeviceExtensions
+    {
+        DeviceExtensions::empty()
+    }
+
+    /// Loads the shader, the variant that prints when `debug_printf` is set and there is one.
+    pub fn load(self, device: Arc<Device>, debug_printf: bool) -> Result<Arc<ShaderModule>, Validated<VulkanError>>
+    {
+        match self {
+            ShaderKind::Mandelbrot if debug_printf => mandelbrot_printf_shader::load(device),
+            ShaderKind::Mandelbrot => mandelbrot_shader::load(device),
+            ShaderKind::Gradient => gradient_shader::load(device),
+            ShaderKind::Blur => blur_shader::load(device),
+            ShaderKind::Normalmap => normalmap_shader::load(device),
+            ShaderKind::Resize => resize_shader::load(device),
+            ShaderKind::Volume => volume_shader::load(device),
+            ShaderKind::Noise => noise_shader::load(device),
+            ShaderKind::Life => life_shader::load(device),
+            ShaderKind::Solid => solid_shader::load(device),
+        }
+    }
+}
+
+/// Loads the variant of the Mandelbrot shader that writes the iteration count of every pixel into
+/// an `R32_UINT` storage image at binding 4, next to the colours at binding 0.
+pub fn load_mandelbrot_iterations_shader(device: Arc<Device>) -> Result<Arc<ShaderModule>, Validated<VulkanError>>
+{
+    mandelbrot_iterations_shader::load(device)
+}
+
+/// Loads the shader that counts the luminance of a rendered image into [`HISTOGRAM_BINS`](crate::HISTOGRAM_BINS) bins.
+pub fn load_histogram_shader(device: Arc<Device>) -> Result<Arc<ShaderModule>, Validated<VulkanError>>
+{
+    histogram_shader::load(device)
+}
+
+/// Loads the shader that writes the checksum pattern of `--verify` into the last row of an image.
+pub fn load_verify_shader(device: Arc<Device>) -> Result<Arc<ShaderModule>, Validated<VulkanError>>
+{
+    verify_shader::load(device)
+}
+
+/// Loads the shader that maps the luminance of a rendered image through the `--palette` lookup table.
+pub fn load_palette_lut_shader(device: Arc<Device>) -> Result<Arc<ShaderModule>, Validated<VulkanError>>
+{
+    palette_lut_shader::load(device)
+}
+
+/// Magic number every SPIR-V module starts with.
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Descriptors the renderer binds in set 0 and what they hold, any shader may use a subset of them.
+const BINDINGS: [(u32, DescriptorType, &str); 4] = [
+    (0, DescriptorType::StorageImage, "the output image"),
+    (1, DescriptorType::StorageImage, "the input image or the previous pass"),
+    (2, DescriptorType::UniformBuffer, "the Params uniform buffer"),
+    (3, DescriptorType::StorageBuffer, "the Counters storage buffer"),
+];
+
+/// Checks that the SPIR-V `words` loaded from `path` declare a compute entry point called
+/// `entry_point`, and lists the ones they do declare when they don't.
+fn check_entry_point_name(words: &[u32], entry_point: &str, path: &Path) -> Result<()>
+{
+    let spirv = Spirv::new(words).with_context(|| format!("{} is malformed SPIR-V", path.display()))?;
+    let names: Vec<&str> = spirv
+        .entry_points()
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::EntryPoint { execution_model: ExecutionModel::GLCompute, name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    if names.is_empty() {
+        bail!("{} declares no compute entry points", path.display());
+    }
+    ensure!(
+        names.contains(&entry_point),
+        "{} has no compute entry point called {entry_point:?}, pass one of these to --entry-point: {}",
+        path.display(),
+        names.join(", "),
+    );
+
+    Ok(())
+}
+
+/// Loads a precompiled SPIR-V module from `path`, e.g. compiled by glslang or naga, and checks
+/// that it has a compute entry point called `entry_point`.
+pub fn load_spirv(device: Arc<Device>, path: &Path, entry_point: &str) -> Result<Arc<ShaderModule>>
+{
+    let bytes = fs::read(path).with_context(|| format!("failed to read a SPIR-V module from {}", path.display()))?;
+    let words = bytes_to_words(&bytes)
+        .map_err(|_| anyhow!("{} isn't SPIR-V, its size isn't a multiple of 4 bytes", path.display()))?;
+    ensure!(
+        words.first() == Some(&SPIRV_MAGIC),
+        "{} isn't SPIR-V, it doesn't start with the magic number",
+        path.display(),
+    );
+    check_entry_point_name(&words, entry_point, path)?;
+
+    // Vulkano parses the module and validates everything it reflects from it, anything else is
+    // up to the compiler that produced it, like for any shader loaded at runtime
+    unsafe { ShaderModule::new(device, ShaderModuleCreateInfo::new(&words)) }
+        .with_context(|| format!("{} is malformed SPIR-V", path.display()))
+}
+
+/// Deepest nesting of `#include` directives, which stops a header that includes itself.
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Resolves an `#include` of `requested` for shaderc.
+///
+/// `#include "file"` is relative to the directory of the file containing the directive and
+/// `#include <file>` to `root`, the directory of the shader being compiled. shaderc reports an
+/// error with the including file and line of the directive.
+fn resolve_include(
+    root: &Path,
+    requested: &str,
+    include_type: shaderc::IncludeType,
+    requesting: &str,
+    depth: usize,
+) -> shaderc::IncludeCallbackResult
+{
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(format!("includes are nested deeper than {MAX_INCLUDE_DEPTH} levels"));
+    }
+
+    let directory = match include_type {
+        shaderc::IncludeType::Relative => Path::new(requesting).parent().unwrap_or(root),
+        shaderc::IncludeType::Standard => root,
+    };
+    let path = directory.join(requested);
+    fs::read_to_string(&path)
+        .map(|content| shaderc::ResolvedInclude { resolved_name: path.display().to_string(), content })
+        .map_err(|error| format!("can't read {}: {error}", path.display()))
+}
+
+/// Compiles the GLSL compute shader at `path` with shaderc, so it can be edited without rebuilding.
+///
+/// `DEBUG_PRINTF` is defined when `debug_printf` is set, like for the built-in printing variants.
+/// `#include` directives load headers next to the shader, see [`resolve_include`]. The shader's
+/// `main` function becomes the SPIR-V entry point called `entry_point`. Compile errors keep
+/// shaderc's `file:line: error: ...` messages.
+pub fn compile_glsl(device: Arc<Device>, path: &Path, debug_printf: bool, entry_point: &str) -> Result<Arc<ShaderModule>>
+{
+    let source =
+        fs::read_to_string(path).with_context(|| format!("failed to read a GLSL shader from {}", path.display()))?;
+
+    let compiler = shaderc::Compiler::new().context("failed to create a shaderc compiler")?;
+    let mut options = shaderc::CompileOptions::new().context("failed to create shaderc compile options")?;
+    options.set_target_env(shaderc::TargetEnv::Vulkan, shaderc::EnvVersion::Vulkan1_0 as u32);
+    if debug_printf {
+        options.add_macro_definition("DEBUG_PRINTF", None);
+    }
+    let root = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    options.set_include_callback(move |requested, include_type, requesting, depth| {
+        resolve_include(&root, requested, include_type, requesting, depth)
+    });
+
+    let artifact = compiler
+        .compile_into_spirv(&source, shaderc::ShaderKind::Compute, &path.display().to_string(), entry_point, Some(&options))
+        .map_err(|error| match error {
+            shaderc::Error::CompilationError(_, messages) => {
+                anyhow!("failed to compile {}:\n{}", path.display(), messages.trim_end())
+            }
+            error => anyhow!("failed to compile {}: {error}", path.display()),
+        })?;
+    if artifact.get_num_warnings() > 0 {
+        warn!("{}", artifact.get_warning_messages().trim_end());
+    }
+    check_entry_point_name(artifact.as_binary(), entry_point, path)?;
+
+    // SAFETY: shaderc only emits valid SPIR-V, and vulkano validates what it reflects from it
+    unsafe { ShaderModule::new(device, ShaderModuleCreateInfo::new(artifact.as_binary())) }
+        .with_context(|| format!("failed to create a shader module from {}", path.display()))
+}
+
+/// Checks that the reflected interface of a runtime loaded `entry_point` matches what the
+/// renderer binds: a compute shader using only [`BINDINGS`] and at most the [`PushConstants`].
+pub fn check_entry_point(entry_point: &EntryPoint, storage_format: Format, path: &Path) -> Result<()>
+{
+    let info = entry_point.info();
+    ensure!(
+        info.execution_model == ExecutionModel::GLCompute,
+        "the main entry point of {} is a {:?} shader, expected a compute shader",
+        path.display(),
+        info.execution_model,
+    );
+
+    for (&(set, binding), requirements) in &info.descriptor_binding_requirements {
+        let Some(&(_, descriptor_type, _)) = BINDINGS.iter().find(|&&(index, ..)| set == 0 && index == binding) else {
+            bail!("{} uses set {set} binding {binding}, but only set 0 bindings 0 to 3 are bound", path.display());
+        };
+        ensure!(
+            requirements.descriptor_types.contains(&descriptor_type),
+            "{} declares set 0 binding {binding} as {:?}, expected {descriptor_type:?}",
+            path.display(),
+            requirements.descriptor_types,
+        );
+
+        // The output image's format is picked at runtime, so a format qualifier has to match it
+        if binding == 0
+            && let Some(image_format) = requirements.image_format
+        {
+            ensure!(
+                image_format == storage_format,
+                "{} declares the output image as {image_format:?}, but it is {storage_format:?}, leave out the format qualifier",
+                path.display(),
+            );
+        }
+    }
+
+    if let Some(range) = &info.push_constant_requirements {
+        ensure!(
+            range.offset + range.size <= size_of::<PushConstants>() as u32,
+            "{} expects {} bytes of push constants, but only {} are pushed",
+            path.display(),
+            range.offset + range.size,
+            size_of::<PushConstants>(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that `descriptor_writes` provide every binding of `layout`, which is reflected from the
+/// descriptor requirements of `shader`, so a missing one fails here rather than at the dispatch.
+pub fn check_descriptor_writes(layout: &DescriptorSetLayout, descriptor_writes: &[WriteDescriptorSet], shader: &str) -> Result<()>
+{
+    for &binding in layout.bindings().keys() {
+        if descriptor_writes.iter().any(|write| write.binding() == binding) {
+            continue;
+        }
+
+        let description = BINDINGS
+            .iter()
+            .find(|&&(index, ..)| index == binding)
+            .map_or("a binding the renderer doesn't know", |&(_, _, description)| description);
+        bail!("{shader} uses set 0 binding {binding} ({description}), but nothing was written to it");
+    }
+
+    Ok(())
+}
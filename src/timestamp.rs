@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use anyhow::{ensure, Context, Ok, Result};
+use log::warn;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::Device;
+use vulkano::query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType};
+use vulkano::sync::PipelineStage;
+
+/// A pair of GPU timestamps written around a span of commands.
+pub struct TimestampQuery {
+    query_pool: Arc<QueryPool>,
+    timestamp_period: f32,
+    valid_bits: u32,
+}
+
+impl TimestampQuery {
+    /// Returns `None` if the queue family can't write timestamps.
+    pub fn new(device: &Arc<Device>, queue_family_index: u32) -> Result<Option<Self>>
+    {
+        let physical_device = device.physical_device();
+        let valid_bits = physical_device.queue_family_properties()[queue_family_index as usize].timestamp_valid_bits;
+
+        let Some(valid_bits) = valid_bits else {
+            warn!("queue family {queue_family_index} doesn't support timestamps, skipping GPU timing");
+            return Ok(None);
+        };
+
+        let query_pool = QueryPool::new(
+            device.clone(),
+            QueryPoolCreateInfo {
+                query_count: 2,
+                ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+            },
+        )
+        .context("failed to create a timestamp QueryPool")?;
+
+        Ok(Some(Self {
+            query_pool,
+            timestamp_period: physical_device.properties().timestamp_period,
+            valid_bits,
+        }))
+    }
+
+    /// Resets the pool and writes the first timestamp.
+    pub fn write_start<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) -> Result<()>
+    {
+        // SAFETY: the pool is only read back after the command buffer has finished executing
+        unsafe {
+            builder
+                .reset_query_pool(self.query_pool.clone(), 0..2)
+                .context("failed to reset the timestamp QueryPool")?
+                .write_timestamp(self.query_pool.clone(), 0, PipelineStage::TopOfPipe)
+                .context("failed to write the start timestamp")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the second timestamp once all previous commands have completed.
+    pub fn write_end<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) -> Result<()>
+    {
+        // SAFETY: see `write_start`
+        unsafe {
+            builder
+                .write_timestamp(self.query_pool.clone(), 1, PipelineStage::BottomOfPipe)
+                .context("failed to write the end timestamp")?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads both timestamps back and returns the time between them in milliseconds.
+    pub fn elapsed_ms(&self) -> Result<f64>
+    {
+        let mut timestamps = [0u64; 2];
+        let available = self
+            .query_pool
+            .get_results(0..2, &mut timestamps, QueryResultFlags::WAIT)
+            .context("failed to read timestamp query results")?;
+        ensure!(available, "timestamp query results are not available");
+
+        let mask = if self.valid_bits >= 64 { u64::MAX } else { (1 << self.valid_bits) - 1 };
+        let ticks = timestamps[1].wrapping_sub(timestamps[0]) & mask;
+
+        Ok(ticks as f64 * self.timestamp_period as f64 / 1_000_000.0)
+    }
+}
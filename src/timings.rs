@@ -0,0 +1,69 @@
+use std::ops::AddAssign;
+use std::time::Duration;
+
+use log::info;
+
+/// Wall-clock time spent in each stage of a render.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timings {
+    /// Loading the library and creating the instance
+    pub instance: Duration,
+    /// Creating the logical device and its queue
+    pub device: Duration,
+    /// Loading the shader and building the compute pipeline
+    pub pipeline: Duration,
+    /// Recording, submitting and waiting for the dispatches
+    pub dispatch: Duration,
+    /// GPU time of the dispatches alone, `None` when the queue doesn't support timestamps
+    pub gpu_dispatch: Option<Duration>,
+    /// Reading the buffer back and assembling the image
+    pub readback: Duration,
+    /// Encoding and writing the output file, filled in by the caller
+    pub save: Duration,
+}
+
+impl Timings {
+    /// Logs the stages as a table at `info!` level.
+    pub fn log(&self)
+    {
+        let stages = [
+            ("instance", self.instance),
+            ("device", self.device),
+            ("pipeline", self.pipeline),
+            ("dispatch", self.dispatch),
+            ("readback", self.readback),
+            ("save", self.save),
+        ];
+        let total: Duration = stages.iter().map(|(_, duration)| *duration).sum();
+
+        info!("Timing breakdown:");
+        for (stage, duration) in stages {
+            info!(
+                "  {stage:<10} {:>10.3} ms {:>5.1}%",
+                duration.as_secs_f64() * 1000.0,
+                duration.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON) * 100.0,
+            );
+        }
+        info!("  {:<10} {:>10.3} ms", "total", total.as_secs_f64() * 1000.0);
+        if let Some(gpu_dispatch) = self.gpu_dispatch {
+            info!("  of which the GPU spent {:.3} ms in the dispatch", gpu_dispatch.as_secs_f64() * 1000.0);
+        }
+    }
+}
+
+impl AddAssign for Timings {
+    /// Adds every stage of `other`, so the timings of several frames can be summed up.
+    fn add_assign(&mut self, other: Self)
+    {
+        self.instance += other.instance;
+        self.device += other.device;
+        self.pipeline += other.pipeline;
+        self.dispatch += other.dispatch;
+        self.gpu_dispatch = match (self.gpu_dispatch, other.gpu_dispatch) {
+            (Some(gpu_dispatch), Some(other_gpu_dispatch)) => Some(gpu_dispatch + other_gpu_dispatch),
+            (gpu_dispatch, other_gpu_dispatch) => gpu_dispatch.or(other_gpu_dispatch),
+        };
+        self.readback += other.readback;
+        self.save += other.save;
+    }
+}
@@ -0,0 +1,39 @@
+use vulkan_tutorial::chain::parse_chain;
+use vulkan_tutorial::ShaderKind;
+
+#[test]
+fn passes_keep_their_order_and_overrides()
+{
+    let passes = parse_chain(r#"{ "passes": [{ "shader": "noise", "seed": 7 }, { "shader": "blur", "radius": 3 }] }"#)
+        .expect("the chain didn't parse");
+
+    assert_eq!(passes.len(), 2);
+    assert_eq!(passes[0].shader, ShaderKind::Noise);
+    assert_eq!(passes[0].seed, Some(7));
+    assert_eq!(passes[0].radius, None);
+    assert_eq!(passes[1].shader, ShaderKind::Blur);
+    assert_eq!(passes[1].radius, Some(3));
+}
+
+#[test]
+fn unknown_shaders_are_rejected()
+{
+    let error = parse_chain(r#"{ "passes": [{ "shader": "sparkles" }] }"#).expect_err("an unknown shader was accepted");
+    let message = format!("{error:#}");
+    assert!(message.contains("sparkles") && message.contains("gradient"), "unhelpful error: {message}");
+}
+
+#[test]
+fn invalid_chains_are_rejected()
+{
+    for text in [
+        r#"{ "passes": [] }"#,
+        // A typo in a push constant would otherwise be silently ignored
+        r#"{ "passes": [{ "shader": "blur", "raduis": 3 }] }"#,
+        // The passes all write images of the same size
+        r#"{ "passes": [{ "shader": "resize" }] }"#,
+        r#"{ "passes": [{ "shader": "volume" }] }"#,
+    ] {
+        assert!(parse_chain(text).is_err(), "{text} was accepted");
+    }
+}
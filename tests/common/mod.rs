@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use vulkan_tutorial::{DownloadMode, ImageMemoryType, PixelFormat, RenderConfig, ShaderKind, Swizzle};
+use vulkano::instance::{Instance, InstanceCreateFlags, InstanceCreateInfo};
+use vulkano::VulkanLibrary;
+
+/// Width and height of the images the tests render.
+pub const SIZE: u32 = 64;
+
+/// Returns why the test can't run when there is no Vulkan device to render on.
+pub fn vulkan_unavailable() -> Option<String>
+{
+    let library = match VulkanLibrary::new() {
+        Ok(library) => library,
+        Err(error) => return Some(format!("no Vulkan library: {error}")),
+    };
+
+    let instance = match Instance::new(
+        library,
+        InstanceCreateInfo {
+            flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
+            ..Default::default()
+        },
+    ) {
+        Ok(instance) => instance,
+        Err(error) => return Some(format!("failed to create an instance: {error}")),
+    };
+
+    match instance.enumerate_physical_devices() {
+        Ok(mut physical_devices) => physical_devices.next().is_none().then(|| "no Vulkan devices".to_owned()),
+        Err(error) => Some(format!("failed to enumerate devices: {error}")),
+    }
+}
+
+/// A small gradient render on any device, software ones included.
+pub fn gradient_config() -> RenderConfig
+{
+    RenderConfig {
+        width: SIZE,
+        height: SIZE,
+        validation: false,
+        debug_printf: false,
+        time: 0.0,
+        scale: 1.0,
+        workgroup_size: [8, 8],
+        download_mode: DownloadMode::Direct,
+        memory_type: ImageMemoryType::Device,
+        pipeline_cache: None,
+        pixel_format: PixelFormat::Rgba8,
+        swizzle: Swizzle::Rgba,
+        device_index: None,
+        device_uuid: None,
+        allow_software: true,
+        clear_buffer: false,
+        input: None,
+        resize: None,
+        shader: ShaderKind::Gradient,
+        radius: 0,
+        strength: 1.0,
+        seed: 0,
+        center: [0.0, 0.0],
+        zoom: 1.0,
+        iterations: 1,
+        timeout: Duration::from_secs(10),
+        queue_priority: 1.0,
+        multi_gpu: false,
+        clear_color: None,
+        mipmaps: false,
+        depth: 1,
+        secondary_command_buffers: false,
+        srgb: false,
+        explicit_barriers: false,
+        require_subgroup_ops: false,
+        tile_size: None,
+        threads: 1,
+        histogram: None,
+        verify: false,
+        palette: None,
+        dump_iterations: None,
+        premultiply_alpha: false,
+        debug_resources: false,
+        passes: 1,
+        iterations_per_submit: None,
+        spirv: None,
+        glsl: None,
+        entry_point: "main".to_owned(),
+    }
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "image-output")]
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use vulkan_tutorial::compare::compare_images;
+
+fn solid(width: u32, height: u32, pixel: [u8; 4]) -> DynamicImage
+{
+    DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba(pixel)))
+}
+
+#[test]
+fn identical_images_have_no_error()
+{
+    let image = solid(4, 4, [10, 20, 30, 255]);
+    let diff = compare_images(&image, &image).expect("compare failed");
+
+    assert_eq!(diff.mean_error, [0.0; 4]);
+    assert_eq!(diff.max(), 0.0);
+}
+
+#[test]
+fn errors_are_per_channel()
+{
+    let mut image = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 255]));
+    image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+    let reference = solid(2, 1, [0, 0, 0, 255]);
+
+    let diff = compare_images(&DynamicImage::ImageRgba8(image), &reference).expect("compare failed");
+    assert_eq!(diff.max_error, [1.0, 0.0, 0.0, 0.0]);
+    assert_eq!(diff.mean_error, [0.5, 0.0, 0.0, 0.0]);
+}
+
+#[test]
+fn mismatched_dimensions_are_an_error()
+{
+    let error = compare_images(&solid(4, 4, [0; 4]), &solid(4, 2, [0; 4])).expect_err("sizes differ");
+
+    assert!(error.to_string().contains("4x4"), "unexpected error: {error}");
+}
@@ -0,0 +1,46 @@
+use vulkan_tutorial::device::{format_uuid, parse_uuid, select_compute_queue_family, HeapBudget};
+use vulkano::device::QueueFlags;
+
+#[test]
+fn uuids_round_trip_through_their_printed_form()
+{
+    let uuid: [u8; 16] = std::array::from_fn(|index| (index * 17) as u8);
+    let printed = format_uuid(uuid);
+
+    assert_eq!(printed, "00112233-4455-6677-8899-aabbccddeeff");
+    assert_eq!(parse_uuid(&printed).expect("the printed UUID didn't parse"), uuid);
+    assert_eq!(parse_uuid("00112233445566778899AABBCCDDEEFF").expect("the bare UUID didn't parse"), uuid);
+}
+
+#[test]
+fn malformed_uuids_are_rejected()
+{
+    for text in [
+        "",
+        "00112233-4455-6677-8899",
+        "00112233-4455-6677-8899-aabbccddeeffaa",
+        "g0112233445566778899aabbccddeeff",
+    ] {
+        assert!(parse_uuid(text).is_err(), "{text:?} was accepted as a UUID");
+    }
+}
+
+#[test]
+fn compute_work_never_lands_on_a_graphics_only_family()
+{
+    let graphics = QueueFlags::GRAPHICS | QueueFlags::TRANSFER;
+    let combined = QueueFlags::GRAPHICS | QueueFlags::COMPUTE | QueueFlags::TRANSFER;
+    let dedicated = QueueFlags::COMPUTE | QueueFlags::TRANSFER;
+
+    assert_eq!(select_compute_queue_family(&[graphics, combined, dedicated]), Some((2, true)));
+    assert_eq!(select_compute_queue_family(&[graphics, combined]), Some((1, false)));
+    assert_eq!(select_compute_queue_family(&[graphics, QueueFlags::TRANSFER]), None);
+    assert_eq!(select_compute_queue_family(&[]), None);
+}
+
+#[test]
+fn an_overdrawn_budget_leaves_nothing_available()
+{
+    assert_eq!(HeapBudget { usage: 256, budget: 1024 }.available(), 768);
+    assert_eq!(HeapBudget { usage: 2048, budget: 1024 }.available(), 0);
+}
@@ -0,0 +1,113 @@
+use vulkan_tutorial::format::{convert_to_rgba, strip_row_padding};
+use vulkan_tutorial::{PixelFormat, Swizzle};
+use vulkano::format::Format;
+
+#[test]
+fn buffer_size_exceeds_u32_for_large_float_images()
+{
+    assert_eq!(PixelFormat::Rgba32f.buffer_size([8192, 8192, 1], 1, 1), Some(1 << 30));
+    assert_eq!(PixelFormat::Rgba32f.buffer_size([16384, 16384, 1], 1, 1), Some(1 << 32));
+    assert_eq!(PixelFormat::Rgba32f.buffer_size([16384, 16384, 2], 1, 1), Some(1 << 33));
+}
+
+#[test]
+fn buffer_size_sums_mip_levels()
+{
+    // 4x4, 2x2 and 1x1 pixels
+    assert_eq!(PixelFormat::Rgba8.buffer_size([4, 4, 1], 3, 1), Some((16 + 4 + 1) * 4));
+    assert_eq!(PixelFormat::Rgba16.buffer_size([1, 1, 1], 1, 1), Some(8));
+}
+
+#[test]
+fn buffer_size_reports_overflow()
+{
+    assert_eq!(PixelFormat::Rgba8.buffer_size([u32::MAX, 1 << 30, 1], 1, 1), Some(u32::MAX as u64 * (1 << 32)));
+    assert_eq!(PixelFormat::Rgba8.buffer_size([u32::MAX, u32::MAX, 1], 1, 1), None);
+    assert_eq!(PixelFormat::Rgba32f.buffer_size([u32::MAX, u32::MAX, u32::MAX], 1, 1), None);
+}
+
+#[test]
+fn pixels_of_the_wrong_length_are_rejected()
+{
+    let error = PixelFormat::Rgba16.to_output(4, 4, vec![0; 127]).expect_err("a short buffer was accepted");
+    let message = error.to_string();
+    assert!(message.contains("127") && message.contains("128"), "the error doesn't state both lengths: {message}");
+
+    assert!(PixelFormat::Rgba16.to_output(4, 4, vec![0; 128]).is_ok(), "a buffer of the right length was rejected");
+}
+
+#[test]
+fn fallback_formats_convert_to_rgba()
+{
+    let mut bgra = [30, 20, 10, 255];
+    convert_to_rgba(Format::B8G8R8A8_UNORM, &mut bgra);
+    assert_eq!(bgra, [10, 20, 30, 255]);
+
+    let mut snorm = [0, 127, 0x81, 64];
+    convert_to_rgba(Format::R8G8B8A8_SNORM, &mut snorm);
+    assert_eq!(snorm, [0, 255, 0, 129]);
+
+    let mut rgba = [1, 2, 3, 4];
+    convert_to_rgba(Format::R8G8B8A8_UNORM, &mut rgba);
+    assert_eq!(rgba, [1, 2, 3, 4]);
+}
+
+#[test]
+fn alpha_is_premultiplied_in_each_format()
+{
+    let mut rgba8 = [255, 128, 0, 128];
+    PixelFormat::Rgba8.premultiply_alpha(&mut rgba8);
+    assert_eq!(rgba8, [128, 64, 0, 128]);
+
+    let mut rgba16: Vec<u8> = [65535u16, 1000, 0, 32768].iter().flat_map(|channel| channel.to_ne_bytes()).collect();
+    PixelFormat::Rgba16.premultiply_alpha(&mut rgba16);
+    let channels: Vec<u16> = rgba16.chunks_exact(2).map(|channel| u16::from_ne_bytes([channel[0], channel[1]])).collect();
+    assert_eq!(channels, [32768, 500, 0, 32768]);
+
+    let mut rgba32f: Vec<u8> = [1.0f32, 0.5, 2.0, 0.25].iter().flat_map(|channel| channel.to_ne_bytes()).collect();
+    PixelFormat::Rgba32f.premultiply_alpha(&mut rgba32f);
+    let channels: Vec<f32> = rgba32f
+        .chunks_exact(4)
+        .map(|channel| f32::from_ne_bytes([channel[0], channel[1], channel[2], channel[3]]))
+        .collect();
+    assert_eq!(channels, [0.25, 0.125, 0.5, 0.25]);
+}
+
+#[test]
+fn rows_are_padded_to_the_alignment()
+{
+    assert_eq!(PixelFormat::Rgba8.row_pitch(13, 1), 52);
+    assert_eq!(PixelFormat::Rgba8.row_pitch(13, 64), 64);
+    assert_eq!(PixelFormat::Rgba8.row_pitch(16, 64), 64);
+    // Rows always start on a whole pixel, even when the alignment is smaller than one
+    assert_eq!(PixelFormat::Rgba32f.row_pitch(3, 4), 48);
+
+    assert_eq!(PixelFormat::Rgba8.buffer_size([13, 2, 1], 1, 64), Some(128));
+    assert_eq!(PixelFormat::Rgba8.buffer_size([13, 2, 1], 2, 64), Some(128 + 64));
+}
+
+#[test]
+fn padding_is_stripped_from_rows()
+{
+    let padded = [1, 2, 3, 0, 4, 5, 6, 0];
+
+    assert_eq!(strip_row_padding(&padded, 3, 4, 2), [1, 2, 3, 4, 5, 6]);
+    assert_eq!(strip_row_padding(&padded, 4, 4, 1), [1, 2, 3, 0]);
+}
+
+#[test]
+fn swizzle_reorders_channels()
+{
+    let mut bgra = [1, 2, 3, 4, 5, 6, 7, 8];
+    Swizzle::Bgra.apply(PixelFormat::Rgba8, &mut bgra);
+    assert_eq!(bgra, [3, 2, 1, 4, 7, 6, 5, 8]);
+
+    // Channels of wider formats move as a whole
+    let mut argb: Vec<u8> = (1..=8).collect();
+    Swizzle::Argb.apply(PixelFormat::Rgba16, &mut argb);
+    assert_eq!(argb, [7, 8, 1, 2, 3, 4, 5, 6]);
+
+    let mut rgba = [1, 2, 3, 4];
+    Swizzle::Rgba.apply(PixelFormat::Rgba8, &mut rgba);
+    assert_eq!(rgba, [1, 2, 3, 4]);
+}
@@ -0,0 +1,27 @@
+use vulkan_tutorial::palette::{parse_palette_csv, LUT_ENTRIES};
+
+#[test]
+fn csv_palettes_fill_every_entry()
+{
+    let ramp: String = (0..LUT_ENTRIES).map(|level| format!("{level}, {level}, {level}\n")).collect();
+    let text = format!("# a grey ramp\n\n{ramp}");
+    let lut = parse_palette_csv(&text).expect("the palette didn't parse");
+
+    assert_eq!(lut[0], [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(lut[LUT_ENTRIES - 1], [1.0, 1.0, 1.0, 1.0]);
+}
+
+#[test]
+fn invalid_palettes_are_rejected()
+{
+    let ramp = |lines: usize, line: &str| line.repeat(lines);
+    for text in [
+        ramp(255, "0,0,0\n"),
+        ramp(257, "0,0,0\n"),
+        ramp(256, "0,0\n"),
+        ramp(256, "0,0,256\n"),
+        ramp(256, "red,0,0\n"),
+    ] {
+        assert!(parse_palette_csv(&text).is_err(), "a palette of {:?} was accepted", text.lines().next());
+    }
+}
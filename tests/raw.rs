@@ -0,0 +1,52 @@
+mod common;
+
+use common::{gradient_config, vulkan_unavailable, SIZE};
+use vulkan_tutorial::{render_into, self_test};
+
+#[test]
+fn render_into_writes_the_gradient()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping render_into_writes_the_gradient: {reason}");
+        return;
+    }
+
+    let mut pixels = vec![0u8; (SIZE * SIZE * 4) as usize];
+    render_into(&gradient_config(), &mut pixels).expect("render failed");
+
+    // Red grows to the right and green grows downwards, blue is constant at a time of 0
+    let pixel = |x: u32, y: u32| {
+        let offset = ((y * SIZE + x) * 4) as usize;
+        &pixels[offset..offset + 4]
+    };
+    assert!(pixel(SIZE - 1, 0)[0] > pixel(0, 0)[0] + 200, "red doesn't grow to the right");
+    assert!(pixel(0, SIZE - 1)[1] > pixel(0, 0)[1] + 200, "green doesn't grow downwards");
+    assert!(pixel(SIZE / 2, SIZE / 2)[2].abs_diff(128) <= 2, "blue isn't half intensity");
+    assert!(pixels.chunks_exact(4).all(|pixel| pixel[3] == 255), "alpha isn't opaque");
+}
+
+#[test]
+fn self_test_passes()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping self_test_passes: {reason}");
+        return;
+    }
+
+    self_test(&gradient_config()).expect("self-test failed");
+}
+
+#[cfg(not(feature = "image-output"))]
+#[test]
+fn raw_renders_match_render_into()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping raw_renders_match_render_into: {reason}");
+        return;
+    }
+
+    let mut pixels = vec![0u8; (SIZE * SIZE * 4) as usize];
+    render_into(&gradient_config(), &mut pixels).expect("render failed");
+    let rendered = vulkan_tutorial::render_compute(&gradient_config()).expect("render failed");
+    assert!(rendered == pixels, "render_compute returned different bytes than render_into");
+}
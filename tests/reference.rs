@@ -0,0 +1,291 @@
+#![cfg(feature = "image-output")]
+
+mod common;
+
+use std::{env, fs, process};
+
+use common::{gradient_config, vulkan_unavailable, SIZE};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use vulkan_tutorial::{render_compute, PixelFormat, PushConstants, RenderConfig, Renderer, ShaderKind};
+
+/// Largest difference in 8-bit channel values allowed between the GPU and the CPU gradient.
+const TOLERANCE: u8 = 2;
+
+/// Highest iteration count the Mandelbrot escape counts are compared up to.
+const MAX_ITERATIONS: u32 = 48;
+
+/// Escape counts of pixels close to the boundary of the set are chaotic and may differ between
+/// the GPU's and the CPU's float rounding, this fraction of them may be off by more than one.
+const MAX_MISMATCHED_FRACTION: f32 = 0.01;
+
+/// Mirrors `gradient.comp` for the pixel at `x`, `y` of a `width`x`height` image.
+fn reference_gradient(x: u32, y: u32, width: u32, height: u32, scale: f32, time: f32) -> [u8; 4]
+{
+    // GLSL's fract is x - floor(x), which `f32::fract` only matches for positive values
+    let fract = |value: f32| value - value.floor();
+    let channel = |value: f32| (value * 255.0).round() as u8;
+    let uv = |coordinate: u32, size: u32| fract((coordinate as f32 + 0.5) / size as f32 * scale);
+
+    [channel(uv(x, width)), channel(uv(y, height)), channel(0.5 + 0.5 * time.sin()), 255]
+}
+
+/// Mirrors `normalmap.comp` for the pixel at `x`, `y` of `heights`.
+fn reference_normal(heights: &GrayImage, x: u32, y: u32, strength: f32) -> [u8; 4]
+{
+    let (width, height) = heights.dimensions();
+    let sample = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        heights.get_pixel(x, y).0[0] as f32 / 255.0
+    };
+
+    let (x, y) = (x as i64, y as i64);
+    let dx = (sample(x + 1, y) - sample(x - 1, y)) * 0.5;
+    let dy = (sample(x, y + 1) - sample(x, y - 1)) * 0.5;
+    let normal = [-dx * strength, dy * strength, 1.0];
+    let length = normal.iter().map(|component| component * component).sum::<f32>().sqrt();
+    let channel = |component: f32| ((component / length * 0.5 + 0.5) * 255.0).round() as u8;
+
+    [channel(normal[0]), channel(normal[1]), channel(normal[2]), 255]
+}
+
+/// Mirrors `resize.comp` for the pixel at `x`, `y` of `source` scaled to `width`x`height`.
+fn reference_resize(source: &RgbaImage, x: u32, y: u32, width: u32, height: u32) -> [u8; 4]
+{
+    let (source_width, source_height) = source.dimensions();
+    let position = |coordinate: u32, size: u32, source_size: u32| {
+        ((coordinate as f32 + 0.5) * source_size as f32 / size as f32 - 0.5).clamp(0.0, source_size as f32 - 1.0)
+    };
+    let (position_x, position_y) = (position(x, width, source_width), position(y, height, source_height));
+    let (base_x, base_y) = (position_x.floor() as u32, position_y.floor() as u32);
+    let (weight_x, weight_y) = (position_x - base_x as f32, position_y - base_y as f32);
+
+    let texel = |x: u32, y: u32, channel: usize| {
+        source.get_pixel(x.min(source_width - 1), y.min(source_height - 1)).0[channel] as f32 / 255.0
+    };
+    let mix = |a: f32, b: f32, weight: f32| a + (b - a) * weight;
+
+    std::array::from_fn(|channel| {
+        let top = mix(texel(base_x, base_y, channel), texel(base_x + 1, base_y, channel), weight_x);
+        let bottom = mix(texel(base_x, base_y + 1, channel), texel(base_x + 1, base_y + 1, channel), weight_x);
+        (mix(top, bottom, weight_y) * 255.0).round() as u8
+    })
+}
+
+/// Mirrors the iteration loop of `mandelbrot.comp` and returns the iteration the pixel at `x`, `y`
+/// escaped in, or `None` if it didn't within `iterations`.
+fn reference_escape(x: u32, y: u32, push_constants: &PushConstants, iterations: u32) -> Option<u32>
+{
+    let [width, height] = push_constants.size;
+    let norm = [(x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32];
+    let view_size = [3.5 / push_constants.zoom, 3.0 / push_constants.zoom];
+    let c = [
+        push_constants.center[0] + (norm[0] - 0.5) * view_size[0],
+        push_constants.center[1] + (norm[1] - 0.5) * view_size[1],
+    ];
+
+    let mut z = [0.0f32, 0.0];
+    for i in 0..iterations {
+        if z[0] * z[0] + z[1] * z[1] > 2000.0 * 2000.0 {
+            return Some(i);
+        }
+
+        z = [z[0] * z[0] - z[1] * z[1] + c[0], 2.0 * z[0] * z[1] + c[1]];
+    }
+
+    None
+}
+
+#[test]
+fn gradient_matches_the_cpu_reference()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping gradient_matches_the_cpu_reference: {reason}");
+        return;
+    }
+
+    // A scale of 2 wraps the gradient once, and with an even size no pixel centre lands exactly on
+    // the wrap where the GPU and CPU could round to opposite ends
+    let config = RenderConfig {
+        scale: 2.0,
+        time: 1.0,
+        ..gradient_config()
+    };
+    let image = render_compute(&config).expect("render failed").to_rgba8();
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let expected = reference_gradient(x, y, SIZE, SIZE, config.scale, config.time);
+        let matches = pixel.0.iter().zip(expected).all(|(&actual, expected)| actual.abs_diff(expected) <= TOLERANCE);
+        assert!(matches, "pixel ({x}, {y}) is {:?}, expected {expected:?}", pixel.0);
+    }
+}
+
+#[test]
+fn normal_map_matches_the_cpu_reference()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping normal_map_matches_the_cpu_reference: {reason}");
+        return;
+    }
+
+    // Slopes that change from pixel to pixel and a steep cliff where the heights wrap around
+    let heights = GrayImage::from_fn(SIZE, SIZE, |x, y| Luma([(x * x / 4 + y * 3) as u8]));
+    let path = env::temp_dir().join(format!("vulkan_tutorial_heightfield_{}.png", process::id()));
+    heights.save(&path).expect("failed to save the heightfield");
+
+    let config = RenderConfig {
+        shader: ShaderKind::Normalmap,
+        input: Some(path.clone()),
+        strength: 4.0,
+        ..gradient_config()
+    };
+    let result = render_compute(&config);
+    fs::remove_file(&path).expect("failed to remove the heightfield");
+    let image = result.expect("render failed").to_rgba8();
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let expected = reference_normal(&heights, x, y, config.strength);
+        let matches = pixel.0.iter().zip(expected).all(|(&actual, expected)| actual.abs_diff(expected) <= TOLERANCE);
+        assert!(matches, "pixel ({x}, {y}) is {:?}, expected {expected:?}", pixel.0);
+    }
+}
+
+#[test]
+fn resize_matches_the_cpu_reference()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping resize_matches_the_cpu_reference: {reason}");
+        return;
+    }
+
+    // Every channel changes at a different rate, so a sample taken from the wrong texel shows
+    let source = RgbaImage::from_fn(SIZE, SIZE, |x, y| Rgba([(x * 8) as u8, (y * 5) as u8, (x * y) as u8, 255 - x as u8]));
+    let path = env::temp_dir().join(format!("vulkan_tutorial_resize_{}.png", process::id()));
+    source.save(&path).expect("failed to save the source image");
+
+    // Shrinks the width and stretches the height in the same dispatch
+    let [width, height] = [SIZE / 3, SIZE * 2];
+    let config = RenderConfig {
+        shader: ShaderKind::Resize,
+        input: Some(path.clone()),
+        resize: Some([width, height]),
+        ..gradient_config()
+    };
+    let result = render_compute(&config);
+    fs::remove_file(&path).expect("failed to remove the source image");
+    let image = result.expect("render failed").to_rgba8();
+    assert_eq!(image.dimensions(), (width, height));
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let expected = reference_resize(&source, x, y, width, height);
+        let matches = pixel.0.iter().zip(expected).all(|(&actual, expected)| actual.abs_diff(expected) <= TOLERANCE);
+        assert!(matches, "pixel ({x}, {y}) is {:?}, expected {expected:?}", pixel.0);
+    }
+}
+
+#[test]
+fn mandelbrot_escape_counts_match_the_cpu_reference()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping mandelbrot_escape_counts_match_the_cpu_reference: {reason}");
+        return;
+    }
+
+    // Float output only writes exact black for pixels that didn't escape, 8-bit output would also
+    // round the darkest palette blends to black
+    let config = RenderConfig {
+        shader: ShaderKind::Mandelbrot,
+        pixel_format: PixelFormat::Rgba32f,
+        center: [-0.5, 0.0],
+        ..gradient_config()
+    };
+    let renderer = Renderer::new(&config).expect("failed to create a renderer");
+    let push_constants = PushConstants {
+        size: [SIZE, SIZE],
+        ..config.push_constants(config.time)
+    };
+
+    // The colours go through the palette, so recover the GPU's escape counts instead: a pixel is
+    // coloured with a limit of `iterations` exactly when it escaped in an earlier iteration
+    let mut gpu_escapes = vec![None; (SIZE * SIZE) as usize];
+    for iterations in 1..=MAX_ITERATIONS {
+        let image = renderer
+            .dispatch_frame(PushConstants { iterations, ..push_constants })
+            .expect("render failed")
+            .into_rgba32f();
+
+        for (escape, pixel) in gpu_escapes.iter_mut().zip(image.pixels()) {
+            if escape.is_none() && pixel.0[..3].iter().any(|&channel| channel > 0.0) {
+                *escape = Some(iterations - 1);
+            }
+        }
+    }
+
+    let mut mismatched = Vec::new();
+    for (index, &gpu_escape) in gpu_escapes.iter().enumerate() {
+        let (x, y) = (index as u32 % SIZE, index as u32 / SIZE);
+        let cpu_escape = reference_escape(x, y, &push_constants, MAX_ITERATIONS);
+
+        let matches = match (gpu_escape, cpu_escape) {
+            (Some(gpu), Some(cpu)) => gpu.abs_diff(cpu) <= 1,
+            (None, None) => true,
+            // Either one may just reach the limit
+            (Some(escape), None) | (None, Some(escape)) => escape + 1 >= MAX_ITERATIONS,
+        };
+        if !matches {
+            mismatched.push((x, y, gpu_escape, cpu_escape));
+        }
+    }
+
+    let mismatched_fraction = mismatched.len() as f32 / gpu_escapes.len() as f32;
+    assert!(
+        mismatched_fraction <= MAX_MISMATCHED_FRACTION,
+        "{} pixels escaped in a different iteration than on the CPU, e.g. (x, y, gpu, cpu) {:?}",
+        mismatched.len(),
+        &mismatched[..mismatched.len().min(8)],
+    );
+
+    // Guards against an image that is uniformly inside or outside the set matching trivially
+    assert!(gpu_escapes.iter().any(Option::is_none), "no pixel is inside the set");
+    assert!(gpu_escapes.iter().any(|escape| escape.is_some_and(|escape| escape > 2)), "every pixel escaped at once");
+}
+
+#[test]
+fn dumped_iteration_counts_match_the_cpu_reference()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping dumped_iteration_counts_match_the_cpu_reference: {reason}");
+        return;
+    }
+
+    let path = env::temp_dir().join(format!("vulkan_tutorial_iterations_{}.png", process::id()));
+    let config = RenderConfig {
+        shader: ShaderKind::Mandelbrot,
+        center: [-0.5, 0.0],
+        iterations: MAX_ITERATIONS,
+        dump_iterations: Some(path.clone()),
+        ..gradient_config()
+    };
+    let result = render_compute(&config);
+    let counts = image::open(&path).map(|counts| counts.to_luma16());
+    let _ = fs::remove_file(&path);
+    result.expect("render failed");
+    let counts = counts.expect("failed to open the iteration counts");
+    assert_eq!(counts.dimensions(), (SIZE, SIZE));
+
+    let push_constants = PushConstants { size: [SIZE, SIZE], ..config.push_constants(config.time) };
+    let mismatched = counts
+        .enumerate_pixels()
+        .filter(|&(x, y, count)| {
+            let expected = reference_escape(x, y, &push_constants, MAX_ITERATIONS).unwrap_or(MAX_ITERATIONS);
+            u32::from(count.0[0]).abs_diff(expected) > 1
+        })
+        .count();
+
+    let mismatched_fraction = mismatched as f32 / (SIZE * SIZE) as f32;
+    assert!(
+        mismatched_fraction <= MAX_MISMATCHED_FRACTION,
+        "{mismatched} pixels have a different iteration count than on the CPU"
+    );
+    assert!(counts.pixels().any(|count| u32::from(count.0[0]) == MAX_ITERATIONS), "no pixel is inside the set");
+}
@@ -0,0 +1,466 @@
+#![cfg(feature = "image-output")]
+
+mod common;
+
+use std::{env, fs, process, thread};
+use std::time::{Duration, Instant};
+
+use common::{gradient_config, vulkan_unavailable, SIZE};
+use vulkan_tutorial::chain::{parse_chain, render_chain};
+use vulkan_tutorial::{
+    render_compute, render_into, render_many, ImageMemoryType, PixelFormat, RenderConfig, Renderer, ShaderKind,
+};
+
+const TOLERANCE: u8 = 2;
+
+/// The colour `gradient.comp` writes to the pixel at `x`, `y` of a `width`x`height` image with a
+/// scale of 1 and a time of 0.
+fn expected_gradient(x: u32, y: u32, width: u32, height: u32) -> [u8; 4]
+{
+    let channel = |coordinate: u32, size: u32| ((coordinate as f32 + 0.5) / size as f32 * 255.0).round() as u8;
+
+    [channel(x, width), channel(y, height), 128, 255]
+}
+
+fn matches_expected(actual: [u8; 4], expected: [u8; 4]) -> bool
+{
+    actual.iter().zip(expected).all(|(&actual, expected)| actual.abs_diff(expected) <= TOLERANCE)
+}
+
+#[test]
+fn gradient_matches_expected_pixels()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping gradient_matches_expected_pixels: {reason}");
+        return;
+    }
+
+    let image = render_compute(&gradient_config()).expect("render failed").to_rgba8();
+    assert_eq!(image.dimensions(), (SIZE, SIZE));
+
+    for (x, y) in [(0, 0), (SIZE - 1, 0), (0, SIZE - 1), (SIZE / 2, SIZE / 4), (SIZE - 1, SIZE - 1)] {
+        let actual = image.get_pixel(x, y).0;
+        let expected = expected_gradient(x, y, SIZE, SIZE);
+        assert!(matches_expected(actual, expected), "pixel ({x}, {y}) is {actual:?}, expected {expected:?}");
+    }
+
+    // Red grows to the right and green grows downwards
+    let top_left = image.get_pixel(0, 0).0;
+    let bottom_right = image.get_pixel(SIZE - 1, SIZE - 1).0;
+    assert!(bottom_right[0] > top_left[0] + 200, "red doesn't grow to the right");
+    assert!(bottom_right[1] > top_left[1] + 200, "green doesn't grow downwards");
+}
+
+#[test]
+fn noise_is_reproducible_for_the_same_seed()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping noise_is_reproducible_for_the_same_seed: {reason}");
+        return;
+    }
+
+    let config = |seed| RenderConfig {
+        shader: ShaderKind::Noise,
+        seed,
+        ..gradient_config()
+    };
+
+    let first = render_compute(&config(42)).expect("render failed").into_bytes();
+    let second = render_compute(&config(42)).expect("render failed").into_bytes();
+    assert!(first == second, "the same seed rendered different images");
+
+    let other_seed = render_compute(&config(43)).expect("render failed").into_bytes();
+    assert!(first != other_seed, "different seeds rendered the same image");
+}
+
+#[test]
+fn secondary_command_buffers_render_the_same_image()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping secondary_command_buffers_render_the_same_image: {reason}");
+        return;
+    }
+
+    let primary = render_compute(&gradient_config()).expect("render failed").into_bytes();
+    let secondary = render_compute(&RenderConfig {
+        secondary_command_buffers: true,
+        ..gradient_config()
+    })
+    .expect("render failed")
+    .into_bytes();
+    assert!(primary == secondary, "the secondary command buffer rendered a different image");
+}
+
+#[test]
+fn explicit_barriers_render_the_same_image()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping explicit_barriers_render_the_same_image: {reason}");
+        return;
+    }
+
+    for shader in [ShaderKind::Gradient, ShaderKind::Noise] {
+        let config = RenderConfig { shader, seed: 3, ..gradient_config() };
+        let automatic = render_compute(&config).expect("render failed").into_bytes();
+        let explicit = render_compute(&RenderConfig {
+            explicit_barriers: true,
+            ..config
+        })
+        .expect("render failed")
+        .into_bytes();
+        assert!(automatic == explicit, "the explicit barriers rendered a different {shader:?} image");
+    }
+}
+
+#[test]
+fn threaded_tiles_render_the_same_image()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping threaded_tiles_render_the_same_image: {reason}");
+        return;
+    }
+
+    let whole = render_compute(&gradient_config()).expect("render failed").into_bytes();
+    // A tile size that doesn't divide the image leaves partial tiles at the right and bottom edges
+    for threads in [1, 4] {
+        let tiled = render_compute(&RenderConfig {
+            tile_size: Some(SIZE / 3),
+            threads,
+            ..gradient_config()
+        })
+        .expect("render failed")
+        .into_bytes();
+        assert!(whole == tiled, "the tiles rendered on {threads} threads form a different image");
+    }
+}
+
+#[test]
+fn histogram_counts_every_pixel_once()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping histogram_counts_every_pixel_once: {reason}");
+        return;
+    }
+
+    let path = env::temp_dir().join(format!("vulkan_tutorial_histogram_{}.csv", process::id()));
+    // Partial edge tiles on two threads, so the slots' histograms are summed and the pixels past
+    // the edge of the image are left out
+    render_compute(&RenderConfig {
+        histogram: Some(path.clone()),
+        tile_size: Some(SIZE / 3),
+        threads: 2,
+        ..gradient_config()
+    })
+    .expect("render failed");
+    let csv = fs::read_to_string(&path).expect("failed to read the histogram");
+    fs::remove_file(&path).expect("failed to remove the histogram");
+
+    let counts: Vec<u32> = csv
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let (_, count) = line.split_once(',').expect("a row without a comma");
+            count.parse().expect("a count that isn't a number")
+        })
+        .collect();
+    assert_eq!(counts.len(), 256);
+    assert_eq!(counts.iter().sum::<u32>(), SIZE * SIZE);
+    // The gradient's luminance spreads over many levels
+    assert!(counts.iter().filter(|&&count| count > 0).count() > 16, "the histogram is too narrow: {counts:?}");
+}
+
+#[test]
+fn verified_renders_match_their_checksum()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping verified_renders_match_their_checksum: {reason}");
+        return;
+    }
+
+    // Partial edge tiles on two threads, so only the tiles of the bottom row write the pattern
+    for pixel_format in [PixelFormat::Rgba8, PixelFormat::Rgba16, PixelFormat::Rgba32f] {
+        let verified = render_compute(&RenderConfig {
+            verify: true,
+            pixel_format,
+            tile_size: Some(SIZE / 3),
+            threads: 2,
+            ..gradient_config()
+        })
+        .expect("render failed")
+        .to_rgba8();
+
+        // Only the last row is overwritten
+        let plain = render_compute(&RenderConfig { pixel_format, ..gradient_config() }).expect("render failed").to_rgba8();
+        for (x, y, pixel) in verified.enumerate_pixels().filter(|&(_, y, _)| y < SIZE - 1) {
+            assert!(pixel == plain.get_pixel(x, y), "{pixel_format:?} pixel ({x}, {y}) changed");
+        }
+    }
+}
+
+#[test]
+fn chained_passes_read_the_pass_before_them()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping chained_passes_read_the_pass_before_them: {reason}");
+        return;
+    }
+
+    // A blur of radius 0 copies what it reads, so the chain ends with the gradient the first pass wrote
+    let passes = parse_chain(r#"{ "passes": [{ "shader": "gradient" }, { "shader": "blur", "radius": 0 }] }"#)
+        .expect("the chain didn't parse");
+    let chained = render_chain(&gradient_config(), &passes).expect("render failed").into_bytes();
+    let single = render_compute(&gradient_config()).expect("render failed").into_bytes();
+    assert!(chained == single, "the chain rendered a different image");
+}
+
+#[test]
+fn batched_passes_render_the_same_image()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping batched_passes_render_the_same_image: {reason}");
+        return;
+    }
+
+    let config = RenderConfig { shader: ShaderKind::Life, passes: 7, seed: 5, ..gradient_config() };
+    let single = render_compute(&config).expect("render failed").into_bytes();
+    // Batches that divide the passes, leave a shorter last batch, and hold one pass each
+    for iterations_per_submit in [7, 3, 1] {
+        let batched = render_compute(&RenderConfig {
+            iterations_per_submit: Some(iterations_per_submit),
+            ..config.clone()
+        })
+        .expect("render failed")
+        .into_bytes();
+        assert!(single == batched, "batches of {iterations_per_submit} passes rendered a different image");
+    }
+}
+
+#[test]
+fn palettes_recolour_the_luminance_of_the_render()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping palettes_recolour_the_luminance_of_the_render: {reason}");
+        return;
+    }
+
+    // Red rises and green falls with the luminance, so a colour from a neighbouring entry is
+    // within the tolerance and anything else isn't
+    let palette: String = (0..=255u8).map(|level| format!("{level},{},0\n", 255 - level)).collect();
+    let path = env::temp_dir().join(format!("vulkan_tutorial_palette_{}.csv", process::id()));
+    fs::write(&path, palette).expect("failed to write the palette");
+
+    // A single pass ends in the second image, and an odd number of Life passes starts in it
+    let configs = [
+        gradient_config(),
+        RenderConfig { shader: ShaderKind::Life, passes: 3, seed: 5, ..gradient_config() },
+    ];
+    let results: Vec<_> = configs
+        .iter()
+        .map(|config| {
+            let recoloured = render_compute(&RenderConfig { palette: Some(path.clone()), ..config.clone() });
+            (config.shader, render_compute(config), recoloured)
+        })
+        .collect();
+    fs::remove_file(&path).expect("failed to remove the palette");
+
+    for (shader, plain, recoloured) in results {
+        let plain = plain.expect("render failed").to_rgba8();
+        let recoloured = recoloured.expect("render with a palette failed").to_rgba8();
+        for (x, y, pixel) in plain.enumerate_pixels() {
+            let [red, green, blue, _] = pixel.0.map(|channel| channel as f32 / 255.0);
+            let level = ((0.2126 * red + 0.7152 * green + 0.0722 * blue) * 255.0).round() as u8;
+            let expected = [level, 255 - level, 0, 255];
+            let actual = recoloured.get_pixel(x, y).0;
+            let matches = actual.iter().zip(expected).all(|(&actual, expected)| actual.abs_diff(expected) <= TOLERANCE);
+            assert!(matches, "{shader:?} pixel ({x}, {y}) is {actual:?}, expected {expected:?}");
+        }
+    }
+}
+
+#[test]
+fn every_memory_type_renders_the_same_image()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping every_memory_type_renders_the_same_image: {reason}");
+        return;
+    }
+
+    let device = render_compute(&gradient_config()).expect("render failed").into_bytes();
+    for memory_type in [ImageMemoryType::Host, ImageMemoryType::Auto] {
+        let image = render_compute(&RenderConfig {
+            memory_type,
+            ..gradient_config()
+        })
+        .expect("render failed")
+        .into_bytes();
+        assert!(device == image, "{memory_type:?} memory rendered a different image");
+    }
+}
+
+#[test]
+fn polled_submissions_render_the_same_image()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping polled_submissions_render_the_same_image: {reason}");
+        return;
+    }
+
+    let config = gradient_config();
+    let renderer = Renderer::new(&config).expect("failed to create a renderer");
+    let fence = renderer.submit_and_get_fence(config.push_constants(config.time)).expect("submission failed");
+    while !fence.is_ready() {
+        thread::sleep(Duration::from_millis(1));
+    }
+    fence.wait(config.timeout).expect("waiting for the fence failed");
+
+    let polled = renderer.read_frame().expect("readback failed").into_bytes();
+    let blocking = render_compute(&config).expect("render failed").into_bytes();
+    assert!(polled == blocking, "the polled submission rendered a different image");
+}
+
+#[test]
+fn odd_widths_are_not_skewed()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping odd_widths_are_not_skewed: {reason}");
+        return;
+    }
+
+    // 13 RGBA8 pixels are 52 bytes, which isn't a multiple of the usual row alignments
+    const WIDTH: u32 = 13;
+    let image = render_compute(&RenderConfig {
+        width: WIDTH,
+        ..gradient_config()
+    })
+    .expect("render failed")
+    .to_rgba8();
+    assert_eq!(image.dimensions(), (WIDTH, SIZE));
+
+    // Skewed rows would shift the red channel further with every row
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let expected = expected_gradient(x, y, WIDTH, SIZE);
+        assert!(matches_expected(pixel.0, expected), "pixel ({x}, {y}) is {:?}, expected {expected:?}", pixel.0);
+    }
+}
+
+#[test]
+fn a_100x100_render_writes_every_pixel()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping a_100x100_render_writes_every_pixel: {reason}");
+        return;
+    }
+
+    // 100 isn't a multiple of the 8x8 workgroups, so the last workgroup of every row and column
+    // only partly covers the image
+    let image = render_compute(&RenderConfig {
+        width: 100,
+        height: 100,
+        ..gradient_config()
+    })
+    .expect("render failed")
+    .to_rgba8();
+    assert_eq!(image.dimensions(), (100, 100));
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        assert_eq!(pixel.0[3], 255, "pixel ({x}, {y}) was never written");
+    }
+}
+
+#[test]
+fn render_many_matches_single_renders()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping render_many_matches_single_renders: {reason}");
+        return;
+    }
+
+    // The first two share a renderer, the noise one needs its own
+    let configs = [
+        gradient_config(),
+        RenderConfig { time: 1.0, ..gradient_config() },
+        RenderConfig { shader: ShaderKind::Noise, seed: 7, ..gradient_config() },
+    ];
+
+    let images = render_many(&configs).expect("batch render failed");
+    assert_eq!(images.len(), configs.len());
+    for (config, image) in configs.iter().zip(images) {
+        let single = render_compute(config).expect("render failed").into_rgba8();
+        assert!(image == single, "the batch rendered a different image for {:?}", config.shader);
+    }
+}
+
+#[test]
+fn render_many_checks_the_push_constants_of_every_config()
+{
+    // Rejected before any device is created, so this runs without Vulkan
+    for invalid in [
+        RenderConfig { zoom: 0.0, ..gradient_config() },
+        RenderConfig { iterations: 0, ..gradient_config() },
+        RenderConfig { scale: -1.0, ..gradient_config() },
+    ] {
+        assert!(render_many(&[gradient_config(), invalid]).is_err(), "a batch sharing a renderer skipped the checks");
+    }
+}
+
+#[test]
+fn frames_in_flight_outnumbering_the_fences_come_back_in_order()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping frames_in_flight_outnumbering_the_fences_come_back_in_order: {reason}");
+        return;
+    }
+
+    // Five frames through two fences, so each fence is reset and reused for later frames
+    let config = RenderConfig { shader: ShaderKind::Noise, ..gradient_config() };
+    let push_constants: Vec<_> =
+        (0..5).map(|seed| RenderConfig { seed, ..config.clone() }.push_constants(config.time)).collect();
+
+    let serialized = Renderer::new(&config).expect("failed to create the renderer");
+    let start = Instant::now();
+    let expected: Vec<_> = push_constants
+        .iter()
+        .map(|&push_constants| serialized.dispatch_frame(push_constants).expect("render failed").into_rgba8())
+        .collect();
+    let serialized_time = start.elapsed();
+    drop(serialized);
+
+    let pipelined = Renderer::with_frames_in_flight(&config, 2).expect("failed to create the renderer");
+    let start = Instant::now();
+    let frames = pipelined.dispatch_frames(&push_constants).expect("render failed");
+    let pipelined_time = start.elapsed();
+    eprintln!(
+        "{} frames: {:.1} images per second one at a time, {:.1} with 2 in flight",
+        frames.len(),
+        frames.len() as f64 / serialized_time.as_secs_f64(),
+        frames.len() as f64 / pipelined_time.as_secs_f64(),
+    );
+
+    assert_eq!(frames.len(), expected.len());
+    for (seed, (frame, expected)) in frames.into_iter().zip(&expected).enumerate() {
+        assert!(frame.into_rgba8() == *expected, "frame {seed} came back different from its single render");
+    }
+
+    // The fences were all reset after the first batch
+    let frames = pipelined.dispatch_frames(&push_constants[..3]).expect("second batch failed");
+    for (seed, (frame, expected)) in frames.into_iter().zip(&expected).enumerate() {
+        assert!(frame.into_rgba8() == *expected, "frame {seed} of the second batch differs");
+    }
+}
+
+#[test]
+fn render_into_fills_the_callers_buffer()
+{
+    if let Some(reason) = vulkan_unavailable() {
+        eprintln!("skipping render_into_fills_the_callers_buffer: {reason}");
+        return;
+    }
+
+    let mut out = vec![0u8; (SIZE * SIZE * 4) as usize];
+    render_into(&gradient_config(), &mut out).expect("render failed");
+    let image = render_compute(&gradient_config()).expect("render failed").into_bytes();
+    assert!(out == image, "the buffer holds a different image");
+
+    let mut short = vec![0u8; out.len() - 1];
+    assert!(render_into(&gradient_config(), &mut short).is_err(), "a buffer of the wrong length was accepted");
+}